@@ -0,0 +1,174 @@
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+use crate::Trade;
+
+/// A statistic a client folds its trades into, one at a time, and reports as its
+/// average. `--stat` picks one of the implementations below; a new statistic only has to
+/// implement this to be usable in place of them.
+pub trait Averager: Send {
+    fn push(&mut self, trade: &Trade);
+    /// The statistic over every trade pushed so far, `None` while it is undefined.
+    fn finalize(&self) -> Option<f64>;
+}
+
+/// Plain mean of the trade prices.
+#[derive(Debug, Default)]
+pub struct Mean {
+    sum: f64,
+    count: usize,
+}
+
+impl Averager for Mean {
+    fn push(&mut self, trade: &Trade) {
+        self.sum += trade.price;
+        self.count += 1;
+    }
+
+    fn finalize(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+}
+
+/// Mean of the quoted prices with exact decimal arithmetic (`--decimal`). Undefined if
+/// the sum overflows; division rounds to the 28 significant digits a `Decimal` holds.
+#[derive(Debug, Default)]
+pub struct DecimalMean {
+    sum: Option<Decimal>,
+    count: usize,
+}
+
+impl Averager for DecimalMean {
+    fn push(&mut self, trade: &Trade) {
+        let sum = if self.count == 0 { Some(Decimal::ZERO) } else { self.sum };
+        self.sum = sum.and_then(|sum| sum.checked_add(trade.exact_price));
+        self.count += 1;
+    }
+
+    fn finalize(&self) -> Option<f64> {
+        self.sum?.checked_div(Decimal::from(self.count))?.to_f64()
+    }
+}
+
+/// Volume-weighted average price: each price weighted by its trade's quantity.
+/// Undefined if the total quantity is zero.
+#[derive(Debug, Default)]
+pub struct Vwap {
+    notional: f64,
+    volume: f64,
+}
+
+impl Averager for Vwap {
+    fn push(&mut self, trade: &Trade) {
+        self.notional += trade.price * trade.qty;
+        self.volume += trade.qty;
+    }
+
+    fn finalize(&self) -> Option<f64> {
+        (self.volume > 0.0).then(|| self.notional / self.volume)
+    }
+}
+
+/// Geometric mean of the trade prices, `exp(mean(ln(price)))`. Undefined if any price is
+/// zero or negative, since those have no real logarithm.
+#[derive(Debug, Default)]
+pub struct Geomean {
+    log_sum: f64,
+    count: usize,
+    non_positive: bool,
+}
+
+impl Averager for Geomean {
+    fn push(&mut self, trade: &Trade) {
+        self.non_positive |= trade.price <= 0.0;
+        self.log_sum += trade.price.ln();
+        self.count += 1;
+    }
+
+    fn finalize(&self) -> Option<f64> {
+        (self.count > 0 && !self.non_positive).then(|| (self.log_sum / self.count as f64).exp())
+    }
+}
+
+/// Time-weighted average price over a window ending at `end` (event time, ms): each price
+/// counts for the time until the next trade, and the last until `end`. Time inside any of
+/// the `excluded` spans, such as `--pausable` pauses, counts for no price. Trades that all
+/// fall at the same instant get a plain average. Undefined without a window end.
+#[derive(Debug)]
+pub struct Twap {
+    points: Vec<(i64, f64)>,
+    end: Option<i64>,
+    excluded: Vec<(i64, i64)>,
+}
+
+impl Twap {
+    pub fn new(end: Option<i64>, excluded: Vec<(i64, i64)>) -> Twap {
+        Twap { points: Vec::new(), end, excluded }
+    }
+
+    /// Milliseconds between `start` and `end` outside the excluded spans.
+    fn counted(&self, start: i64, end: i64) -> i64 {
+        let skipped: i64 = self.excluded.iter().map(|&(from, to)| (to.min(end) - from.max(start)).max(0)).sum();
+        (end - start - skipped).max(0)
+    }
+}
+
+impl Averager for Twap {
+    fn push(&mut self, trade: &Trade) {
+        self.points.push((trade.event_time, trade.price));
+    }
+
+    fn finalize(&self) -> Option<f64> {
+        let end = self.end?;
+        let (&(last_time, last_price), _) = self.points.split_last()?;
+        let mut weighted = 0.0;
+        let mut elapsed = 0;
+        for pair in self.points.windows(2) {
+            let ((time, price), (next_time, _)) = (pair[0], pair[1]);
+            let weight = self.counted(time, next_time);
+            weighted += price * weight as f64;
+            elapsed += weight;
+        }
+        let weight = self.counted(last_time, end);
+        weighted += last_price * weight as f64;
+        elapsed += weight;
+        if elapsed == 0 {
+            return Some(self.points.iter().map(|&(_, price)| price).sum::<f64>() / self.points.len() as f64);
+        }
+        Some(weighted / elapsed as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn geomean(prices: &[f64]) -> Option<f64> {
+        let mut geomean = Geomean::default();
+        for &price in prices {
+            geomean.push(&Trade { id: 1, price, exact_price: Decimal::ZERO, qty: 1.0, time: 0, event_time: 0 });
+        }
+        geomean.finalize()
+    }
+
+    #[test]
+    fn geomean_matches_known_values() {
+        for (prices, expected) in [
+            (&[2.0, 8.0][..], 4.0),
+            (&[4.0, 1.0, 1.0 / 32.0][..], 0.5),
+            (&[1.0, 3.0, 9.0, 27.0, 81.0][..], 9.0),
+            (&[60_000.0][..], 60_000.0),
+        ] {
+            let actual = geomean(prices).unwrap();
+            assert!((actual - expected).abs() <= expected * 1e-12, "{prices:?}: {actual}, expected {expected}");
+        }
+    }
+
+    #[test]
+    fn geomean_is_undefined_for_non_positive_prices() {
+        assert_eq!(geomean(&[]), None);
+        assert_eq!(geomean(&[100.0, 0.0, 100.0]), None);
+        assert_eq!(geomean(&[100.0, -5.0]), None);
+        // Even when the product of the prices is positive.
+        assert_eq!(geomean(&[-1.0, -1.0]), None);
+    }
+}
@@ -0,0 +1,232 @@
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use tokio::time::{Duration, Instant};
+
+use crate::Trade;
+
+pub type SinkResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// Wait after the first failed write before trying again; doubled after each further
+/// failure up to [`MAX_RETRY_DELAY`].
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// A destination that trades can be streamed to while a client's window is still open.
+pub trait PriceSink: Send {
+    /// Write `trades`, oldest first. On error the same trades are offered again with the
+    /// next batch.
+    fn write_batch<'a>(&'a mut self, trades: &'a [Trade]) -> BoxFuture<'a, SinkResult>;
+}
+
+/// Keeps every trade written to it in memory, so tests can check what a client streamed
+/// out without touching the disk. Clones share the same list.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub struct InMemorySink {
+    trades: Arc<Mutex<Vec<Trade>>>,
+}
+
+#[cfg(test)]
+impl InMemorySink {
+    /// A sink, and a handle to read the trades written to it.
+    pub fn new() -> (InMemorySink, Arc<Mutex<Vec<Trade>>>) {
+        let sink = InMemorySink::default();
+        let trades = sink.trades.clone();
+        (sink, trades)
+    }
+}
+
+#[cfg(test)]
+impl PriceSink for InMemorySink {
+    fn write_batch<'a>(&'a mut self, trades: &'a [Trade]) -> BoxFuture<'a, SinkResult> {
+        self.trades.lock().unwrap().extend_from_slice(trades);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Buffers trades and hands them to a [`PriceSink`] in batches of `batch_size`, or
+/// whenever [`Batcher::flush`] is called (on a timer, and once at the end of the window).
+///
+/// A failed write keeps its trades buffered and is retried with backoff, so a sink that
+/// is briefly unreachable loses nothing. Until the retry is due, trades are only buffered.
+/// At most `capacity` trades are held; past that the oldest are dropped and counted.
+pub struct Batcher {
+    sink: Box<dyn PriceSink>,
+    buffer: Vec<Trade>,
+    batch_size: usize,
+    capacity: usize,
+    /// After a failed write, when the next attempt is due and the wait after that one.
+    retry_at: Option<Instant>,
+    retry_delay: Duration,
+    dropped: u64,
+}
+
+impl Batcher {
+    pub fn new(sink: Box<dyn PriceSink>, batch_size: usize, capacity: usize) -> Batcher {
+        let batch_size = batch_size.max(1);
+        Batcher {
+            sink,
+            buffer: Vec::with_capacity(batch_size),
+            batch_size,
+            capacity: capacity.max(batch_size),
+            retry_at: None,
+            retry_delay: FIRST_RETRY_DELAY,
+            dropped: 0,
+        }
+    }
+
+    /// Buffer a trade, writing the batch out once it is full.
+    pub async fn push(&mut self, trade: Trade) -> SinkResult {
+        if self.buffer.len() >= self.capacity {
+            let excess = self.buffer.len() + 1 - self.capacity;
+            self.buffer.drain(..excess);
+            self.dropped += excess as u64;
+        }
+        self.buffer.push(trade);
+        if self.buffer.len() >= self.batch_size {
+            self.flush().await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Write out everything buffered, unless a retry after a failed write isn't due yet.
+    /// Trades stay buffered if the write fails.
+    pub async fn flush(&mut self) -> SinkResult {
+        if self.retry_at.is_some_and(|retry_at| Instant::now() < retry_at) {
+            return Ok(());
+        }
+        self.write().await
+    }
+
+    /// Write out everything buffered now, whatever the backoff, as the last attempt
+    /// before the batcher is dropped.
+    pub async fn finish(&mut self) -> SinkResult {
+        self.write().await
+    }
+
+    /// Trades dropped so far because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    async fn write(&mut self) -> SinkResult {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        if let Err(e) = self.sink.write_batch(&self.buffer).await {
+            self.retry_at = Some(Instant::now() + self.retry_delay);
+            self.retry_delay = (self.retry_delay * 2).min(MAX_RETRY_DELAY);
+            return Err(e);
+        }
+        self.buffer.clear();
+        self.retry_at = None;
+        self.retry_delay = FIRST_RETRY_DELAY;
+        Ok(())
+    }
+}
+
+impl Drop for Batcher {
+    // Writes are async, so they can't happen here; callers flush before dropping.
+    fn drop(&mut self) {
+        if !self.buffer.is_empty() {
+            eprintln!("{} batched trades were dropped without being written.", self.buffer.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::*;
+
+    fn trade(id: u64) -> Trade {
+        Trade { id, price: 100.0 + id as f64, exact_price: Decimal::ZERO, qty: 1.0, time: id as i64, event_time: id as i64 }
+    }
+
+    /// Records the size of every batch written through it, and fails the writes whose
+    /// numbers (counting from 0) are in `fail`.
+    struct FlakySink {
+        inner: InMemorySink,
+        batches: Shared<usize>,
+        writes: usize,
+        fail: Vec<usize>,
+    }
+
+    impl PriceSink for FlakySink {
+        fn write_batch<'a>(&'a mut self, trades: &'a [Trade]) -> BoxFuture<'a, SinkResult> {
+            let write = self.writes;
+            self.writes += 1;
+            if self.fail.contains(&write) {
+                return Box::pin(async { Err("sink unreachable".into()) });
+            }
+            self.batches.lock().unwrap().push(trades.len());
+            self.inner.write_batch(trades)
+        }
+    }
+
+    type Shared<T> = Arc<Mutex<Vec<T>>>;
+
+    /// A batcher over a [`FlakySink`], with handles to the trades and batch sizes written.
+    fn batcher(batch_size: usize, fail: Vec<usize>) -> (Batcher, Shared<Trade>, Shared<usize>) {
+        let (inner, written) = InMemorySink::new();
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sink = FlakySink { inner, batches: batches.clone(), writes: 0, fail };
+        (Batcher::new(Box::new(sink), batch_size, 1000), written, batches)
+    }
+
+    fn ids(written: &Mutex<Vec<Trade>>) -> Vec<u64> {
+        written.lock().unwrap().iter().map(|trade| trade.id).collect()
+    }
+
+    #[tokio::test]
+    async fn full_batches_are_written_and_the_rest_on_finish() {
+        let (mut batcher, written, batches) = batcher(3, Vec::new());
+        for id in 1..=10 {
+            batcher.push(trade(id)).await.unwrap();
+        }
+        assert_eq!(ids(&written), (1..=9).collect::<Vec<_>>());
+        batcher.finish().await.unwrap();
+        assert_eq!(ids(&written), (1..=10).collect::<Vec<_>>());
+        assert_eq!(*batches.lock().unwrap(), [3, 3, 3, 1]);
+        assert_eq!(batcher.dropped(), 0);
+    }
+
+    #[tokio::test]
+    async fn a_timed_flush_writes_a_partial_batch() {
+        let (mut batcher, written, _) = batcher(100, Vec::new());
+        for id in 1..=5 {
+            batcher.push(trade(id)).await.unwrap();
+        }
+        assert!(ids(&written).is_empty());
+        batcher.flush().await.unwrap();
+        batcher.push(trade(6)).await.unwrap();
+        batcher.finish().await.unwrap();
+        assert_eq!(ids(&written), (1..=6).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn a_failed_write_is_retried_without_losing_or_repeating_trades() {
+        // The second batch fails; its trades stay buffered behind the backoff, and the
+        // shutdown flush writes them together with everything that came after.
+        let (mut batcher, written, batches) = batcher(3, vec![1]);
+        for id in 1..=3 {
+            batcher.push(trade(id)).await.unwrap();
+        }
+        batcher.push(trade(4)).await.unwrap();
+        batcher.push(trade(5)).await.unwrap();
+        assert!(batcher.push(trade(6)).await.is_err());
+        for id in 7..=8 {
+            batcher.push(trade(id)).await.unwrap();
+        }
+        // The retry isn't due yet, so a flush leaves the trades buffered.
+        batcher.flush().await.unwrap();
+        assert_eq!(ids(&written), [1, 2, 3]);
+        batcher.finish().await.unwrap();
+        assert_eq!(ids(&written), (1..=8).collect::<Vec<_>>());
+        assert_eq!(*batches.lock().unwrap(), [3, 5]);
+    }
+}
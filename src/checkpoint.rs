@@ -0,0 +1,81 @@
+use futures::future;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{self, Duration, Instant};
+
+/// How long a checkpoint waits for clients to answer. Clients that have already finished
+/// their window never do.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A client's running average at a `--checkpoint-on-signal` checkpoint.
+#[derive(Debug)]
+pub struct ClientCheckpoint {
+    /// Which checkpoint this answers, so a late reply isn't counted towards the next one.
+    pub number: u64,
+    pub id: usize,
+    pub name: String,
+    pub symbol: String,
+    /// `None` before the client's first trade.
+    pub average: Option<f64>,
+}
+
+/// The clients' end: when a checkpoint is requested, save a snapshot and reply with the
+/// running average. Clones share the channels.
+#[derive(Debug, Clone)]
+pub struct Checkpoints {
+    requests: watch::Receiver<u64>,
+    replies: mpsc::UnboundedSender<ClientCheckpoint>,
+}
+
+impl Checkpoints {
+    /// A receiver that wakes on each new checkpoint, for the client's read loop.
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.requests.clone()
+    }
+
+    pub fn reply(&self, checkpoint: ClientCheckpoint) {
+        let _ = self.replies.send(checkpoint);
+    }
+}
+
+/// The signal handler's end, which requests checkpoints and gathers the replies.
+pub struct Trigger {
+    requests: watch::Sender<u64>,
+    replies: mpsc::UnboundedReceiver<ClientCheckpoint>,
+}
+
+impl Trigger {
+    /// Ask every client for a checkpoint and wait for up to `clients` replies, returned in
+    /// client id order.
+    pub async fn collect(&mut self, clients: usize) -> Vec<ClientCheckpoint> {
+        self.requests.send_modify(|number| *number += 1);
+        let number = *self.requests.borrow();
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        let mut replies = Vec::with_capacity(clients);
+        while replies.len() < clients {
+            match time::timeout_at(deadline, self.replies.recv()).await {
+                Ok(Some(reply)) if reply.number == number => replies.push(reply),
+                Ok(Some(_)) => {}
+                Ok(None) | Err(_) => break,
+            }
+        }
+        replies.sort_by_key(|reply| reply.id);
+        replies
+    }
+}
+
+/// The number of the next checkpoint requested, pending forever without checkpoints.
+pub async fn next_request(requests: &mut Option<watch::Receiver<u64>>) -> Option<u64> {
+    match requests {
+        Some(requests) => {
+            requests.changed().await.ok()?;
+            Some(*requests.borrow_and_update())
+        }
+        None => future::pending().await,
+    }
+}
+
+pub fn channel() -> (Trigger, Checkpoints) {
+    let (requests, subscribed) = watch::channel(0);
+    let (replied, replies) = mpsc::unbounded_channel();
+    (Trigger { requests, replies }, Checkpoints { requests: subscribed, replies: replied })
+}
@@ -0,0 +1,46 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+/// Result of checking a file against its `.sha256` sidecar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    Match,
+    Mismatch,
+    /// The file was saved without `--checksum`.
+    NoSidecar,
+}
+
+fn sidecar_path(path: &str) -> String {
+    format!("{path}.sha256")
+}
+
+/// SHA-256 of `bytes`, as lowercase hex.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// SHA-256 of the file's bytes, as lowercase hex.
+fn digest(path: &str) -> io::Result<String> {
+    Ok(sha256_hex(&fs::read(path)?))
+}
+
+/// Write `<path>.sha256` next to a finished file, in the `<hash>  <file name>` format
+/// `sha256sum -c` understands.
+pub fn write_sidecar(path: &str) -> io::Result<()> {
+    let name = Path::new(path).file_name().map_or_else(|| path.into(), |name| name.to_string_lossy());
+    fs::write(sidecar_path(path), format!("{}  {name}\n", digest(path)?))
+}
+
+/// Hash `path` again and compare it with the hash in its sidecar.
+pub fn verify(path: &str) -> io::Result<Verification> {
+    let sidecar = match fs::read_to_string(sidecar_path(path)) {
+        Ok(sidecar) => sidecar,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Verification::NoSidecar),
+        Err(e) => return Err(e),
+    };
+    let expected = sidecar.split_whitespace().next().unwrap_or_default();
+    Ok(if expected.eq_ignore_ascii_case(&digest(path)?) { Verification::Match } else { Verification::Mismatch })
+}
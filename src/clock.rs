@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Weak};
+
+use chrono::Utc;
+use serde::Deserialize;
+use tokio::time::{self, Duration, Instant};
+
+/// Binance REST endpoint returning the exchange's current time.
+pub const SERVER_TIME_URL: &str = "https://api.binance.com/api/v3/time";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerTime {
+    server_time: i64,
+}
+
+/// The local clock, corrected by the offset to Binance server time measured with
+/// `--correct-clock`. Without it the offset stays zero. Clones share the offset, so a
+/// re-measurement reaches every client.
+#[derive(Debug, Clone, Default)]
+pub struct ServerClock {
+    offset_ms: Arc<AtomicI64>,
+}
+
+impl ServerClock {
+    /// Current time on the server's clock, in milliseconds since the epoch.
+    pub fn now_millis(&self) -> i64 {
+        Utc::now().timestamp_millis() + self.offset_ms()
+    }
+
+    /// How far the server's clock is ahead of ours, in milliseconds.
+    pub fn offset_ms(&self) -> i64 {
+        self.offset_ms.load(Ordering::Relaxed)
+    }
+
+    /// Measure the offset and start using it. On failure the previous offset is kept.
+    pub async fn sync(&self) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        let offset = measure_offset().await?;
+        self.offset_ms.store(offset, Ordering::Relaxed);
+        Ok(offset)
+    }
+
+    /// Re-measure the offset every `interval`, since clocks drift on long runs. The task
+    /// stops once every handle has been dropped.
+    pub fn resync_periodically(&self, interval: Duration) {
+        tokio::spawn(resync(Arc::downgrade(&self.offset_ms), interval));
+    }
+}
+
+/// Describe an offset for the log, e.g. `12 ms behind`.
+pub fn describe(offset_ms: i64) -> String {
+    match offset_ms {
+        0 => "in sync with".to_string(),
+        offset if offset > 0 => format!("{offset} ms behind"),
+        offset => format!("{} ms ahead of", -offset),
+    }
+}
+
+/// Server time minus local time, taking the server's reading to be from halfway
+/// through the request.
+async fn measure_offset() -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+    let sent = Utc::now().timestamp_millis();
+    let body = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?
+        .get(SERVER_TIME_URL)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let received = Utc::now().timestamp_millis();
+    let time: ServerTime = serde_json::from_str(&body)?;
+    Ok(time.server_time - (sent + received) / 2)
+}
+
+async fn resync(offset_ms: Weak<AtomicI64>, interval: Duration) {
+    let mut tick = time::interval_at(Instant::now() + interval, interval);
+    loop {
+        tick.tick().await;
+        if offset_ms.strong_count() == 0 {
+            return;
+        }
+        match measure_offset().await {
+            Ok(offset) => {
+                let Some(shared) = offset_ms.upgrade() else {
+                    return;
+                };
+                let previous = shared.swap(offset, Ordering::Relaxed);
+                println!(
+                    "Clock: Local clock is {} Binance server time (drifted {:+} ms).",
+                    describe(offset),
+                    offset - previous
+                );
+            }
+            Err(e) => eprintln!("Clock: Failed to re-measure Binance server time: {e}; keeping the last offset."),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use chrono::DateTime;
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+
+use crate::{Frame, Trade};
+
+/// Coinbase Exchange public market-data feed.
+pub const URL: &str = "wss://ws-feed.exchange.coinbase.com";
+
+/// Map a Binance-style symbol such as `BTCUSDT` to a Coinbase product id (`BTC-USDT`).
+pub fn product_id(symbol: &str) -> String {
+    let symbol = symbol.to_uppercase();
+    for quote in ["USDT", "USDC", "USD", "EUR", "GBP", "BTC", "ETH"] {
+        if let Some(base) = symbol.strip_suffix(quote) {
+            if !base.is_empty() {
+                return format!("{base}-{quote}");
+            }
+        }
+    }
+    symbol
+}
+
+/// Subscribe message for the `matches` (trades) channel of a symbol.
+pub fn subscription(symbol: &str) -> String {
+    json!({
+        "type": "subscribe",
+        "product_ids": [product_id(symbol)],
+        "channels": ["matches"],
+    })
+    .to_string()
+}
+
+/// Process a Coinbase `match` message to extract the trade. Other messages, such as
+/// subscription acks and heartbeats, are [`Frame::Other`].
+pub fn process_message(text: &str) -> Result<Frame, Box<dyn std::error::Error>> {
+    let json: Value = serde_json::from_str(text)?;
+    match json.get("type").and_then(Value::as_str) {
+        Some("match") | Some("last_match") => {}
+        _ => return Ok(Frame::Other),
+    }
+    let price_text = json
+        .get("price")
+        .and_then(Value::as_str)
+        .ok_or("No price field found")?;
+    let price = price_text.parse::<f64>()?;
+    let exact_price = price_text.parse::<Decimal>()?;
+    let qty = json
+        .get("size")
+        .and_then(Value::as_str)
+        .ok_or("No size field found")?
+        .parse::<f64>()?;
+    let time = json
+        .get("time")
+        .and_then(Value::as_str)
+        .ok_or("No time field found")?;
+    let time = DateTime::parse_from_rfc3339(time)?.timestamp_millis();
+    let id = json
+        .get("trade_id")
+        .and_then(Value::as_u64)
+        .ok_or("No trade id field found")?;
+    // Coinbase only stamps the match itself, so it doubles as the event time.
+    Ok(Frame::Trade(Trade { id, price, exact_price, qty, time, event_time: time }))
+}
@@ -0,0 +1,140 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{JsonStyle, MiniTicker, Trade};
+
+/// Everything a run saved with `--merge-clients`, written as one `combined_data.json`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CombinedData {
+    /// One section per client, in client id order.
+    pub clients: Vec<ClientSection>,
+    /// One entry per symbol.
+    pub global: Vec<GlobalSection>,
+    /// With `--correlate`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation: Option<CorrelationSection>,
+}
+
+/// A client's window, as it would otherwise be saved to `client_{id}_data.txt`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClientSection {
+    pub id: usize,
+    /// The client's `--client-labels` label, if it was given one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub symbol: String,
+    pub source: String,
+    pub method: String,
+    pub average: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalized_open: Option<f64>,
+    /// p50, p90 and p99.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percentiles: Option<[f64; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ticker: Option<MiniTicker>,
+    /// Most consecutive trades at the same price.
+    pub longest_identical_run: u64,
+    /// Trades counted when `trades` was cut short by `--max-samples-per-client`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated_at: Option<u64>,
+    pub reconnects: u32,
+    pub downtime_secs: f64,
+    pub trades: Vec<Trade>,
+}
+
+/// The aggregator's result for one symbol.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct GlobalSection {
+    pub symbol: String,
+    pub method: String,
+    /// Client averages in client id order.
+    pub client_averages: Vec<f64>,
+    pub global_average: f64,
+    /// Sample variance of the client averages, with `--consensus`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_variance: Option<f64>,
+    /// Coefficient of variation of the client averages in percent, with `--consensus`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_cv_pct: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CorrelationSection {
+    pub symbols: (String, String),
+    /// `null` when undefined.
+    pub coefficient: Option<f64>,
+    pub aligned_seconds: usize,
+}
+
+/// Write `data` to `path` as JSON in the given style.
+pub fn write(path: &str, data: &CombinedData, style: JsonStyle) -> Result<(), Box<dyn std::error::Error>> {
+    style.write(BufWriter::new(File::create(path)?), data)?;
+    Ok(())
+}
+
+/// Read a `combined_data.json` back, in either style.
+pub fn read(path: &str) -> Result<CombinedData, Box<dyn std::error::Error>> {
+    Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn combined_data() -> CombinedData {
+        let client = test_support::client_data();
+        let global = test_support::global_data();
+        CombinedData {
+            clients: vec![ClientSection {
+                id: client.id,
+                label: client.label,
+                symbol: client.symbol,
+                source: client.source,
+                method: client.method,
+                average: client.average,
+                normalized_open: None,
+                percentiles: Some([test_support::AWKWARD_PRICES[0], test_support::AWKWARD_PRICES[1], test_support::AWKWARD_PRICES[4]]),
+                ticker: None,
+                longest_identical_run: client.longest_identical_run,
+                truncated_at: None,
+                reconnects: 2,
+                downtime_secs: 0.125,
+                trades: client.trades,
+            }],
+            global: vec![GlobalSection {
+                symbol: global.symbol,
+                method: global.method,
+                client_averages: global.client_averages,
+                global_average: global.global_average,
+                client_variance: global.client_variance,
+                client_cv_pct: None,
+            }],
+            correlation: Some(CorrelationSection {
+                symbols: ("BTCUSDT".to_string(), "ETHUSDT".to_string()),
+                coefficient: None,
+                aligned_seconds: 0,
+            }),
+        }
+    }
+
+    #[test]
+    fn both_styles_read_back_identically() {
+        let data = combined_data();
+        let pretty = test_support::temp_path("combined_pretty.json");
+        let compact = test_support::temp_path("combined_compact.json");
+        write(&pretty, &data, JsonStyle::Pretty).unwrap();
+        write(&compact, &data, JsonStyle::Compact).unwrap();
+        assert!(std::fs::read_to_string(&pretty).unwrap().lines().count() > 1);
+        assert_eq!(std::fs::read_to_string(&compact).unwrap().lines().count(), 1);
+
+        assert_eq!(read(&pretty).unwrap(), data);
+        assert_eq!(read(&compact).unwrap(), data);
+        // Read mode prints either file.
+        crate::print_combined(&pretty).unwrap();
+        crate::print_combined(&compact).unwrap();
+    }
+}
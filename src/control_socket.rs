@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixStream as StdUnixStream;
+use std::sync::{Arc, Mutex};
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::Precision;
+
+/// The most recent global average of each tracked symbol, `None` until one is known.
+/// Updated every second from the `--live-window` broadcast and by the aggregator at the
+/// end of each cycle; clones share the values.
+#[derive(Debug, Clone)]
+pub struct LatestAverages {
+    shared: Arc<Mutex<BTreeMap<String, Option<f64>>>>,
+}
+
+impl LatestAverages {
+    pub fn new(symbols: &[String]) -> LatestAverages {
+        let averages = symbols.iter().map(|symbol| (symbol.clone(), None)).collect();
+        LatestAverages { shared: Arc::new(Mutex::new(averages)) }
+    }
+
+    pub fn set(&self, symbol: &str, average: f64) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.insert(symbol.to_string(), Some(average));
+        }
+    }
+
+    /// One `SYMBOL AVERAGE` line per symbol, with `none` for a symbol without an average.
+    fn lines(&self, precision: &Precision) -> String {
+        let Ok(shared) = self.shared.lock() else {
+            return String::new();
+        };
+        shared
+            .iter()
+            .map(|(symbol, average)| match average {
+                Some(average) => format!("{symbol} {average:.prec$}\n", prec = precision.of(symbol)),
+                None => format!("{symbol} none\n"),
+            })
+            .collect()
+    }
+}
+
+/// The socket `--control-socket` listens on. Every connection is sent the current
+/// averages and closed, so `nc -U` or `socat` can poll a running instance. The socket
+/// file is removed when this is dropped.
+pub struct ControlSocket {
+    path: String,
+}
+
+impl ControlSocket {
+    /// Listen on `path` and serve `latest` from a background task. A socket file left
+    /// behind by a crashed run is replaced; one still in use, or any other file, is an
+    /// error.
+    pub fn bind(path: &str, latest: LatestAverages, precision: Precision) -> io::Result<ControlSocket> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, "file exists and is not a socket"));
+            }
+            if StdUnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, "another instance is listening on it"));
+            }
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        tokio::spawn(serve(listener, latest, precision));
+        Ok(ControlSocket { path: path.to_string() })
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+async fn serve(listener: UnixListener, latest: LatestAverages, precision: Precision) {
+    loop {
+        match listener.accept().await {
+            // Each reader gets its own task, so a slow one doesn't hold up the rest.
+            Ok((stream, _)) => {
+                tokio::spawn(reply(stream, latest.lines(&precision)));
+            }
+            Err(e) => eprintln!("Control socket: Failed to accept a connection: {e}"),
+        }
+    }
+}
+
+async fn reply(mut stream: UnixStream, lines: String) {
+    if stream.write_all(lines.as_bytes()).await.is_ok() {
+        let _ = stream.shutdown().await;
+    }
+}
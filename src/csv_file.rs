@@ -0,0 +1,45 @@
+use std::fs::File;
+
+use futures::stream::{self, StreamExt};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use crate::FrameStream;
+
+/// Read a `timestamp_ms,price[,qty]` CSV file and turn each row into a Binance-style trade
+/// frame for `symbol`, numbering trades by row. A header row is skipped; other rows that
+/// don't parse are skipped and counted. After the last frame the stream stays open
+/// without producing anything.
+pub fn frames(path: &str, symbol: &str) -> Result<(FrameStream, usize), Box<dyn std::error::Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(File::open(path)?);
+    let symbol = symbol.to_uppercase();
+    let mut frames = Vec::new();
+    let mut malformed = 0;
+    for (row, record) in reader.records().enumerate() {
+        let Some((time, price, qty)) = record.ok().and_then(|record| parse_row(&record)) else {
+            // Only a first row that doesn't parse is taken to be a header.
+            if row > 0 {
+                malformed += 1;
+            }
+            continue;
+        };
+        frames.push(Ok(Message::Text(format!(
+            r#"{{"e":"trade","E":{time},"s":"{symbol}","t":{id},"p":"{price}","q":"{qty}","T":{time},"m":false,"M":true}}"#,
+            id = row + 1,
+        ))));
+    }
+    Ok((stream::iter(frames).chain(stream::pending()).boxed(), malformed))
+}
+
+fn parse_row(record: &csv::StringRecord) -> Option<(i64, f64, f64)> {
+    let time = record.get(0)?.parse().ok()?;
+    let price = record.get(1)?.parse().ok()?;
+    let qty = match record.get(2) {
+        Some(qty) => qty.parse().ok()?,
+        None => 0.0,
+    };
+    Some((time, price, qty))
+}
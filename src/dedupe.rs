@@ -0,0 +1,59 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Trade ids already counted by some client, per symbol, with the oldest evicted first.
+#[derive(Debug, Default)]
+struct Seen {
+    ids: HashSet<u64>,
+    order: VecDeque<u64>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    by_symbol: HashMap<String, Seen>,
+    capacity: usize,
+    suppressed: u64,
+}
+
+/// Shared across clients with `--dedupe-across-clients`, so a trade that reaches several
+/// clients is counted by the first one only. Remembers at most `capacity` ids per symbol;
+/// a duplicate arriving after its id was evicted is counted again.
+#[derive(Debug, Clone)]
+pub struct TradeFilter {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl TradeFilter {
+    pub fn new(capacity: usize) -> TradeFilter {
+        TradeFilter { shared: Arc::new(Mutex::new(Shared { by_symbol: HashMap::new(), capacity, suppressed: 0 })) }
+    }
+
+    /// Whether the calling client should count trade `id` of `symbol`: true the first
+    /// time any client offers it, false (and counted as suppressed) after that.
+    pub fn claim(&self, symbol: &str, id: u64) -> bool {
+        let Ok(mut shared) = self.shared.lock() else {
+            return true;
+        };
+        let capacity = shared.capacity;
+        if !shared.by_symbol.contains_key(symbol) {
+            shared.by_symbol.insert(symbol.to_string(), Seen::default());
+        }
+        let seen = shared.by_symbol.get_mut(symbol).unwrap();
+        if !seen.ids.insert(id) {
+            shared.suppressed += 1;
+            return false;
+        }
+        seen.order.push_back(id);
+        if seen.order.len() > capacity {
+            if let Some(oldest) = seen.order.pop_front() {
+                seen.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    /// Duplicates turned away so far.
+    pub fn suppressed(&self) -> u64 {
+        self.shared.lock().map_or(0, |shared| shared.suppressed)
+    }
+}
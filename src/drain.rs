@@ -0,0 +1,37 @@
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+/// Work that has to finish before a gracefully shut down run has saved everything, by
+/// name, so `--drain-timeout` can say what it gave up on.
+#[derive(Debug, Clone, Default)]
+pub struct Pending {
+    names: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl Pending {
+    /// Mark `name` as pending until the returned guard is dropped.
+    pub fn track(&self, name: String) -> PendingGuard {
+        if let Ok(mut names) = self.names.lock() {
+            names.insert(name.clone());
+        }
+        PendingGuard { pending: self.clone(), name }
+    }
+
+    /// What is still pending, in name order.
+    pub fn names(&self) -> Vec<String> {
+        self.names.lock().map(|names| names.iter().cloned().collect()).unwrap_or_default()
+    }
+}
+
+pub struct PendingGuard {
+    pending: Pending,
+    name: String,
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        if let Ok(mut names) = self.pending.names.lock() {
+            names.remove(&self.name);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::http::{header, HeaderValue};
+
+/// Extra fields for the WebSocket upgrade request, for endpoints and proxies that want
+/// them (`--ws-subprotocol`, `--origin`).
+#[derive(Debug, Clone, Default)]
+pub struct Handshake {
+    pub subprotocol: Option<String>,
+    pub origin: Option<String>,
+}
+
+impl Handshake {
+    /// Set the configured fields on an upgrade request. Both were validated as header
+    /// values when parsed.
+    pub fn apply(&self, request: &mut Request) {
+        let headers = request.headers_mut();
+        if let Some(subprotocol) = self.subprotocol.as_deref().and_then(|value| HeaderValue::from_str(value).ok()) {
+            headers.insert(header::SEC_WEBSOCKET_PROTOCOL, subprotocol);
+        }
+        if let Some(origin) = self.origin.as_deref().and_then(|value| HeaderValue::from_str(value).ok()) {
+            headers.insert(header::ORIGIN, origin);
+        }
+    }
+}
+
+/// Parse `--ws-subprotocol`, which must be an HTTP token (RFC 7230), e.g. `v1.trades`.
+pub fn parse_subprotocol(value: &str) -> Result<String, String> {
+    let is_token_char = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    if !value.is_empty() && value.chars().all(is_token_char) {
+        Ok(value.to_string())
+    } else {
+        Err(format!("`{value}` is not a valid subprotocol token"))
+    }
+}
+
+/// Parse `--origin`, a scheme and host such as `https://example.com`.
+pub fn parse_origin(value: &str) -> Result<String, String> {
+    let host = value.strip_prefix("https://").or_else(|| value.strip_prefix("http://"));
+    match host {
+        Some(host) if !host.is_empty() && HeaderValue::from_str(value).is_ok() && !host.contains(char::is_whitespace) => Ok(value.to_string()),
+        _ => Err(format!("`{value}` is not an origin like https://example.com")),
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+
+use chrono::{DateTime, Utc};
+
+/// File `--html-report` writes at the end of a run.
+pub const REPORT_PATH: &str = "report.html";
+
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 240.0;
+/// Room left of and below the plot for the axis labels.
+const MARGIN_LEFT: f64 = 90.0;
+const MARGIN_BOTTOM: f64 = 24.0;
+
+/// One client's row in the report's table.
+pub struct ClientRow {
+    pub name: String,
+    pub symbol: String,
+    pub source: String,
+    pub average: f64,
+    pub trades: usize,
+    /// Lowest and highest price.
+    pub extremes: Option<(f64, f64)>,
+    pub precision: usize,
+}
+
+/// One symbol's global average and its per-second series for the chart.
+pub struct SymbolSection {
+    pub symbol: String,
+    pub method: String,
+    pub global_average: f64,
+    /// `(unix second, average)`, oldest first.
+    pub points: Vec<(i64, f64)>,
+    pub precision: usize,
+}
+
+/// Render a self-contained page: styles and charts are inline, so it opens offline.
+pub fn render(generated_at: DateTime<Utc>, clients: &[ClientRow], symbols: &[SymbolSection]) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>Price report</title>\n");
+    html.push_str(
+        "<style>\nbody{font-family:sans-serif;margin:2em;color:#222}\ntable{border-collapse:collapse;margin-bottom:2em}\n\
+         th,td{border:1px solid #ccc;padding:4px 10px;text-align:right}\nth{background:#f0f0f0}\n\
+         td:first-child,td:nth-child(2),td:nth-child(3){text-align:left}\nsvg{background:#fafafa;border:1px solid #ddd}\n</style>\n",
+    );
+    html.push_str("</head>\n<body>\n<h1>Price report</h1>\n");
+    let _ = writeln!(html, "<p>Generated {}</p>", generated_at.format("%Y-%m-%d %H:%M:%S UTC"));
+
+    html.push_str("<h2>Global averages</h2>\n<table>\n<tr><th>Symbol</th><th>Method</th><th>Global average</th></tr>\n");
+    for section in symbols {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{:.prec$}</td></tr>",
+            escape(&section.symbol),
+            escape(&section.method),
+            section.global_average,
+            prec = section.precision
+        );
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Clients</h2>\n<table>\n<tr><th>Client</th><th>Symbol</th><th>Source</th><th>Average</th><th>Trades</th><th>Low</th><th>High</th></tr>\n");
+    for client in clients {
+        let prec = client.precision;
+        let (low, high) = match client.extremes {
+            Some((low, high)) => (format!("{low:.prec$}"), format!("{high:.prec$}")),
+            None => (String::new(), String::new()),
+        };
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.prec$}</td><td>{}</td><td>{low}</td><td>{high}</td></tr>",
+            escape(&client.name),
+            escape(&client.symbol),
+            escape(&client.source),
+            client.average,
+            client.trades
+        );
+    }
+    html.push_str("</table>\n");
+
+    for section in symbols {
+        let _ = writeln!(html, "<h2>{} per second</h2>", escape(&section.symbol));
+        html.push_str(&chart(&section.points, section.precision));
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Write the rendered report to `path`.
+pub fn write(path: &str, html: &str) -> io::Result<()> {
+    fs::write(path, html)
+}
+
+/// An inline SVG line chart of `points`, with the price range on the left and the time
+/// range along the bottom.
+fn chart(points: &[(i64, f64)], precision: usize) -> String {
+    let (Some(&(first, _)), Some(&(last, _))) = (points.first(), points.last()) else {
+        return "<p>No per-second data.</p>\n".to_string();
+    };
+    let low = points.iter().map(|&(_, price)| price).fold(f64::INFINITY, f64::min);
+    let high = points.iter().map(|&(_, price)| price).fold(f64::NEG_INFINITY, f64::max);
+    let plot_width = CHART_WIDTH - MARGIN_LEFT;
+    let plot_height = CHART_HEIGHT - MARGIN_BOTTOM;
+    // A flat or single-point series is drawn along the middle.
+    let x = |second: i64| MARGIN_LEFT + if last > first { (second - first) as f64 / (last - first) as f64 * plot_width } else { plot_width / 2.0 };
+    let y = |price: f64| if high > low { (high - price) / (high - low) * plot_height } else { plot_height / 2.0 };
+    let path: Vec<String> = points.iter().map(|&(second, price)| format!("{:.1},{:.1}", x(second), y(price))).collect();
+    let time = |second: i64| DateTime::from_timestamp(second, 0).map_or_else(String::new, |time| time.format("%H:%M:%S").to_string());
+
+    let mut svg = String::new();
+    let _ = writeln!(svg, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{CHART_WIDTH}\" height=\"{CHART_HEIGHT}\" viewBox=\"0 0 {CHART_WIDTH} {CHART_HEIGHT}\">");
+    let _ = writeln!(svg, "<line x1=\"{MARGIN_LEFT}\" y1=\"0\" x2=\"{MARGIN_LEFT}\" y2=\"{plot_height}\" stroke=\"#999\"/>");
+    let _ = writeln!(svg, "<line x1=\"{MARGIN_LEFT}\" y1=\"{plot_height}\" x2=\"{CHART_WIDTH}\" y2=\"{plot_height}\" stroke=\"#999\"/>");
+    let _ = writeln!(svg, "<text x=\"{}\" y=\"12\" font-size=\"11\" text-anchor=\"end\">{high:.precision$}</text>", MARGIN_LEFT - 4.0);
+    let _ = writeln!(svg, "<text x=\"{}\" y=\"{plot_height}\" font-size=\"11\" text-anchor=\"end\">{low:.precision$}</text>", MARGIN_LEFT - 4.0);
+    let _ = writeln!(svg, "<text x=\"{MARGIN_LEFT}\" y=\"{}\" font-size=\"11\">{}</text>", CHART_HEIGHT - 6.0, time(first));
+    let _ = writeln!(svg, "<text x=\"{CHART_WIDTH}\" y=\"{}\" font-size=\"11\" text-anchor=\"end\">{}</text>", CHART_HEIGHT - 6.0, time(last));
+    if let [(second, price)] = points {
+        let _ = writeln!(svg, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"#1f77b4\"/>", x(*second), y(*price));
+    }
+    let _ = writeln!(svg, "<polyline fill=\"none\" stroke=\"#1f77b4\" stroke-width=\"1.5\" points=\"{}\"/>", path.join(" "));
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Escape text for HTML element content.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,145 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+
+use futures::future::{BoxFuture, FutureExt};
+use tokio::time::{self, Duration};
+
+use crate::batch::{PriceSink, SinkResult};
+use crate::{redact_url, Trade};
+
+/// Attempts for a POST made once, e.g. a client's lines at the end of its window.
+const POST_ATTEMPTS: u32 = 3;
+/// Wait after the first failed attempt; doubled after each further one.
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Escape a tag value for InfluxDB line protocol.
+fn escape_tag(value: &str) -> String {
+    value.replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+/// Convert epoch milliseconds to the nanosecond timestamps Influx expects.
+fn millis_to_nanos(millis: i64) -> i64 {
+    millis.saturating_mul(1_000_000)
+}
+
+/// Line for a single trade: `trade,symbol=BTCUSDT price=..,qty=.. <ns>`.
+pub fn trade_line(symbol: &str, trade: &Trade) -> String {
+    format!(
+        "trade,symbol={} price={},qty={} {}",
+        escape_tag(symbol),
+        trade.price,
+        trade.qty,
+        millis_to_nanos(trade.time)
+    )
+}
+
+/// Line for a client's average over its window.
+pub fn client_average_line(symbol: &str, client: &str, average: f64, millis: i64) -> String {
+    format!(
+        "client_average,symbol={},client={} price={average} {}",
+        escape_tag(symbol),
+        escape_tag(client),
+        millis_to_nanos(millis)
+    )
+}
+
+/// Line for the aggregator's global average.
+pub fn global_average_line(symbol: &str, average: f64, millis: i64) -> String {
+    format!(
+        "global_average,symbol={} price={average} {}",
+        escape_tag(symbol),
+        millis_to_nanos(millis)
+    )
+}
+
+/// Write `lines` to `path`, or POST them to `url` when one is given.
+pub async fn write_lines(lines: &[String], path: &str, url: Option<&str>) -> SinkResult {
+    let body = body(lines);
+    match url {
+        Some(url) => post_with_retries(url, body).await?,
+        None => {
+            let mut file = File::create(path)?;
+            file.write_all(body.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Append `lines` to `path`, or POST them to `url` when one is given.
+pub async fn append_lines(lines: &[String], path: &str, url: Option<&str>) -> SinkResult {
+    match url {
+        Some(url) => post_with_retries(url, body(lines)).await,
+        None => append(lines, path),
+    }
+}
+
+fn body(lines: &[String]) -> String {
+    let mut body = lines.join("\n");
+    body.push('\n');
+    body
+}
+
+fn append(lines: &[String], path: &str) -> SinkResult {
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+    file.write_all(body(lines).as_bytes())?;
+    Ok(())
+}
+
+async fn post(url: &str, body: String) -> SinkResult {
+    reqwest::Client::new()
+        .post(url)
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// POST `body`, retrying with backoff when the server can't be reached or fails. Used for
+/// the one-off writes at the end of a window, which have no later batch to retry with.
+async fn post_with_retries(url: &str, body: String) -> SinkResult {
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match post(url, body.clone()).await {
+            Err(e) if attempt < POST_ATTEMPTS => {
+                eprintln!("Influx: Failed to POST to {}: {e}; retrying in {}s.", redact_url(url), delay.as_secs());
+                time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Streams a client's trades as line protocol, batch by batch, to `path` or `url`.
+pub struct TradeSink {
+    symbol: String,
+    path: String,
+    url: Option<String>,
+}
+
+impl TradeSink {
+    /// Start a fresh stream, truncating `path` when writing to a file.
+    pub fn create(symbol: &str, path: String, url: Option<String>) -> std::io::Result<TradeSink> {
+        if url.is_none() {
+            File::create(&path)?;
+        }
+        Ok(TradeSink { symbol: symbol.to_string(), path, url })
+    }
+}
+
+impl PriceSink for TradeSink {
+    fn write_batch<'a>(&'a mut self, trades: &'a [Trade]) -> BoxFuture<'a, SinkResult> {
+        async move {
+            let lines: Vec<String> = trades.iter().map(|trade| trade_line(&self.symbol, trade)).collect();
+            // A single attempt: the batcher keeps failed trades and retries with backoff.
+            match &self.url {
+                Some(url) => post(url, body(&lines)).await,
+                None => append(&lines, &self.path),
+            }
+        }
+        .boxed()
+    }
+}
@@ -1,205 +1,5635 @@
-use futures::StreamExt;
+use futures::{future::{self, BoxFuture}, stream::BoxStream, SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tokio::{net::TcpStream, sync::mpsc, task};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message, MaybeTlsStream};
-use std::time::Instant;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::{net::TcpStream, sync::{broadcast, mpsc}, task, time::{self, Instant}};
+use tokio_tungstenite::{connect_async_with_config, tungstenite::{client::IntoClientRequest, http::StatusCode, protocol::{Message, WebSocketConfig}, Error as WsError}, MaybeTlsStream, WebSocketStream};
+use std::time::Duration;
 use std::fs::File;
 use std::io::{self, BufRead, Write, BufReader};
-use clap::{Command, Arg};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use clap::{builder::PossibleValue, Command, Arg};
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+use rand::Rng;
+use rust_decimal::Decimal;
+use tokio_util::sync::CancellationToken;
 
-/// Client process: Fetch prices, calculate average, send to aggregator.
-async fn client_process(id: usize, tx: mpsc::Sender<(usize, f64)>, duration: u64) {
-    let mut ws_stream = match connect_to_websocket().await {
-        Ok(ws) => ws,
-        Err(e) => {
-            eprintln!("Client {id}: Failed to connect to WebSocket: {e}");
-            return;
+mod averager;
+mod batch;
+mod checkpoint;
+mod checksum;
+mod clock;
+mod coinbase;
+mod combined;
+mod control_socket;
+mod dedupe;
+mod drain;
+mod handshake;
+mod html_report;
+mod csv_file;
+mod influx;
+mod metrics;
+mod miniticker;
+mod mock;
+mod msgpack;
+mod parse_pool;
+mod pause;
+mod percentiles;
+mod recorder;
+mod rest_poll;
+mod rotate;
+mod run_log;
+mod series;
+mod state;
+mod symbol_stats;
+#[cfg(test)]
+mod test_support;
+mod trade_cap;
+mod trade_expr;
+mod trade_stream;
+mod webhook;
+mod yaml;
+
+use averager::Averager;
+use batch::Batcher;
+use checksum::Verification;
+use metrics::{ClientMetrics, RunMetrics, SharedMetrics};
+use miniticker::MiniTicker;
+use percentiles::{Percentiles, QUANTILES};
+use clock::ServerClock;
+use control_socket::{ControlSocket, LatestAverages};
+use dedupe::TradeFilter;
+use handshake::Handshake;
+use recorder::Recorder;
+use pause::PauseSwitch;
+use trade_cap::TradeCap;
+use trade_expr::TradeExpr;
+use trade_stream::{FeedStats, TradeStream};
+use state::{ClientState, State, StateFile};
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+/// Frames from whatever feed a client is reading, live or synthetic.
+type FrameStream = BoxStream<'static, Result<Message, WsError>>;
+/// A client's share of the trades routed by the shard dispatcher.
+type ShardFeed = Arc<tokio::sync::Mutex<mpsc::Receiver<Message>>>;
+
+/// Binance REST endpoint describing a symbol's trading rules.
+const BINANCE_EXCHANGE_INFO_URL: &str = "https://api.binance.com/api/v3/exchangeInfo";
+/// How often clients send live averages and the live aggregator prints them.
+const LIVE_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the aggregator waits for reports beyond the clients' window by default.
+const DEFAULT_AGGREGATOR_GRACE_SECS: u64 = 60;
+/// Symbol tracked by the clients unless `--symbols` says otherwise.
+const SYMBOL: &str = "BTCUSDT";
+/// Prefix of the environment variables settings are read from, unless `--env-prefix` is given.
+const ENV_PREFIX: &str = "RMC_";
+/// Where `--merge-clients` saves a run.
+const COMBINED_PATH: &str = "combined_data.json";
+
+/// How the backoff grows after each failed attempt, from `--backoff-strategy`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BackoffStrategy {
+    /// Always wait the base delay.
+    Fixed,
+    /// Add the base delay after each failure.
+    Linear,
+    /// Double the delay after each failure.
+    Exponential,
+}
+
+impl BackoffStrategy {
+    fn name(self) -> &'static str {
+        match self {
+            BackoffStrategy::Fixed => "fixed",
+            BackoffStrategy::Linear => "linear",
+            BackoffStrategy::Exponential => "exponential",
         }
-    };
+    }
+}
 
-    println!("Client {id}: Connected to WebSocket.");
-    let mut prices: Vec<f64> = Vec::new();
-    let start_time = Instant::now();
+/// How `connect_with_retry` spaces out its attempts.
+#[derive(Debug, Clone, Copy)]
+struct RetryPolicy {
+    strategy: BackoffStrategy,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    /// Sleep a random time between zero and the current backoff ("full jitter") so clients
+    /// that lost their connections together don't all retry in lockstep.
+    jitter: bool,
+}
 
-    while start_time.elapsed().as_secs() < duration {
-        if let Some(Ok(Message::Text(text))) = ws_stream.next().await {
-            if let Ok(price) = process_message(&text) {
-                prices.push(price);
-                // println!("Client {id}: {}", price);
-            }
+impl RetryPolicy {
+    /// How long to wait before the next attempt, given the current backoff.
+    fn delay(&self, backoff: Duration) -> Duration {
+        if self.jitter {
+            rand::thread_rng().gen_range(Duration::ZERO..=backoff)
         } else {
-            eprintln!("Client {id}: Failed to receive message.");
-            break;
+            backoff
         }
     }
 
-    if let Some(avg) = calculate_average(&prices) {
-        println!("Client {id}: Average BTC price: {:.4}", avg);
-        let _ = tx.send((id, avg)).await;
-        save_client_data(id, &prices, avg).unwrap_or_else(|e| eprintln!("Client {id}: Failed to save data: {e}"));
-    } else {
-        eprintln!("Client {id}: No data points collected.");
+    /// The backoff after another failure, given the current one. Never above `max_backoff`.
+    fn next_backoff(&self, backoff: Duration) -> Duration {
+        let next = match self.strategy {
+            BackoffStrategy::Fixed => self.initial_backoff,
+            BackoffStrategy::Linear => backoff + self.initial_backoff,
+            BackoffStrategy::Exponential => backoff * 2,
+        };
+        next.min(self.max_backoff)
     }
 }
 
-/// Aggregator process: Compute global average from clients.
-async fn aggregator_process(mut rx: mpsc::Receiver<(usize, f64)>, num_clients: usize) {
-    let mut averages = Vec::with_capacity(5);
+/// A single trade parsed from the WebSocket feed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Trade {
+    /// Exchange-assigned trade id, increasing within a symbol.
+    id: u64,
+    price: f64,
+    /// `price` exactly as quoted, for `--decimal`. Not saved.
+    #[serde(skip)]
+    exact_price: Decimal,
+    qty: f64,
+    /// Trade time in milliseconds since the Unix epoch.
+    time: i64,
+    /// When the exchange emitted the event, in milliseconds since the Unix epoch.
+    event_time: i64,
+}
+
+/// What a text frame from a feed turned out to be, when it parsed.
+#[derive(Debug, Clone, Copy)]
+enum Frame {
+    Trade(Trade),
+    /// Valid JSON that isn't a trade, such as a subscription ack, a keepalive or an error
+    /// frame. Neither data nor a parse error.
+    Other,
+}
+
+/// Timezone used to render timestamps in saved output.
+#[derive(Debug, Clone, Copy)]
+enum DisplayTz {
+    Local,
+    Named(Tz),
+}
+
+/// Where client and global results are written.
+#[derive(Debug, Clone)]
+enum Sink {
+    /// Plain text files (`client_{id}_data.txt`, `global_data.txt`).
+    File,
+    /// InfluxDB line protocol, written to `.lp` files or POSTed to `url` when set.
+    Influx { url: Option<String> },
+    /// MessagePack files (`client_{id}_data.msgpack`, `global_data.msgpack`).
+    MessagePack,
+    /// YAML files (`client_{id}_data.yaml`, `global_data.yaml`) with the same contents as
+    /// the MessagePack ones.
+    Yaml,
+    /// One merged stdout stream, each line tagged with its origin. Lines go through a
+    /// single writer task so concurrent clients never interleave partial lines.
+    Stdout(mpsc::Sender<String>),
+    /// Save nothing; averages are only printed (`--no-save`).
+    Discard,
+    /// One `combined_data.json` written by the aggregator, with each client's data sent
+    /// along with its report (`--merge-clients`).
+    Merged(JsonStyle),
+    /// Trades streamed through a batcher into memory, for tests to read back. Nothing is
+    /// saved at the end.
+    #[cfg(test)]
+    Memory(batch::InMemorySink),
+}
 
-    for _ in 0..num_clients {
-        if let Some((id, avg)) = rx.recv().await {
-            println!("Aggregator: Received average from client {id}: {avg:.4}");
-            averages.push(avg);
+impl Sink {
+    /// The file a client's data ends up in, if it is saved to one.
+    fn client_path(&self, name: &str) -> Option<String> {
+        match self {
+            Sink::File => Some(format!("client_{name}_data.txt")),
+            Sink::Influx { url: None } => Some(format!("client_{name}_data.lp")),
+            Sink::MessagePack => Some(format!("client_{name}_data.msgpack")),
+            Sink::Yaml => Some(format!("client_{name}_data.yaml")),
+            _ => None,
         }
     }
 
-    if let Some(global_avg) = calculate_average(&averages) {
-        println!("Aggregator: Global average BTC price: {:.4}", global_avg);
-        save_global_data(&averages, global_avg).unwrap_or_else(|e| eprintln!("Aggregator: Failed to save global data: {e}"));
-    } else {
-        eprintln!("Aggregator: No averages received.");
+    /// The file the aggregator's results end up in, if they are saved to one.
+    fn global_path(&self) -> Option<&'static str> {
+        match self {
+            Sink::File => Some("global_data.txt"),
+            Sink::Influx { url: None } => Some("global_data.lp"),
+            Sink::MessagePack => Some("global_data.msgpack"),
+            Sink::Yaml => Some("global_data.yaml"),
+            Sink::Merged(_) => Some(COMBINED_PATH),
+            _ => None,
+        }
     }
 }
 
-/// Connect to WebSocket server.
-async fn connect_to_websocket() -> Result<tokio_tungstenite::WebSocketStream<MaybeTlsStream<TcpStream>>, Box<dyn std::error::Error>> {
-    let url = "wss://stream.binance.com:9443/ws/btcusdt@trade";
-    let (ws_stream, _) = connect_async(url).await?;
-    Ok(ws_stream)
+/// Statistic a client reports as its average.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stat {
+    /// Plain mean of the trade prices.
+    Mean,
+    /// Time-weighted average price.
+    Twap,
+    /// Geometric mean of the trade prices, `exp(mean(ln(price)))`.
+    Geomean,
+    /// Volume-weighted average price.
+    Vwap,
 }
 
-/// Process WebSocket message to extract price.
-fn process_message(text: &str) -> Result<f64, Box<dyn std::error::Error>> {
-    let json: Value = serde_json::from_str(text)?;
-    if let Some(price) = json.get("p") {
-        price.as_str().unwrap().parse::<f64>().map_err(|e| e.into())
-    } else {
-        Err("No price field found".into())
+impl Stat {
+    /// A fresh [`Averager`] for this statistic. `decimal` asks for exact decimal
+    /// arithmetic, which only the mean supports. A TWAP needs the end of the window and
+    /// any spans to leave out.
+    fn averager(self, decimal: bool, window_end: Option<i64>, excluded: Vec<(i64, i64)>) -> Box<dyn Averager> {
+        match self {
+            Stat::Mean if decimal => Box::<averager::DecimalMean>::default(),
+            Stat::Mean => Box::<averager::Mean>::default(),
+            Stat::Twap => Box::new(averager::Twap::new(window_end, excluded)),
+            Stat::Geomean => Box::<averager::Geomean>::default(),
+            Stat::Vwap => Box::<averager::Vwap>::default(),
+        }
     }
 }
 
-/// Calculate the average of a vector of numbers.
-fn calculate_average(prices: &Vec<f64>) -> Option<f64> {
-    if prices.is_empty() {
-        None
-    } else {
-        Some(prices.iter().sum::<f64>() / prices.len() as f64)
+/// How a client computes its percentiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PercentileMode {
+    Exact,
+    TDigest,
+}
+
+/// Which Binance stream a client subscribes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamType {
+    /// Every individual trade (`@trade`).
+    Trade,
+    /// Rolling 24h statistics, about once a second (`@miniTicker`).
+    MiniTicker,
+}
+
+/// How the aggregator weighs client averages into a global average.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Weighting {
+    /// Plain mean of the client averages.
+    Equal,
+    /// By how many trades each client saw (with `--shard`).
+    Trades,
+    /// By how recent each client's last trade is compared to the freshest client's
+    /// (`--recency-half-life`).
+    Recency { decay: Decay, half_life: Duration },
+}
+
+/// How a client's recency weight falls off with the age of its last trade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decay {
+    /// Halves every half-life.
+    Exponential,
+    /// Falls in a straight line, reaching one half at the half-life and zero at twice it.
+    Linear,
+}
+
+impl Decay {
+    /// Weight of data that is `age` old, between 0 and 1.
+    fn weight(self, age: Duration, half_life: Duration) -> f64 {
+        let half_lives = age.as_secs_f64() / half_life.as_secs_f64();
+        match self {
+            Decay::Exponential => 0.5_f64.powf(half_lives),
+            Decay::Linear => (1.0 - half_lives / 2.0).max(0.0),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Decay::Exponential => "exponential",
+            Decay::Linear => "linear",
+        }
     }
 }
 
-/// Save individual client data to file.
-fn save_client_data(id: usize, prices: &Vec<f64>, average: f64) -> std::io::Result<()> {
-    let mut file = File::create(format!("client_{id}_data.txt"))?;
-    writeln!(file, "Prices: {:?}\nAverage: {:.4}", prices, average)?;
-    Ok(())
+/// How JSON output is laid out (`--json-style`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JsonStyle {
+    /// Indented over many lines, for reading.
+    Pretty,
+    /// One line, for machines: smaller and faster to parse.
+    Compact,
 }
 
-/// Save global aggregator data to file.
-fn save_global_data(averages: &Vec<f64>, global_average: f64) -> std::io::Result<()> {
-    let mut file = File::create("global_data.txt")?;
-    writeln!(file, "Client Averages: {:?}\nGlobal Average: {:.4}", averages, global_average)?;
-    Ok(())
+impl JsonStyle {
+    fn write<W: io::Write, T: Serialize + ?Sized>(self, writer: W, value: &T) -> serde_json::Result<()> {
+        match self {
+            JsonStyle::Pretty => serde_json::to_writer_pretty(writer, value),
+            JsonStyle::Compact => serde_json::to_writer(writer, value),
+        }
+    }
+
+    fn format<T: Serialize + ?Sized>(self, value: &T) -> serde_json::Result<String> {
+        match self {
+            JsonStyle::Pretty => serde_json::to_string_pretty(value),
+            JsonStyle::Compact => serde_json::to_string(value),
+        }
+    }
 }
 
-/// Parse the command-line arguments
-fn parse_arguments() -> clap::ArgMatches {
-    Command::new("WebSocket Listener")
-        .version("1.0")
-        .author("Pruthvi Thakor")
-        .about("Listens to the WebSocket for BTC/USDT prices")
-        .arg(
-            Arg::new("mode")
-                .short('m')
-                .long("mode")
-                .value_name("MODE")
-                .help("Specifies the mode of operation")
-                .default_value("cache"),
-        )
-        .arg(
-            Arg::new("times")
-                .short('t')
-                .long("times")
-                .value_name("NUMBER")
-                .help("The number of seconds to listen")
-                .default_value("1"),
-            )
-            .get_matches()
+/// Where clients get their prices from.
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    /// Live Binance stream.
+    Binance { stream: StreamType },
+    /// Live Coinbase Exchange `matches` channel.
+    Coinbase,
+    /// Offline random walk, reproducible from `seed`, producing `rate` trades per second.
+    Mock { seed: u64, rate: u32 },
+    /// Prices read from the `--input` CSV file.
+    Csv,
+    /// The fixed prices given with `--seed-prices`.
+    Seed,
+    /// Binance recent trades polled over REST every `interval` plus up to `jitter`, for
+    /// networks that block WebSockets.
+    RestPoll { interval: Duration, jitter: Duration },
+}
+
+impl Source {
+    /// Short name used to tag client results.
+    fn name(&self) -> &'static str {
+        match self {
+            Source::Binance { .. } => "binance",
+            Source::Coinbase => "coinbase",
+            Source::Mock { .. } => "mock",
+            Source::Csv => "csv",
+            Source::Seed => "seed",
+            Source::RestPoll { .. } => "rest-poll",
         }
-        
-/// Prints the data after reading it from file
-fn read_mode(num_clients: usize) -> io::Result<()> {
-    println!("Reading prices data ...\n");
-    let mut files: Vec<String> = Vec::with_capacity(num_clients+1);
-    for i in 1..=num_clients {
-        files.push(format!("client_{}_data.txt", i));
     }
-    files.push(String::from("global_data.txt"));
-    'file_loop: for file_path in files.iter() {
-        // Attempt to open the file
-        let file = match File::open(file_path) {
-            Ok(file) => file,
-            Err(err) => {
-                eprintln!("Failed to open {}: {}", file_path, err);
-                break 'file_loop; // Exit the loop on error
-            }
-        };
-        println!("\nReading file: {}\n", file_path);
-        let reader = BufReader::new(file);
 
-        // Read the file line by line
-        for line in reader.lines() {
-            match line {
-                Ok(content) => println!("{}", content),
-                Err(err) => {
-                    eprintln!("Error reading a line in {}: {}", file_path, err);
-                    break 'file_loop; // Exit the loop on error
-                }
+    /// WebSocket endpoint carrying trades for `symbol`.
+    fn url(&self, symbol: &str) -> String {
+        match self {
+            Source::Coinbase => coinbase::URL.to_string(),
+            Source::RestPoll { .. } => format!("{}?symbol={}", rest_poll::TRADES_URL, symbol.to_uppercase()),
+            Source::Binance { stream: StreamType::MiniTicker } => binance_mini_ticker_url(symbol),
+            Source::Binance { .. } | Source::Mock { .. } | Source::Csv | Source::Seed => binance_url(symbol),
+        }
+    }
+
+    /// Message to send right after connecting, for feeds that need one.
+    fn subscription(&self, symbol: &str) -> Option<String> {
+        match self {
+            Source::Coinbase => Some(coinbase::subscription(symbol)),
+            Source::Binance { .. } | Source::Mock { .. } | Source::Csv | Source::Seed | Source::RestPoll { .. } => None,
+        }
+    }
+
+    /// Parse a text frame from this source.
+    fn parse(&self, text: &str) -> Result<Frame, Box<dyn std::error::Error>> {
+        match self {
+            Source::Coinbase => coinbase::process_message(text),
+            Source::Binance { stream: StreamType::MiniTicker } => {
+                miniticker::process_message(text).map(|ticker| ticker.map_or(Frame::Other, |ticker| Frame::Trade(ticker.trade())))
             }
+            // The offline and REST sources emit Binance-style frames.
+            Source::Binance { .. } | Source::Mock { .. } | Source::Csv | Source::Seed | Source::RestPoll { .. } => process_message(text),
         }
     }
+}
 
-    Ok(())
+/// Why a client finished its window without an average.
+#[derive(Debug)]
+enum PriceError {
+    NoData,
+    NoDataAfterTrimming,
+    ZeroOpen,
+    /// A geometric mean was asked for, but a price was zero or negative.
+    NonPositivePrice,
+    /// A VWAP was asked for, but the trades had no quantity.
+    ZeroVolume,
+    /// The exact `--decimal` sum of the prices doesn't fit in a `Decimal`.
+    DecimalOverflow,
+    /// Never connected, with `--fail-fast`.
+    ConnectFailed,
+    /// Stopped because another client failed to connect, with `--fail-fast`.
+    Aborted,
+    /// Stopped after this many frames failed to parse, with `--strict-json`.
+    TooManyParseErrors(u64),
+    /// Binance rejected the stream with an error frame.
+    Rejected(BinanceError),
+    /// Stopped on a price outside `--min-price`/`--max-price`, with `--strict-range`.
+    OutOfRange(f64),
+    /// Connected, but no trade arrived within `--first-frame-timeout`.
+    NoFirstTrade(Duration),
 }
 
-#[tokio::main]
-async fn main() {
-    let num_clients: usize = 5;
-    // let duration = 10; // seconds
-    let matches = parse_arguments();
+impl std::fmt::Display for PriceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PriceError::NoData => write!(f, "No data points collected."),
+            PriceError::NoDataAfterTrimming => write!(f, "No data after trimming."),
+            PriceError::ZeroOpen => write!(f, "Cannot normalize, the window opened at a price of zero."),
+            PriceError::NonPositivePrice => write!(f, "Cannot take the geometric mean, a price was zero or negative."),
+            PriceError::ZeroVolume => write!(f, "Cannot take the VWAP, the trades' total quantity is zero."),
+            PriceError::DecimalOverflow => write!(f, "Cannot take the exact mean, the sum of the prices overflows a decimal (--decimal)."),
+            PriceError::ConnectFailed => write!(f, "Could not connect."),
+            PriceError::Aborted => write!(f, "Discarded data because the run was aborted."),
+            PriceError::TooManyParseErrors(errors) => write!(f, "Discarded data after {errors} frames failed to parse (--strict-json)."),
+            PriceError::Rejected(error) => write!(f, "Binance rejected the stream: {error}."),
+            PriceError::OutOfRange(price) => write!(f, "Discarded data after a price of {price} fell outside --min-price/--max-price (--strict-range)."),
+            PriceError::NoFirstTrade(timeout) => write!(f, "Connected but no data: no trade within {}s (--first-frame-timeout).", timeout.as_secs()),
+        }
+    }
+}
 
-    // Extract the mode and times arguments
-    let mode = matches.get_one::<String>("mode").unwrap();
-    let times: u64 = matches
-        .get_one::<String>("times")
-        .unwrap()
-        .parse()
-        .unwrap_or(1);
+impl std::error::Error for PriceError {}
 
-    // Print the parsed arguments
-    println!("Mode: {}", mode);
+/// What a client computed over its window, as written to the sinks.
+#[derive(Debug, Clone, Copy)]
+struct WindowSummary {
+    average: f64,
+    /// The open prices were divided by, with `--normalize`.
+    normalized_open: Option<f64>,
+    /// p50, p90 and p99, with `--percentile-mode`.
+    percentiles: Option<[f64; 3]>,
+    /// The last 24h snapshot, with `--stream-type miniticker`.
+    ticker: Option<MiniTicker>,
+    repeats: RepeatStats,
+    /// Trades from before a `--resume` that the average includes.
+    resumed_trades: u64,
+    /// With `--max-samples-per-client`, the number of trades counted once the prices
+    /// stopped being kept.
+    truncated_at: Option<u64>,
+}
 
+impl WindowSummary {
+    /// The summary of a window still open, for snapshots: `average` is the running mean
+    /// and the end-of-window statistics are left out.
+    fn running(average: f64, ticker: Option<MiniTicker>, repeats: RepeatStats, resumed_trades: u64, truncated_at: Option<u64>) -> WindowSummary {
+        WindowSummary { average, normalized_open: None, percentiles: None, ticker, repeats, resumed_trades, truncated_at }
+    }
+}
 
-    // Start the WebSocket listener in the "cache" mode
-    match mode.as_str() {
-        "cache" => {
-            let (tx, rx) = mpsc::channel(num_clients);
-            let aggregator = task::spawn(aggregator_process(rx, num_clients));
+/// What `client_process` ends its window with, for callers that hold its task handle.
+#[derive(Debug, Clone)]
+struct ClientResult {
+    name: String,
+    symbol: String,
+    source: &'static str,
+    average: f64,
+    trades: usize,
+    parse_errors: u64,
+    /// Prices dropped for falling outside `--min-price`/`--max-price`.
+    out_of_range: u64,
+    stats: ConnectionStats,
+    /// With `--profile`.
+    profile: Option<PipelineProfile>,
+}
 
-            let mut clients = Vec::new();
-            for id in 1..=num_clients {
-                let tx_clone = tx.clone();
-                clients.push(task::spawn(client_process(id, tx_clone, times)));
-            }
-            println!("Will listen for {} seconds.", times);
-            for client in clients {
-                let _ = client.await;
+/// A client's average over the trailing `--live-window`, sent every second.
+#[derive(Debug, Clone)]
+struct LiveUpdate {
+    id: usize,
+    symbol: String,
+    /// `None` when no trade arrived within the window.
+    average: Option<f64>,
+}
+
+/// A client's cumulative average so far, sent every `--compute-on-the-fly` trades.
+#[derive(Debug)]
+struct PartialAverage {
+    id: usize,
+    name: String,
+    symbol: String,
+    average: f64,
+    /// Number of trades behind `average`.
+    trades: u64,
+}
+
+/// What a client sends the aggregator: with `--compute-on-the-fly`, partial averages
+/// while it listens, then always one final report.
+#[derive(Debug)]
+enum ClientUpdate {
+    Partial(PartialAverage),
+    Final(Box<ClientReport>),
+}
+
+/// What a client reports to the aggregator at the end of its window.
+#[derive(Debug)]
+struct ClientReport {
+    id: usize,
+    name: String,
+    symbol: String,
+    /// Name of the source the client read from.
+    source: &'static str,
+    average: f64,
+    /// Number of trades behind `average`.
+    trades: usize,
+    /// Average price for each second of trade time, as `(unix second, average)`.
+    buckets: Vec<(i64, f64)>,
+    /// Event time of the client's last trade, in milliseconds, before any trimming.
+    last_event_time: i64,
+    /// Lowest and highest price behind `average`.
+    extremes: Option<(f64, f64)>,
+    /// With `--merge-clients`, the client's full data, moved here rather than copied.
+    section: Option<Box<combined::ClientSection>>,
+    /// The client's `--groups` group, when clients are grouped.
+    group: Option<String>,
+    /// With `--aggregator-saves-per-client`, the client's data file for the aggregator to
+    /// write.
+    save: Option<Box<ClientSave>>,
+}
+
+/// A client's data file, handed to the aggregator to write with
+/// `--aggregator-saves-per-client`.
+#[derive(Debug)]
+struct ClientSave {
+    path: String,
+    contents: ClientContents,
+}
+
+#[derive(Debug)]
+enum ClientContents {
+    /// The lines of a text data file.
+    Text(Vec<String>),
+    /// The data of a MessagePack or YAML file, picked by `path`'s extension.
+    Structured(Box<msgpack::ClientData>),
+}
+
+impl ClientSave {
+    fn write(&self) -> Result<(), Box<dyn std::error::Error>> {
+        match &self.contents {
+            ClientContents::Text(lines) => {
+                let mut contents = lines.join("\n");
+                contents.push('\n');
+                Ok(state::write_atomic(&self.path, contents.as_bytes())?)
             }
+            ClientContents::Structured(data) => write_structured(&self.path, data),
+        }
+    }
+}
 
-            let _ = aggregator.await;
-        },
-        "read" => read_mode(num_clients).expect("Failed to read price data"),
-        _ => eprintln!("Invalid mode: {mode}. Use --mode=cache or --mode=read.")
+/// Decimal places used when printing prices.
+#[derive(Debug, Clone)]
+struct Precision {
+    /// From `--precision`.
+    default: usize,
+    /// Looked up from each symbol's tick size with `--decimals-from-symbol`.
+    by_symbol: HashMap<String, usize>,
+}
+
+impl Precision {
+    fn of(&self, symbol: &str) -> usize {
+        self.by_symbol.get(symbol).copied().unwrap_or(self.default)
     }
-    
 }
 
+/// The aggregator's result for one symbol.
+#[derive(Debug, Clone)]
+struct SymbolSummary {
+    symbol: String,
+    /// Decimal places for printing this symbol's prices.
+    precision: usize,
+    /// Client averages in client id order.
+    averages: Vec<f64>,
+    global_average: f64,
+    /// How the global average was computed, see [`global_method`].
+    method: String,
+    comparison: Option<SourceComparison>,
+    /// With `--groups`, each group's average, by group name.
+    groups: Vec<GroupAverage>,
+    /// With `--consensus`, how closely the client averages agree.
+    consensus: Option<Consensus>,
+}
+
+/// How closely a symbol's client averages agree. Every client reads the same feed, so
+/// they should be nearly equal; a large spread points at a stuck or misconfigured client.
+#[derive(Debug, Clone, Copy)]
+struct Consensus {
+    /// Sample variance of the client averages.
+    variance: f64,
+    /// Their standard deviation as a percentage of their mean (coefficient of variation).
+    cv_pct: f64,
+}
+
+/// The consensus of `averages`, or `None` with fewer than two or a zero mean.
+fn consensus(averages: &[f64]) -> Option<Consensus> {
+    if averages.len() < 2 {
+        return None;
+    }
+    let mean = calculate_average(averages).filter(|&mean| mean != 0.0)?;
+    let variance = averages.iter().map(|average| (average - mean).powi(2)).sum::<f64>() / (averages.len() - 1) as f64;
+    Some(Consensus { variance, cv_pct: variance.sqrt() / mean.abs() * 100.0 })
+}
+
+/// One `--groups` group's average for a symbol.
+#[derive(Debug, Clone)]
+struct GroupAverage {
+    name: String,
+    clients: usize,
+    average: f64,
+}
+
+/// Average prices of two sources over the same window and the spread between them.
+#[derive(Debug, Clone)]
+struct SourceComparison {
+    first: (&'static str, f64),
+    second: (&'static str, f64),
+    /// Mean of `first - second` over the seconds both sources traded in.
+    mean_spread: Option<f64>,
+    /// Largest absolute per-second spread.
+    max_spread: Option<f64>,
+    aligned_seconds: usize,
+}
+
+impl SourceComparison {
+    /// The mean spread as a percentage of the two sources' mean price, ignoring its sign.
+    fn spread_pct(&self) -> Option<f64> {
+        let midpoint = (self.first.1 + self.second.1) / 2.0;
+        self.mean_spread.map(|spread| spread.abs() / midpoint * 100.0).filter(|pct| pct.is_finite())
+    }
+}
+
+/// What the aggregator hands back once every client has reported.
+#[derive(Debug, Default)]
+struct AggregateOutcome {
+    /// Global average per symbol.
+    global_averages: BTreeMap<String, f64>,
+    /// With `--compare-sources`, each symbol's mean spread between the sources as a
+    /// percentage of their mean price, for the symbols where they overlapped.
+    spread_pcts: BTreeMap<String, f64>,
+}
+
+/// Pearson correlation of two symbols' per-second average returns.
+#[derive(Debug, Clone)]
+struct Correlation {
+    symbols: (String, String),
+    /// `None` when there are too few aligned seconds or a series doesn't move.
+    coefficient: Option<f64>,
+    /// Seconds in which both symbols traded.
+    aligned_buckets: usize,
+}
+
+/// Settings for the aggregator.
+#[derive(Debug, Clone)]
+struct AggregatorConfig {
+    num_clients: usize,
+    /// Stop waiting for reports after this long.
+    timeout: Duration,
+    weighting: Weighting,
+    /// Correlate the returns of the two tracked symbols.
+    correlate: bool,
+    precision: Precision,
+    sink: Sink,
+    /// Write a `.sha256` sidecar next to the saved global file.
+    checksum: bool,
+    /// Symbols the clients track, so `--symbols-stats-csv` has a row even for those that
+    /// produced no data.
+    symbols: Vec<String>,
+    /// From `--symbols-stats-csv`.
+    stats_csv: Option<String>,
+    /// Write `report.html` (`--html-report`).
+    html_report: bool,
+    /// With `--aggregate-window`, the window length of `global_series.json` in seconds.
+    aggregate_window: Option<u64>,
+    /// With `--consensus`, the coefficient of variation of the client averages, in
+    /// percent, above which they are flagged.
+    consensus_max_cv: Option<f64>,
+    json_style: JsonStyle,
+    metrics: SharedMetrics,
+}
+
+/// Settings shared by every client.
+#[derive(Debug, Clone)]
+struct ClientConfig {
+    /// How the client is named in logs, file names and saved data: its `--client-labels`
+    /// label, or its id.
+    name: String,
+    /// Number of seconds to listen for.
+    duration: u64,
+    /// Log the running average every `print_every` trades (0 = never).
+    print_every: u64,
+    tz: DisplayTz,
+    /// Decimal places for printing prices.
+    precision: usize,
+    /// Symbol being tracked, e.g. `BTCUSDT`.
+    symbol: String,
+    source: Source,
+    sink: Sink,
+    /// Primary WebSocket endpoint.
+    url: String,
+    /// Backup endpoint kept connected as a warm standby, if any.
+    failover_url: Option<String>,
+    retry: RetryPolicy,
+    /// Frame and message size limits for WebSocket connections.
+    ws_config: WebSocketConfig,
+    /// Subprotocol and `Origin` sent with the WebSocket upgrade request.
+    handshake: Handshake,
+    /// With `--live-window`, the trailing window and the channel each second's average over
+    /// it is broadcast on.
+    live: Option<(Duration, broadcast::Sender<LiveUpdate>)>,
+    /// Give up after this many reconnects over the whole window.
+    max_reconnects: u32,
+    /// Reconnect when the primary connection delivers no trades for this long.
+    idle_timeout: Option<Duration>,
+    /// Give up when a connection delivers no trade for this long after connecting, before
+    /// the first trade of the window.
+    first_frame_timeout: Option<Duration>,
+    /// Warn when a trade arrives more than this many milliseconds after its event time.
+    latency_warn_ms: Option<i64>,
+    /// Warn when more than this many trades in a row have the same price.
+    stale_threshold: u64,
+    /// Leave trades from the last this-many seconds of the window out of the average.
+    trim_end: Option<Duration>,
+    /// Express prices relative to the first trade of the window.
+    normalize: bool,
+    /// Round every price to the nearest multiple of this before it is used.
+    tick_size: Option<f64>,
+    /// Multiply every parsed price by this, before anything else sees it (`--price-scale`).
+    price_scale: Option<PriceScale>,
+    stat: Stat,
+    /// Average the quoted prices with exact decimal arithmetic (`--decimal`).
+    decimal: bool,
+    /// How p50/p90/p99 are computed, if they are wanted.
+    percentile_mode: Option<PercentileMode>,
+    /// CSV file read by the `csv` source.
+    input: Option<String>,
+    /// Prices every client of the `seed` source reports (`--seed-prices`).
+    seed_prices: Vec<f64>,
+    /// With `--shard`, read trades routed by the dispatcher instead of connecting.
+    shard: Option<ShardFeed>,
+    /// Cancelled on Ctrl-C; clients then finish early with what they have.
+    shutdown: CancellationToken,
+    /// With `--fail-fast`, cancelled when any client fails to connect. `shutdown` is its
+    /// child, so clients stop too, but then discard their data instead of reporting it.
+    abort: Option<CancellationToken>,
+    /// With `--batch-size`, stream trades to the sink in batches of this many, and at least
+    /// every `batch_interval`, instead of writing them all at the end of the window.
+    batch_size: Option<usize>,
+    batch_interval: Duration,
+    /// Most streamed trades held while the sink is unreachable (`--sink-buffer`).
+    sink_buffer: usize,
+    /// With `--strict-json`, how many frames may fail to parse before the client gives up.
+    strict_json: Option<u64>,
+    /// With `--record-frames`, where every text frame received is appended.
+    recorder: Option<Recorder>,
+    /// Write a `.sha256` sidecar next to each saved data file.
+    checksum: bool,
+    /// Hand the data file to the aggregator to write instead of writing it here
+    /// (`--aggregator-saves-per-client`).
+    aggregator_saves: bool,
+    /// Time the read, parse and update stages of each frame (`--profile`).
+    profile: bool,
+    /// With `--state-file`, where the client checkpoints its running mean, and how often.
+    state_file: Option<StateFile>,
+    checkpoint_interval: Duration,
+    /// With `--resume`, the client's state from the previous run.
+    resume: Option<ClientState>,
+    /// Local time corrected to Binance server time with `--correct-clock`.
+    clock: ServerClock,
+    /// With `--dedupe-across-clients`, the trade ids every client has counted.
+    dedupe: Option<TradeFilter>,
+    bounds: PriceBounds,
+    /// With `--filter`, the expression a trade has to match to count.
+    filter: Option<TradeExpr>,
+    /// Stop the client on the first price outside `bounds` instead of dropping it.
+    strict_range: bool,
+    /// Most trades kept in memory (`--max-samples-per-client`); past it only the running
+    /// statistics are updated.
+    max_samples: Option<usize>,
+    /// With `--max-trades-total`, the cap on trades counted across every client.
+    trade_cap: Option<TradeCap>,
+    /// With `--compute-on-the-fly`, send the aggregator the cumulative average every this
+    /// many trades.
+    on_the_fly: Option<u64>,
+    /// With `--pausable`, whether collection is paused and when it was.
+    pause: Option<PauseSwitch>,
+    /// The client's `--groups` group; `None` when clients aren't grouped.
+    group: Option<String>,
+    /// Size of the `--parse-workers` pool; frames are parsed in the read loop without one.
+    parse_workers: Option<usize>,
+    /// How often to save an in-progress snapshot of the client's data, with `--stats-interval`.
+    stats_interval: Option<Duration>,
+    /// With `--checkpoint-on-signal`, where SIGUSR1 checkpoints are requested and answered.
+    checkpoint: Option<checkpoint::Checkpoints>,
+    metrics: SharedMetrics,
+}
+
+/// Lifecycle of a client's primary connection over its window.
+#[derive(Debug, Clone, Default)]
+struct ConnectionStats {
+    /// Time from the start of the window to the first successful connection.
+    time_to_connect: Option<Duration>,
+    /// Successful connections after the first one (including failovers).
+    reconnects: u32,
+    /// Total time a primary connection was open.
+    connected: Duration,
+    /// Total time between a disconnect and the next successful connection.
+    downtime: Duration,
+    disconnect_reasons: Vec<String>,
+    /// Whether the client stopped early after `--max-reconnects` reconnects.
+    reconnect_cap_reached: bool,
+    connected_since: Option<Instant>,
+    disconnected_at: Option<Instant>,
+}
+
+impl ConnectionStats {
+    /// Record a new primary connection. True if it is a reconnect.
+    fn on_connect(&mut self, start_time: Instant) -> bool {
+        let now = Instant::now();
+        let reconnected = self.time_to_connect.is_some();
+        if reconnected {
+            self.reconnects += 1;
+        } else {
+            self.time_to_connect = Some(now - start_time);
+        }
+        if let Some(disconnected_at) = self.disconnected_at.take() {
+            self.downtime += now - disconnected_at;
+        }
+        self.connected_since = Some(now);
+        reconnected
+    }
+
+    /// Record the loss of the primary connection.
+    fn on_disconnect(&mut self, reason: String) {
+        let now = Instant::now();
+        if let Some(connected_since) = self.connected_since.take() {
+            self.connected += now - connected_since;
+        }
+        self.disconnected_at = Some(now);
+        self.disconnect_reasons.push(reason);
+    }
+
+    /// Close out the connection still open at the end of the window. A gap that was
+    /// never followed by a reconnect is not counted as downtime.
+    fn finish(&mut self) {
+        if let Some(connected_since) = self.connected_since.take() {
+            self.connected += Instant::now() - connected_since;
+        }
+    }
+}
+
+/// Feed latency (local receive time minus exchange event time) over a client's window.
+#[derive(Debug, Clone, Default)]
+struct LatencyStats {
+    /// Trades with a non-negative latency.
+    samples: u64,
+    total_ms: i64,
+    max_ms: i64,
+    /// Trades above the warning threshold.
+    warnings: u64,
+    /// Trades that arrived before their event time, which can only be clock skew.
+    negative: u64,
+    /// Most negative skew seen.
+    min_skew_ms: i64,
+}
+
+impl LatencyStats {
+    /// Record one trade's skew. Returns true if it exceeds `threshold_ms`.
+    fn record(&mut self, skew_ms: i64, threshold_ms: i64) -> bool {
+        if skew_ms < 0 {
+            self.negative += 1;
+            self.min_skew_ms = self.min_skew_ms.min(skew_ms);
+            return false;
+        }
+        self.samples += 1;
+        self.total_ms += skew_ms;
+        self.max_ms = self.max_ms.max(skew_ms);
+        let high = skew_ms > threshold_ms;
+        if high {
+            self.warnings += 1;
+        }
+        high
+    }
+
+    fn average_ms(&self) -> Option<f64> {
+        (self.samples > 0).then(|| self.total_ms as f64 / self.samples as f64)
+    }
+}
+
+/// Runs of consecutive trades at exactly the same price. A frozen feed can keep repeating
+/// its last price, which looks like valid data.
+#[derive(Debug, Clone, Copy, Default)]
+struct RepeatStats {
+    last_price: Option<f64>,
+    /// Length of the current run.
+    run: u64,
+    /// Length of the longest run of the window.
+    longest: u64,
+    /// Runs that grew longer than the threshold.
+    stale_runs: u64,
+}
+
+impl RepeatStats {
+    /// Record one trade's price. Returns true once per run, when it exceeds `threshold` trades.
+    fn record(&mut self, price: f64, threshold: u64) -> bool {
+        if self.last_price == Some(price) {
+            self.run += 1;
+        } else {
+            self.last_price = Some(price);
+            self.run = 1;
+        }
+        self.longest = self.longest.max(self.run);
+        let stale = self.run == threshold + 1;
+        if stale {
+            self.stale_runs += 1;
+        }
+        stale
+    }
+}
+
+/// Plausible prices for the tracked symbol, from `--min-price` and `--max-price`. A price
+/// outside them points at a corrupted feed or the wrong symbol.
+#[derive(Debug, Clone, Copy, Default)]
+struct PriceBounds {
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl PriceBounds {
+    fn contains(&self, price: f64) -> bool {
+        self.min.is_none_or(|min| price >= min) && self.max.is_none_or(|max| price <= max)
+    }
+}
+
+/// Where a client's time went over its window, with `--profile`.
+#[derive(Debug, Clone, Copy, Default)]
+struct PipelineProfile {
+    /// Frames received.
+    frames: u64,
+    /// Waiting for the next frame from the socket (or offline source).
+    read: Duration,
+    /// Turning frames into trades.
+    parse: Duration,
+    /// Everything done with a parsed trade: dedup, statistics, batching.
+    update: Duration,
+    /// The whole window, from start to the last frame.
+    total: Duration,
+}
+
+impl PipelineProfile {
+    fn summary(&self) -> String {
+        let per_frame = |stage: Duration| stage.as_secs_f64() * 1e6 / self.frames.max(1) as f64;
+        let other = self.total.saturating_sub(self.read + self.parse + self.update);
+        format!(
+            "{} frames in {:.3}s: socket read {:.3}s, parse {:.3}s ({:.1} µs/frame), update {:.3}s ({:.1} µs/frame), other {:.3}s",
+            self.frames,
+            self.total.as_secs_f64(),
+            self.read.as_secs_f64(),
+            self.parse.as_secs_f64(),
+            per_frame(self.parse),
+            self.update.as_secs_f64(),
+            per_frame(self.update),
+            other.as_secs_f64()
+        )
+    }
+}
+
+/// Time since `started`, or nothing when the stage isn't being timed.
+fn lap(started: Option<Instant>) -> Duration {
+    started.map_or(Duration::ZERO, |started| started.elapsed())
+}
+
+/// Record the client's running mean in the `--state-file`, if there is one. A failed
+/// write is logged and the previous checkpoint is left in place.
+fn save_checkpoint(config: &ClientConfig, count: u64, mean: f64, last_trade_id: Option<u64>) {
+    let Some(state_file) = &config.state_file else {
+        return;
+    };
+    let name = &config.name;
+    let client_state = ClientState { symbol: config.symbol.clone(), source: config.source.name().to_string(), count, mean, last_trade_id };
+    if let Err(e) = state_file.checkpoint(name, client_state) {
+        eprintln!("Client {name}: Failed to checkpoint state: {e}");
+    }
+}
+
+/// An open feed connection and the URL it was opened against.
+struct Connection {
+    url: String,
+    frames: FrameStream,
+}
+
+/// Which of a client's connections a frame arrived on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Primary,
+    Standby,
+}
+
+impl ClientConfig {
+    /// Parse a text frame. The shard dispatcher passes every source's trades on as Binance
+    /// trade frames.
+    fn parse(&self, text: &str) -> Result<Frame, Box<dyn std::error::Error>> {
+        match self.shard {
+            Some(_) => process_message(text),
+            None => self.source.parse(text),
+        }
+    }
+
+    /// Endpoints to try when the client has no connection at all, primary first.
+    fn endpoints(&self) -> Vec<String> {
+        std::iter::once(self.url.clone()).chain(self.failover_url.clone()).collect()
+    }
+
+    /// The `--client-labels` label, when the client was given one.
+    fn label(&self, id: usize) -> Option<String> {
+        (self.name != id.to_string()).then(|| self.name.clone())
+    }
+
+    /// The last trade id of a resumed run; trades at or below it were already counted.
+    /// Exchange trade ids carry on across runs; offline sources number their trades from
+    /// the start again.
+    fn resumed_trade_id(&self) -> Option<u64> {
+        self.resume
+            .as_ref()
+            .filter(|_| !matches!(self.source, Source::Mock { .. } | Source::Csv | Source::Seed))
+            .and_then(|resume| resume.last_trade_id)
+    }
+}
+
+/// Client process: Fetch prices, calculate average, send to aggregator.
+/// The listening window is fixed by a deadline computed once at start, so time spent
+/// reconnecting counts against it and the client never listens past `config.duration` seconds.
+///
+/// The feed is read through a [`TradeStream`], which keeps it connected. With a failover
+/// URL configured it holds a warm standby connection open and promotes it as soon as the
+/// primary fails. Trades seen on both streams are counted once.
+async fn client_process(id: usize, tx: mpsc::Sender<ClientUpdate>, config: ClientConfig) -> Result<ClientResult, PriceError> {
+    let deadline = Instant::now() + Duration::from_secs(config.duration);
+    let feed = TradeStream::new(&format!("Client {}", config.name), id, config.clone(), deadline);
+    run_client(id, tx, config, feed).await
+}
+
+/// [`client_process`] reading a feed built by the caller, such as one with a
+/// [`TradeStream::on_trade`] hook for reacting to trades as they arrive. `feed` should read
+/// `config`'s source and end `config.duration` seconds from now.
+async fn run_client(id: usize, tx: mpsc::Sender<ClientUpdate>, config: ClientConfig, mut feed: TradeStream) -> Result<ClientResult, PriceError> {
+    let name = config.name.clone();
+    let start_time = Instant::now();
+    let print_every = config.print_every;
+    let resumed_count = config.resume.as_ref().map_or(0, |resume| resume.count);
+    let mut last_trade_id = config.resumed_trade_id();
+    // An error that ended the feed early, such as a fatal Binance error frame.
+    let mut ended: Option<PriceError> = None;
+    let mut out_of_range: u64 = 0;
+    // Trades that passed and failed `--filter`.
+    let mut filter_passed: u64 = 0;
+    let mut filter_dropped: u64 = 0;
+    // Trades discarded while `--pausable` collection was paused.
+    let mut paused_trades: u64 = 0;
+    // With --strict-range, the price that ended the window.
+    let mut out_of_range_stop: Option<f64> = None;
+    // Time spent on trades once the feed has yielded them, with `--profile`.
+    let mut update = Duration::ZERO;
+    let mut latency = LatencyStats::default();
+    let mut repeats = RepeatStats::default();
+    let mut percentiles = config.percentile_mode.map(|mode| match mode {
+        PercentileMode::Exact => Percentiles::exact(),
+        PercentileMode::TDigest => Percentiles::tdigest(),
+    });
+    // Local arrival and event time of the first trade, relating the feed's clock to ours.
+    let mut first_trade: Option<(Instant, i64)> = None;
+    let mut trades: Vec<Trade> = Vec::new();
+    // Trades counted this window, including any past `--max-samples-per-client`, and the
+    // lowest and highest of their prices.
+    let mut seen: u64 = 0;
+    let mut range: Option<(f64, f64)> = None;
+    let mut truncated = false;
+    let mut running_avg = config.resume.as_ref().map_or(0.0, |resume| resume.mean);
+    // With --compute-on-the-fly: the --stat statistic over this run's trades so far.
+    let mut partial = config.on_the_fly.map(|_| config.stat.averager(config.decimal, None, Vec::new()));
+    if let Some(resume) = &config.resume {
+        println!("Client {name}: Resuming from {} earlier trades with mean {:.prec$}.", resume.count, resume.mean, prec = config.precision);
+    }
+    let mut checkpoint_tick = time::interval_at(start_time + config.checkpoint_interval, config.checkpoint_interval);
+    // With --live-window: when each recent trade arrived, and its price.
+    let mut recent: VecDeque<(Instant, f64)> = VecDeque::new();
+    let mut live_tick = time::interval_at(start_time + LIVE_INTERVAL, LIVE_INTERVAL);
+    let mut batcher = match (&config.sink, config.batch_size) {
+        (Sink::Influx { url }, Some(batch_size)) => {
+            match influx::TradeSink::create(&config.symbol, format!("client_{name}_data.lp"), url.clone()) {
+                Ok(sink) => Some(Batcher::new(Box::new(sink), batch_size, config.sink_buffer)),
+                Err(e) => {
+                    eprintln!("Client {name}: Failed to open the trade stream, writing at the end instead: {e}");
+                    None
+                }
+            }
+        }
+        #[cfg(test)]
+        (Sink::Memory(sink), batch_size) => Some(Batcher::new(Box::new(sink.clone()), batch_size.unwrap_or(1), config.sink_buffer)),
+        _ => None,
+    };
+    let mut batch_tick = time::interval_at(start_time + config.batch_interval, config.batch_interval);
+    // Snapshots go to the data file, so only the file sink takes them.
+    let stats_interval = config.stats_interval.filter(|_| matches!(config.sink, Sink::File));
+    let mut checkpoint_requests = config.checkpoint.as_ref().map(checkpoint::Checkpoints::subscribe);
+    let mut stats_tick = time::interval_at(start_time + stats_interval.unwrap_or(LIVE_INTERVAL), stats_interval.unwrap_or(LIVE_INTERVAL));
+
+    loop {
+        let trade = tokio::select! {
+            biased;
+            _ = trade_cap::reached(config.trade_cap.as_ref()) => {
+                println!("Client {name}: Trade cap reached, finishing with the data collected so far.");
+                break;
+            }
+            _ = live_tick.tick(), if config.live.is_some() => {
+                if let Some((window, live)) = &config.live {
+                    while recent.front().is_some_and(|&(received, _)| received.elapsed() > *window) {
+                        recent.pop_front();
+                    }
+                    let prices: Vec<f64> = recent.iter().map(|&(_, price)| price).collect();
+                    let update = LiveUpdate { id, symbol: config.symbol.clone(), average: calculate_average(&prices) };
+                    let _ = live.send(update);
+                }
+                continue;
+            }
+            _ = batch_tick.tick(), if batcher.is_some() => {
+                if let Some(batcher) = &mut batcher {
+                    batcher.flush().await.unwrap_or_else(|e| eprintln!("Client {name}: Failed to write a batch of trades, keeping them for a retry: {e}"));
+                }
+                continue;
+            }
+            _ = checkpoint_tick.tick(), if config.state_file.is_some() => {
+                save_checkpoint(&config, resumed_count + seen, running_avg, last_trade_id);
+                continue;
+            }
+            _ = stats_tick.tick(), if stats_interval.is_some() => {
+                let feed_stats = feed.stats();
+                let summary = WindowSummary::running(running_avg, feed_stats.ticker, repeats, resumed_count, truncated.then_some(seen));
+                let mut stats = feed_stats.connection;
+                stats.finish();
+                save_client_snapshot(&trades, &summary, &stats, &config)
+                    .unwrap_or_else(|e| eprintln!("Client {name}: Failed to save a snapshot: {e}"));
+                continue;
+            }
+            Some(number) = checkpoint::next_request(&mut checkpoint_requests) => {
+                let feed_stats = feed.stats();
+                let summary = WindowSummary::running(running_avg, feed_stats.ticker, repeats, resumed_count, truncated.then_some(seen));
+                let mut stats = feed_stats.connection;
+                stats.finish();
+                save_client_snapshot(&trades, &summary, &stats, &config)
+                    .unwrap_or_else(|e| eprintln!("Client {name}: Failed to save a checkpoint: {e}"));
+                if let Some(checkpoints) = &config.checkpoint {
+                    checkpoints.reply(checkpoint::ClientCheckpoint {
+                        number,
+                        id,
+                        name: name.clone(),
+                        symbol: config.symbol.clone(),
+                        average: (seen + resumed_count > 0).then_some(running_avg),
+                    });
+                }
+                continue;
+            }
+            trade = feed.next() => trade,
+        };
+        let trade = match trade {
+            Some(Ok(trade)) => config.price_scale.map_or(trade, |scale| scale.apply(trade)),
+            // --fail-fast has already cancelled the other clients.
+            Some(Err(PriceError::ConnectFailed)) => return Err(PriceError::ConnectFailed),
+            Some(Err(e)) => {
+                ended = Some(e);
+                break;
+            }
+            None => break,
+        };
+        // A price outside the bounds means a corrupted feed or the wrong symbol.
+        if !config.bounds.contains(trade.price) {
+            out_of_range += 1;
+            if config.strict_range {
+                eprintln!("Client {name}: Price {} is outside --min-price/--max-price; stopping (--strict-range).", trade.price);
+                out_of_range_stop = Some(trade.price);
+                break;
+            }
+            if out_of_range == 1 {
+                eprintln!("Client {name}: Dropping price {} and any others outside --min-price/--max-price.", trade.price);
+            }
+            continue;
+        }
+        let mut trade = trade;
+        let update_started = config.profile.then(Instant::now);
+        if let Some(tick) = config.tick_size {
+            trade.price = (trade.price / tick).round() * tick;
+        }
+        // With --parse-workers, trades come back in any order.
+        last_trade_id = last_trade_id.max(Some(trade.id));
+        if config.pause.as_ref().is_some_and(PauseSwitch::is_paused) {
+            paused_trades += 1;
+            continue;
+        }
+        if let Some(filter) = &config.filter {
+            if !filter.matches(&trade) {
+                filter_dropped += 1;
+                continue;
+            }
+            filter_passed += 1;
+        }
+        // Another client reading the same trades may have counted it already.
+        if config.dedupe.as_ref().is_some_and(|filter| !filter.claim(&config.symbol, trade.id)) {
+            continue;
+        }
+        if config.trade_cap.as_ref().is_some_and(|cap| !cap.count()) {
+            continue;
+        }
+        if let Some(threshold) = config.latency_warn_ms {
+            let skew = config.clock.now_millis() - trade.event_time;
+            if latency.record(skew, threshold) {
+                eprintln!("Client {name}: High latency on trade {}: {skew} ms (threshold {threshold} ms).", trade.id);
+            }
+        }
+        if repeats.record(trade.price, config.stale_threshold) {
+            eprintln!(
+                "Client {name}: Price {} repeated more than {} times in a row; the feed may be frozen.",
+                trade.price, config.stale_threshold
+            );
+        }
+        first_trade.get_or_insert((Instant::now(), trade.event_time));
+        if let Some(percentiles) = &mut percentiles {
+            percentiles.push(trade.price);
+        }
+        if config.live.is_some() {
+            recent.push_back((Instant::now(), trade.price));
+        }
+        if let Some(batcher) = &mut batcher {
+            let dropped = batcher.dropped();
+            batcher.push(trade).await.unwrap_or_else(|e| eprintln!("Client {name}: Failed to write a batch of trades, keeping them for a retry: {e}"));
+            if dropped == 0 && batcher.dropped() > 0 {
+                eprintln!("Client {name}: --sink-buffer is full while the sink is unreachable; dropping the oldest buffered trades.");
+            }
+        }
+        if config.max_samples.is_some_and(|max| trades.len() >= max) {
+            if !truncated {
+                eprintln!(
+                    "Client {name}: Keeping no more than {} trades in memory; only the running statistics are updated from here on.",
+                    trades.len()
+                );
+                truncated = true;
+            }
+        } else {
+            trades.push(trade);
+        }
+        seen += 1;
+        range = Some(range.map_or((trade.price, trade.price), |(low, high)| (low.min(trade.price), high.max(trade.price))));
+        let count = resumed_count + seen;
+        running_avg += (trade.price - running_avg) / count as f64;
+        if print_every > 0 && count.is_multiple_of(print_every) {
+            println!("Client {name}: {count} trades, running average: {running_avg:.prec$}", prec = config.precision);
+        }
+        if let (Some(every), Some(partial)) = (config.on_the_fly, &mut partial) {
+            partial.push(&trade);
+            // `--state-file` only allows plain means, and the running mean
+            // already covers the resumed trades.
+            let average = if resumed_count > 0 { Some(running_avg) } else { partial.finalize() };
+            if let Some(average) = average.filter(|_| seen.is_multiple_of(every)) {
+                let update = PartialAverage { id, name: name.clone(), symbol: config.symbol.clone(), average, trades: count };
+                // A partial the aggregator has no room for is skipped; the next
+                // one supersedes it anyway.
+                let _ = tx.try_send(ClientUpdate::Partial(update));
+            }
+        }
+        update += lap(update_started);
+    }
+    let FeedStats { connection: mut stats, parse_errors, non_trade_frames, ticker, profile } = feed.stats();
+    stats.finish();
+    let profile = PipelineProfile { update, total: start_time.elapsed(), ..profile };
+    // The pool's workers hand trades back in whatever order they finish.
+    if config.parse_workers.is_some() {
+        trades.sort_by_key(|trade| trade.id);
+    }
+    // Whatever ended the window, streamed trades still buffered are written out.
+    if let Some(batcher) = &mut batcher {
+        batcher.finish().await.unwrap_or_else(|e| eprintln!("Client {name}: Failed to write the last batch of trades: {e}"));
+        if batcher.dropped() > 0 {
+            eprintln!("Client {name}: Dropped {} trades that didn't fit in --sink-buffer.", batcher.dropped());
+        }
+    }
+    if config.abort.as_ref().is_some_and(CancellationToken::is_cancelled) {
+        return Err(PriceError::Aborted);
+    }
+    if let Some(error) = ended {
+        return Err(error);
+    }
+    if let Some(price) = out_of_range_stop {
+        return Err(PriceError::OutOfRange(price));
+    }
+    if out_of_range > 0 {
+        eprintln!("Client {name}: Dropped {out_of_range} prices outside --min-price/--max-price.");
+    }
+    if config.filter.is_some() {
+        println!("Client {name}: {filter_passed} trades passed --filter, {filter_dropped} were filtered out.");
+    }
+    if paused_trades > 0 {
+        println!("Client {name}: Ignored {paused_trades} trades while collection was paused.");
+    }
+    if non_trade_frames > 0 {
+        println!("Client {name}: Ignored {non_trade_frames} frames that were valid JSON but not trades.");
+    }
+    let last_event_time = trades.last().map_or(0, |trade| trade.event_time);
+    // The window's end on the feed's clock, assuming it runs at the same pace as ours.
+    let mut window_end = first_trade.map(|(first_received, first_event_time)| {
+        first_event_time + (Instant::now() - first_received).as_millis() as i64
+    });
+    if let (Some(trim_end), Some(end)) = (config.trim_end, window_end) {
+        let cutoff = end - trim_end.as_millis() as i64;
+        let before = trades.len();
+        trades.retain(|trade| trade.event_time <= cutoff);
+        println!("Client {name}: Trimmed {} trades from the last {}s of the window.", before - trades.len(), trim_end.as_secs());
+        window_end = Some(cutoff);
+    }
+    // The open is the first trade left after trimming.
+    let mut normalized_open = None;
+    if config.normalize {
+        if let Some(open) = trades.first().map(|trade| trade.price) {
+            if open == 0.0 {
+                return Err(PriceError::ZeroOpen);
+            }
+            for trade in &mut trades {
+                trade.price /= open;
+            }
+            normalized_open = Some(open);
+        }
+    }
+    if repeats.stale_runs > 0 {
+        eprintln!(
+            "Client {name}: {} runs of identical prices longer than {} trades; the longest was {}.",
+            repeats.stale_runs, config.stale_threshold, repeats.longest
+        );
+    }
+    if config.latency_warn_ms.is_some() {
+        match latency.average_ms() {
+            Some(avg) => println!(
+                "Client {name}: Latency average {avg:.1} ms, max {} ms, {} trades above threshold.",
+                latency.max_ms, latency.warnings
+            ),
+            None => println!("Client {name}: No latency samples."),
+        }
+        if latency.negative > 0 {
+            eprintln!(
+                "Client {name}: {} trades arrived before their event time (down to {} ms); the local clock is behind the exchange's.",
+                latency.negative, latency.min_skew_ms
+            );
+        }
+    }
+
+    let average = if truncated {
+        // Only the running mean covers the trades past `--max-samples-per-client`. It
+        // already includes any resumed trades.
+        Some(running_avg)
+    } else {
+        let pauses = config.pause.as_ref().map(|pause| pause.intervals(config.clock.now_millis())).unwrap_or_default();
+        let mut averager = config.stat.averager(config.decimal, window_end, pauses);
+        for trade in &trades {
+            averager.push(trade);
+        }
+        averager.finalize()
+    };
+    // Fold in the mean from before the restart; `--state-file` only allows plain means,
+    // which can be combined like this.
+    let average = match &config.resume {
+        Some(resume) if resume.count > 0 && !truncated => average.map(|average| {
+            let count = resume.count + trades.len() as u64;
+            (resume.mean * resume.count as f64 + average * trades.len() as f64) / count as f64
+        }),
+        _ => average,
+    };
+    if let Ok(mut metrics) = config.metrics.lock() {
+        metrics.record_client(ClientMetrics {
+            id,
+            label: config.label(id),
+            source: config.source.name().to_string(),
+            trades: seen,
+            parse_errors,
+            out_of_range,
+            reconnects: stats.reconnects,
+            reconnect_cap_reached: stats.reconnect_cap_reached,
+            time_to_connect_secs: stats.time_to_connect.map(|elapsed| elapsed.as_secs_f64()),
+            connected_secs: stats.connected.as_secs_f64(),
+            downtime_secs: stats.downtime.as_secs_f64(),
+            latency_avg_ms: latency.average_ms(),
+            latency_max_ms: (latency.samples > 0).then_some(latency.max_ms),
+            latency_warnings: latency.warnings,
+            negative_skew_trades: latency.negative,
+            longest_identical_run: repeats.longest,
+            stale_runs: repeats.stale_runs,
+            average,
+        });
+    }
+    let Some(avg) = average else {
+        return Err(if first_trade.is_none() {
+            PriceError::NoData
+        } else if trades.is_empty() {
+            PriceError::NoDataAfterTrimming
+        } else {
+            match config.stat {
+                Stat::Mean if config.decimal => PriceError::DecimalOverflow,
+                Stat::Vwap => PriceError::ZeroVolume,
+                Stat::Geomean => PriceError::NonPositivePrice,
+                // Defined for any trade in the window.
+                Stat::Mean | Stat::Twap => PriceError::NoData,
+            }
+        });
+    };
+    save_checkpoint(&config, resumed_count + seen, avg, last_trade_id);
+    match calculate_average_decimal(&trades).filter(|_| config.decimal) {
+        Some(exact) => println!("Client {name}: Average {} price: {exact}", config.symbol),
+        None => println!("Client {name}: Average {} price: {:.prec$}", config.symbol, avg, prec = config.precision),
+    }
+    // Percentiles are tracked as trades arrive, before normalizing; scaling by the open
+    // doesn't change their order, so they can be normalized afterwards.
+    let percentiles = percentiles
+        .and_then(Percentiles::finish)
+        .map(|values| values.map(|value| value / normalized_open.unwrap_or(1.0)));
+    if let Some(values) = percentiles {
+        println!("Client {name}: {}", format_percentiles(values, config.precision));
+    }
+    let trade_count = seen as usize;
+    let mut report = ClientReport {
+        id,
+        name: name.clone(),
+        symbol: config.symbol.clone(),
+        source: config.source.name(),
+        average: avg,
+        trades: trade_count,
+        buckets: per_second_averages(&trades),
+        last_event_time,
+        extremes: if truncated { range } else { price_extremes(&trades).map(|(high, low)| (low.price, high.price)) },
+        section: None,
+        group: config.group.clone(),
+        save: None,
+    };
+    let summary = WindowSummary {
+        average: avg,
+        normalized_open,
+        percentiles,
+        ticker,
+        repeats,
+        resumed_trades: resumed_count,
+        truncated_at: truncated.then_some(seen),
+    };
+    // Save before reporting, so the aggregator's output always comes after every client's.
+    let saved = match &config.sink {
+        Sink::File if config.aggregator_saves => {
+            report.save = Some(Box::new(ClientSave {
+                path: config.sink.client_path(&name).unwrap_or_default(),
+                contents: ClientContents::Text(client_data_lines(&trades, &summary, &stats, &config)),
+            }));
+            false
+        }
+        Sink::File => save_client_data(&trades, &summary, &stats, &config)
+            .inspect_err(|e| eprintln!("Client {name}: Failed to save data: {e}"))
+            .is_ok(),
+        // The trades were already streamed; only the average is left.
+        Sink::Influx { url } if batcher.is_some() => {
+            let last_time = trades.last().map_or_else(|| Utc::now().timestamp_millis(), |trade| trade.time);
+            let line = influx::client_average_line(&config.symbol, &name, avg, last_time);
+            influx::append_lines(&[line], &format!("client_{name}_data.lp"), url.as_deref())
+                .await
+                .inspect_err(|e| eprintln!("Client {name}: Failed to write line protocol: {e}"))
+                .is_ok()
+        }
+        Sink::Influx { url } => save_client_influx(&name, &config.symbol, &trades, avg, url.as_deref())
+            .await
+            .inspect_err(|e| eprintln!("Client {name}: Failed to write line protocol: {e}"))
+            .is_ok(),
+        Sink::MessagePack | Sink::Yaml => {
+            let data = msgpack::ClientData {
+                id,
+                label: config.label(id),
+                symbol: config.symbol.clone(),
+                source: config.source.name().to_string(),
+                trades: trades.clone(),
+                average: avg,
+                method: client_method(&config),
+                normalized_open,
+                tick_size: config.tick_size,
+                price_scale: config.price_scale.map(|scale| scale.factor),
+                ticker,
+                longest_identical_run: repeats.longest,
+                truncated_at: summary.truncated_at,
+            };
+            let path = config.sink.client_path(&name).unwrap_or_default();
+            if config.aggregator_saves {
+                report.save = Some(Box::new(ClientSave { path, contents: ClientContents::Structured(Box::new(data)) }));
+                false
+            } else {
+                write_structured(&path, &data)
+                    .inspect_err(|e| eprintln!("Client {name}: Failed to save data: {e}"))
+                    .is_ok()
+            }
+        }
+        Sink::Stdout(out) => {
+            for line in client_data_lines(&trades, &summary, &stats, &config) {
+                let _ = out.send(format!("client_{name} {line}")).await;
+            }
+            false
+        }
+        Sink::Merged(_) => {
+            report.section = Some(Box::new(combined::ClientSection {
+                id,
+                label: config.label(id),
+                symbol: config.symbol.clone(),
+                source: config.source.name().to_string(),
+                method: client_method(&config),
+                average: avg,
+                normalized_open,
+                percentiles,
+                ticker,
+                longest_identical_run: repeats.longest,
+                truncated_at: summary.truncated_at,
+                reconnects: stats.reconnects,
+                downtime_secs: stats.downtime.as_secs_f64(),
+                trades: std::mem::take(&mut trades),
+            }));
+            false
+        }
+        Sink::Discard => false,
+        #[cfg(test)]
+        Sink::Memory(_) => false,
+    };
+    if let Some(path) = config.sink.client_path(&name).filter(|_| saved && config.checksum) {
+        checksum::write_sidecar(&path).unwrap_or_else(|e| eprintln!("Client {name}: Failed to write the checksum of {path}: {e}"));
+    }
+    let _ = tx.send(ClientUpdate::Final(Box::new(report))).await;
+    Ok(ClientResult {
+        name,
+        symbol: config.symbol.clone(),
+        source: config.source.name(),
+        average: avg,
+        trades: trade_count,
+        parse_errors,
+        out_of_range,
+        stats,
+        profile: config.profile.then_some(profile),
+    })
+}
+
+/// Open the client's configured price source.
+async fn open_feed(id: usize, config: &ClientConfig, deadline: Instant) -> Option<Connection> {
+    let name = &config.name;
+    if let Some(feed) = &config.shard {
+        // The dispatcher owns the connection; once it is done and the channel is drained
+        // there is nothing left to read.
+        let rx = feed.lock().await;
+        if rx.is_closed() && rx.is_empty() {
+            return None;
+        }
+        return Some(Connection { url: "shard dispatcher".to_string(), frames: shard_frames(feed.clone()) });
+    }
+    match config.source {
+        Source::Binance { .. } | Source::Coinbase => {
+            let subscription = config.source.subscription(&config.symbol);
+            connect_with_retry(name.clone(), config.endpoints(), subscription, config.retry, config.ws_config, config.handshake.clone(), deadline).await
+        }
+        Source::Mock { seed, rate } => {
+            // Each client walks from its own seed so the clients don't all report the same prices.
+            let seed = seed.wrapping_add(id as u64);
+            let count = u64::from(rate) * config.duration;
+            Some(Connection {
+                url: format!("mock (seed {seed})"),
+                frames: mock::frames(&config.symbol, seed, rate, count),
+            })
+        }
+        Source::RestPoll { interval, jitter } => Some(Connection {
+            url: config.url.clone(),
+            frames: rest_poll::frames(name, &config.symbol, interval, jitter, config.retry),
+        }),
+        Source::Csv => {
+            let path = config.input.as_deref()?;
+            match csv_file::frames(path, &config.symbol) {
+                Ok((frames, malformed)) => {
+                    if malformed > 0 {
+                        eprintln!("Client {name}: Skipped {malformed} malformed rows in {path}.");
+                    }
+                    Some(Connection { url: format!("csv {path}"), frames })
+                }
+                Err(e) => {
+                    eprintln!("Client {name}: Failed to read {path}: {e}");
+                    None
+                }
+            }
+        }
+        Source::Seed => Some(Connection {
+            url: format!("{} seeded prices", config.seed_prices.len()),
+            frames: mock::fixed(&config.symbol, &config.seed_prices),
+        }),
+    }
+}
+
+/// Frames routed to a client by the shard dispatcher. Reopening after an idle timeout
+/// keeps reading the same channel.
+fn shard_frames(feed: ShardFeed) -> FrameStream {
+    futures::stream::unfold(feed, |feed| async move {
+        let message = feed.lock().await.recv().await;
+        message.map(|message| (Ok(message), feed))
+    })
+    .boxed()
+}
+
+/// Shard dispatcher: owns the single connection used with `--shard` and routes each trade
+/// to client `trade_id % clients.len()`, so the clients average disjoint subsets of one
+/// stream instead of each listening to all of it. Trades are passed on as Binance trade
+/// frames, whatever the source.
+async fn shard_dispatcher(config: ClientConfig, clients: Vec<mpsc::Sender<Message>>) {
+    let deadline = Instant::now() + Duration::from_secs(config.duration);
+    let symbol = config.symbol.clone();
+    let routed = Arc::new(AtomicU64::new(0));
+    let counter = routed.clone();
+    // The clients record the frames they are handed, so the dispatcher records nothing.
+    let config = ClientConfig { recorder: None, ..config };
+    let mut trades = TradeStream::new("Shard dispatcher", 0, config, deadline).on_trade(move |_| {
+        counter.fetch_add(1, Ordering::Relaxed);
+    });
+    while let Some(trade) = trades.next().await {
+        match trade {
+            Ok(trade) => {
+                let client = &clients[(trade.id % clients.len() as u64) as usize];
+                let _ = client.send(Message::Text(binance_frame(&trade, &symbol))).await;
+            }
+            Err(e) => {
+                eprintln!("Shard dispatcher: {e}");
+                break;
+            }
+        }
+    }
+    println!("Shard dispatcher: Routed {} trades.", routed.load(Ordering::Relaxed));
+}
+
+/// Wait for the next frame on an optional connection, pending forever if there is none.
+async fn next_message(conn: &mut Option<Connection>) -> Option<Result<Message, WsError>> {
+    match conn {
+        Some(conn) => conn.frames.next().await,
+        None => future::pending().await,
+    }
+}
+
+/// Run `work`, holding `guard` until it finishes so the work counts as pending until then.
+async fn tracked<T>(guard: drain::PendingGuard, work: impl std::future::Future<Output = T>) -> T {
+    let output = work.await;
+    drop(guard);
+    output
+}
+
+/// Wait for an in-flight connection attempt, pending forever if there is none.
+async fn wait_for_connection(connect: &mut Option<BoxFuture<'static, Option<Connection>>>) -> Option<Connection> {
+    match connect {
+        Some(connect) => connect.await,
+        None => future::pending().await,
+    }
+}
+
+/// Aggregator process: Compute global average from clients.
+/// Results are grouped by symbol, each symbol getting its own global average. Within a
+/// symbol, when clients read from more than one source, the average spread between the
+/// first two sources is reported as well.
+/// If some client is wedged and never reports or drops its sender, the aggregator stops
+/// waiting after `timeout` and works with whatever has arrived.
+async fn aggregator_process(mut rx: mpsc::Receiver<ClientUpdate>, config: AggregatorConfig) -> AggregateOutcome {
+    let AggregatorConfig {
+        num_clients,
+        timeout,
+        weighting,
+        correlate,
+        precision,
+        sink,
+        checksum,
+        symbols: tracked,
+        stats_csv,
+        html_report,
+        aggregate_window,
+        consensus_max_cv,
+        json_style,
+        metrics,
+    } = config;
+    let mut received = Vec::with_capacity(num_clients);
+
+    let collect = async {
+        // The latest average from each client, partial or final, for the evolving global
+        // average printed with --compute-on-the-fly.
+        let mut latest: BTreeMap<usize, (String, f64)> = BTreeMap::new();
+        while received.len() < num_clients {
+            match rx.recv().await {
+                Some(ClientUpdate::Partial(partial)) => {
+                    latest.insert(partial.id, (partial.symbol.clone(), partial.average));
+                    let averages: Vec<f64> = latest
+                        .values()
+                        .filter(|(symbol, _)| *symbol == partial.symbol)
+                        .map(|&(_, average)| average)
+                        .collect();
+                    if let Some(global) = calculate_average(&averages) {
+                        println!(
+                            "Aggregator: Client {} at {} trades: {:.prec$}; running {} global average: {global:.prec$} ({} clients)",
+                            partial.name,
+                            partial.trades,
+                            partial.average,
+                            partial.symbol,
+                            averages.len(),
+                            prec = precision.of(&partial.symbol)
+                        );
+                    }
+                }
+                Some(ClientUpdate::Final(report)) => {
+                    println!(
+                        "Aggregator: Received {} average from client {} ({}): {:.prec$}",
+                        report.symbol,
+                        report.name,
+                        report.source,
+                        report.average,
+                        prec = precision.of(&report.symbol)
+                    );
+                    latest.insert(report.id, (report.symbol.clone(), report.average));
+                    received.push(*report);
+                }
+                None => break,
+            }
+        }
+    };
+    if time::timeout(timeout, collect).await.is_err() {
+        eprintln!(
+            "Aggregator: Timed out after {}s with {} of {num_clients} clients still outstanding; using the averages received so far.",
+            timeout.as_secs(),
+            num_clients - received.len()
+        );
+    }
+    // Order by client id rather than arrival so saved output is reproducible.
+    received.sort_by_key(|report| report.id);
+    for report in &mut received {
+        let Some(save) = report.save.take() else {
+            continue;
+        };
+        match save.write() {
+            Ok(()) if checksum => checksum::write_sidecar(&save.path)
+                .unwrap_or_else(|e| eprintln!("Aggregator: Failed to write the checksum of {}: {e}", save.path)),
+            Ok(()) => {}
+            Err(e) => eprintln!("Aggregator: Failed to save client {}'s data to {}: {e}", report.name, save.path),
+        }
+    }
+    let sections: Vec<combined::ClientSection> = received
+        .iter_mut()
+        .filter_map(|report| report.section.take().map(|section| *section))
+        .collect();
+
+    let mut by_symbol: HashMap<String, Vec<ClientReport>> = HashMap::new();
+    for report in received {
+        by_symbol.entry(report.symbol.clone()).or_default().push(report);
+    }
+    let mut symbols: Vec<&String> = by_symbol.keys().collect();
+    symbols.sort();
+
+    let mut summaries = Vec::with_capacity(symbols.len());
+    for &symbol in &symbols {
+        let reports = &by_symbol[symbol];
+        let precision = precision.of(symbol);
+        let averages: Vec<f64> = reports.iter().map(|report| report.average).collect();
+
+        let comparison = compare_sources(reports);
+        if let Some(comparison) = &comparison {
+            let (first, first_avg) = comparison.first;
+            let (second, second_avg) = comparison.second;
+            println!("Aggregator: {symbol} {first} average: {first_avg:.precision$}, {second} average: {second_avg:.precision$}");
+            match (comparison.mean_spread, comparison.max_spread) {
+                (Some(mean), Some(max)) => println!(
+                    "Aggregator: {symbol} spread ({first} - {second}) over {} seconds: mean {mean:.precision$}, max {max:.precision$}",
+                    comparison.aligned_seconds
+                ),
+                _ => println!("Aggregator: No overlapping seconds between {first} and {second} for {symbol}; spread undefined."),
+            }
+        }
+        let consensus = consensus_max_cv.and_then(|max_cv| {
+            let Some(consensus) = consensus(&averages) else {
+                println!("Aggregator: {symbol} consensus needs at least two client averages.");
+                return None;
+            };
+            println!(
+                "Aggregator: {symbol} client consensus: variance {:.6}, coefficient of variation {:.4}%",
+                consensus.variance, consensus.cv_pct
+            );
+            if consensus.cv_pct > max_cv {
+                let mean = averages.iter().sum::<f64>() / averages.len() as f64;
+                if let Some(outlier) = reports.iter().max_by(|a, b| (a.average - mean).abs().total_cmp(&(b.average - mean).abs())) {
+                    eprintln!(
+                        "Aggregator: {symbol} client averages disagree: coefficient of variation {:.4}% is above {max_cv}%. Client {} is furthest from the mean at {:.precision$}; it may be stuck or misconfigured.",
+                        consensus.cv_pct, outlier.name, outlier.average
+                    );
+                }
+            }
+            Some(consensus)
+        });
+
+        // With --groups, clients are first combined within their group, and the global
+        // average is the plain mean of the group averages.
+        let mut by_group: BTreeMap<&str, Vec<&ClientReport>> = BTreeMap::new();
+        for report in reports {
+            if let Some(group) = &report.group {
+                by_group.entry(group.as_str()).or_default().push(report);
+            }
+        }
+        let groups: Vec<GroupAverage> = by_group
+            .into_iter()
+            .filter_map(|(group, members)| {
+                let average = combine_reports(&format!("{symbol} group {group}"), &members, weighting)?;
+                println!("Aggregator: {symbol} group {group} average: {average:.precision$} ({} clients)", members.len());
+                Some(GroupAverage { name: group.to_string(), clients: members.len(), average })
+            })
+            .collect();
+        let global_average = if groups.is_empty() {
+            combine_reports(symbol, &reports.iter().collect::<Vec<_>>(), weighting)
+        } else {
+            calculate_average(&groups.iter().map(|group| group.average).collect::<Vec<_>>())
+        };
+        if let Some(global_average) = global_average {
+            println!("Aggregator: Global average {symbol} price: {:.precision$}", global_average);
+            summaries.push(SymbolSummary {
+                symbol: symbol.clone(),
+                precision,
+                averages,
+                global_average,
+                method: if groups.is_empty() {
+                    global_method(weighting)
+                } else {
+                    format!("mean of group averages, each a {}", global_method(weighting))
+                },
+                comparison,
+                groups,
+                consensus,
+            });
+        }
+    }
+
+    let correlation = match symbols.as_slice() {
+        [first, second] if correlate => {
+            let correlation = correlate_symbols(first, &by_symbol[*first], second, &by_symbol[*second]);
+            println!("Aggregator: {}", correlation_line(&correlation));
+            Some(correlation)
+        }
+        _ => None,
+    };
+
+    if let Some(path) = &stats_csv {
+        let rows = symbol_rows(&tracked, &by_symbol, &summaries);
+        match symbol_stats::write(path, &rows) {
+            Ok(()) => println!("Aggregator: Wrote statistics for {} symbols to {path}.", rows.len()),
+            Err(e) => eprintln!("Aggregator: Failed to write {path}: {e}"),
+        }
+    }
+    if let Some(window_secs) = aggregate_window {
+        let series = series::GlobalSeries {
+            window_secs,
+            symbols: symbols
+                .iter()
+                .map(|&symbol| {
+                    let buckets: Vec<&[(i64, f64)]> = by_symbol[symbol].iter().map(|report| report.buckets.as_slice()).collect();
+                    series::SymbolSeries { symbol: symbol.clone(), windows: series::windows(&buckets, window_secs) }
+                })
+                .collect(),
+        };
+        match series::write(series::SERIES_PATH, &series, json_style) {
+            Ok(()) => println!("Aggregator: Wrote {window_secs}s rolling global averages to {}.", series::SERIES_PATH),
+            Err(e) => eprintln!("Aggregator: Failed to write {}: {e}", series::SERIES_PATH),
+        }
+    }
+    if summaries.is_empty() {
+        eprintln!("Aggregator: No averages received.");
+        return AggregateOutcome::default();
+    }
+    if let Ok(mut metrics) = metrics.lock() {
+        for summary in &summaries {
+            metrics.global_averages.insert(summary.symbol.clone(), summary.global_average);
+        }
+        if let [summary] = summaries.as_slice() {
+            metrics.global_average = Some(summary.global_average);
+        }
+    }
+    if html_report {
+        let html = render_report(&by_symbol, &summaries, &precision);
+        match html_report::write(html_report::REPORT_PATH, &html) {
+            Ok(()) => println!("Aggregator: Wrote {}.", html_report::REPORT_PATH),
+            Err(e) => eprintln!("Aggregator: Failed to write {}: {e}", html_report::REPORT_PATH),
+        }
+    }
+    let saved = match &sink {
+        Sink::File => save_global_data(&summaries, correlation.as_ref())
+            .inspect_err(|e| eprintln!("Aggregator: Failed to save global data: {e}"))
+            .is_ok(),
+        Sink::Influx { url } => {
+            let now = Utc::now().timestamp_millis();
+            let lines: Vec<String> = summaries
+                .iter()
+                .map(|summary| influx::global_average_line(&summary.symbol, summary.global_average, now))
+                .collect();
+            influx::write_lines(&lines, "global_data.lp", url.as_deref())
+                .await
+                .inspect_err(|e| eprintln!("Aggregator: Failed to write line protocol: {e}"))
+                .is_ok()
+        }
+        Sink::MessagePack | Sink::Yaml => {
+            let data: Vec<msgpack::GlobalData> = summaries
+                .iter()
+                .map(|summary| msgpack::GlobalData {
+                    symbol: summary.symbol.clone(),
+                    client_averages: summary.averages.clone(),
+                    global_average: summary.global_average,
+                    method: summary.method.clone(),
+                    client_variance: summary.consensus.map(|consensus| consensus.variance),
+                    client_cv_pct: summary.consensus.map(|consensus| consensus.cv_pct),
+                })
+                .collect();
+            write_structured(sink.global_path().unwrap_or_default(), &data)
+                .inspect_err(|e| eprintln!("Aggregator: Failed to save global data: {e}"))
+                .is_ok()
+        }
+        Sink::Stdout(out) => {
+            if let Some(correlation) = &correlation {
+                let _ = out.send(format!("global {}", correlation_line(correlation))).await;
+            }
+            for summary in &summaries {
+                let prefix = if summaries.len() == 1 { "global".to_string() } else { format!("global_{}", summary.symbol) };
+                for line in global_data_lines(summary) {
+                    let _ = out.send(format!("{prefix} {line}")).await;
+                }
+            }
+            false
+        }
+        Sink::Merged(style) => {
+            let data = combined::CombinedData {
+                clients: sections,
+                global: summaries
+                    .iter()
+                    .map(|summary| combined::GlobalSection {
+                        symbol: summary.symbol.clone(),
+                        method: summary.method.clone(),
+                        client_averages: summary.averages.clone(),
+                        global_average: summary.global_average,
+                        client_variance: summary.consensus.map(|consensus| consensus.variance),
+                        client_cv_pct: summary.consensus.map(|consensus| consensus.cv_pct),
+                    })
+                    .collect(),
+                correlation: correlation.map(|correlation| combined::CorrelationSection {
+                    symbols: correlation.symbols,
+                    coefficient: correlation.coefficient,
+                    aligned_seconds: correlation.aligned_buckets,
+                }),
+            };
+            combined::write(COMBINED_PATH, &data, *style)
+                .inspect_err(|e| eprintln!("Aggregator: Failed to save combined data: {e}"))
+                .is_ok()
+        }
+        Sink::Discard => false,
+        #[cfg(test)]
+        Sink::Memory(_) => false,
+    };
+    if let Some(path) = sink.global_path().filter(|_| saved && checksum) {
+        checksum::write_sidecar(path).unwrap_or_else(|e| eprintln!("Aggregator: Failed to write the checksum of {path}: {e}"));
+    }
+    AggregateOutcome {
+        spread_pcts: summaries
+            .iter()
+            .filter_map(|summary| Some((summary.symbol.clone(), summary.comparison.as_ref()?.spread_pct()?)))
+            .collect(),
+        global_averages: summaries.into_iter().map(|summary| (summary.symbol, summary.global_average)).collect(),
+    }
+}
+
+/// The `--html-report` report: every client's stats, and each symbol's global average
+/// with a chart of its per-second average across clients.
+fn render_report(by_symbol: &HashMap<String, Vec<ClientReport>>, summaries: &[SymbolSummary], precision: &Precision) -> String {
+    let mut reports: Vec<&ClientReport> = by_symbol.values().flatten().collect();
+    reports.sort_by_key(|report| report.id);
+    let clients: Vec<html_report::ClientRow> = reports
+        .iter()
+        .map(|report| html_report::ClientRow {
+            name: report.name.clone(),
+            symbol: report.symbol.clone(),
+            source: report.source.to_string(),
+            average: report.average,
+            trades: report.trades,
+            extremes: report.extremes,
+            precision: precision.of(&report.symbol),
+        })
+        .collect();
+    let symbols: Vec<html_report::SymbolSection> = summaries
+        .iter()
+        .map(|summary| {
+            let reports: Vec<&ClientReport> = by_symbol[&summary.symbol].iter().collect();
+            html_report::SymbolSection {
+                symbol: summary.symbol.clone(),
+                method: summary.method.clone(),
+                global_average: summary.global_average,
+                points: merged_buckets(&reports).into_iter().collect(),
+                precision: summary.precision,
+            }
+        })
+        .collect();
+    html_report::render(Utc::now(), &clients, &symbols)
+}
+
+/// One `--symbols-stats-csv` row per tracked symbol, in order, followed by any symbol
+/// that reported without being tracked.
+fn symbol_rows(
+    tracked: &[String],
+    by_symbol: &HashMap<String, Vec<ClientReport>>,
+    summaries: &[SymbolSummary],
+) -> Vec<symbol_stats::SymbolRow> {
+    let mut symbols: Vec<&String> = tracked.iter().collect();
+    let mut untracked: Vec<&String> = by_symbol.keys().filter(|symbol| !tracked.contains(symbol)).collect();
+    untracked.sort();
+    symbols.extend(untracked);
+    symbols
+        .into_iter()
+        .map(|symbol| {
+            let reports = by_symbol.get(symbol).map_or(&[][..], Vec::as_slice);
+            let average = summaries.iter().find(|summary| &summary.symbol == symbol).map(|summary| summary.global_average);
+            let extremes = reports
+                .iter()
+                .filter_map(|report| report.extremes)
+                .reduce(|(low, high), (other_low, other_high)| (low.min(other_low), high.max(other_high)));
+            let trades = reports.iter().map(|report| report.trades).sum();
+            symbol_stats::SymbolRow::new(symbol, reports.len(), trades, average, extremes)
+        })
+        .collect()
+}
+
+/// Follow the clients' trailing-window averages until every client has finished, and every
+/// second call `on_tick` with each symbol's live global average and the number of clients
+/// it combines. Each consumer follows its own receiver, so every one of them sees every
+/// update; one that falls behind skips ahead to the newest updates.
+async fn follow_live_averages(mut rx: broadcast::Receiver<LiveUpdate>, mut on_tick: impl FnMut(&str, f64, usize)) {
+    let mut latest: BTreeMap<usize, LiveUpdate> = BTreeMap::new();
+    let mut tick = time::interval_at(Instant::now() + LIVE_INTERVAL, LIVE_INTERVAL);
+    loop {
+        tokio::select! {
+            update = rx.recv() => match update {
+                Ok(update) => {
+                    latest.insert(update.id, update);
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = tick.tick() => {
+                let mut by_symbol: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+                for update in latest.values() {
+                    if let Some(average) = update.average {
+                        by_symbol.entry(update.symbol.as_str()).or_default().push(average);
+                    }
+                }
+                for (symbol, averages) in by_symbol {
+                    if let Some(global) = calculate_average(&averages) {
+                        on_tick(symbol, global, averages.len());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Live aggregator: print the live global average per symbol every second until every
+/// client has finished.
+async fn live_aggregator_process(rx: broadcast::Receiver<LiveUpdate>, window: Duration, precision: Precision) {
+    follow_live_averages(rx, |symbol, global, clients| {
+        println!(
+            "Live: {symbol} average over the last {}s: {global:.prec$} ({clients} clients)",
+            window.as_secs(),
+            prec = precision.of(symbol)
+        );
+    })
+    .await
+}
+
+/// Connect to WebSocket server, retrying with `retry`'s backoff until `deadline`.
+/// Attempts rotate through `urls` in order. Only transient errors are retried; a URL that
+/// fails with a fatal error is dropped from the rotation. Returns `None` if no connection
+/// could be established before the deadline or every URL failed fatally.
+async fn connect_with_retry(
+    name: String,
+    mut urls: Vec<String>,
+    subscription: Option<String>,
+    retry: RetryPolicy,
+    ws_config: WebSocketConfig,
+    handshake: Handshake,
+    deadline: Instant,
+) -> Option<Connection> {
+    let mut backoff = retry.initial_backoff;
+    let mut attempt = 0;
+    while !urls.is_empty() {
+        let url = urls[attempt % urls.len()].clone();
+        match time::timeout_at(deadline, connect_to_websocket(&url, subscription.as_deref(), ws_config, &handshake)).await {
+            Ok(Ok(ws)) => return Some(Connection { url, frames: ws.boxed() }),
+            Ok(Err(e)) if is_transient(&e) => eprintln!("Client {name}: Failed to connect to {url}: {e}"),
+            Ok(Err(e)) => {
+                eprintln!("Client {name}: Failed to connect to {url}: {e}. This error is not transient; not retrying it.");
+                urls.retain(|other| *other != url);
+                continue;
+            }
+            Err(_) => return None,
+        }
+        attempt += 1;
+        let delay = retry.delay(backoff);
+        if Instant::now() + delay >= deadline {
+            return None;
+        }
+        eprintln!("Client {name}: Retrying in {:.1}s", delay.as_secs_f64());
+        time::sleep(delay).await;
+        backoff = retry.next_backoff(backoff);
+    }
+    None
+}
+
+/// Whether a connection error is worth retrying. Network hiccups (resets, refusals,
+/// timeouts), server-side HTTP errors and rate limiting are transient; a bad URL, a host
+/// name that doesn't resolve, TLS failures and other HTTP 4xx responses mean the
+/// configuration is wrong and retrying won't help.
+fn is_transient(error: &WsError) -> bool {
+    match error {
+        // Only errors from the network itself. Resolver failures have no kind of their
+        // own (they are `Uncategorized`), so like other unexpected I/O errors they are fatal.
+        WsError::Io(e) => matches!(
+            e.kind(),
+            io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::UnexpectedEof
+                | io::ErrorKind::Interrupted
+                | io::ErrorKind::HostUnreachable
+                | io::ErrorKind::NetworkUnreachable
+                | io::ErrorKind::NetworkDown
+        ),
+        WsError::Http(response) => {
+            let status = response.status();
+            status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::REQUEST_TIMEOUT
+        }
+        WsError::ConnectionClosed | WsError::AlreadyClosed | WsError::Protocol(_) => true,
+        // Bad URLs, malformed requests, TLS failures and anything unexpected.
+        _ => false,
+    }
+}
+
+/// Connect to WebSocket server, sending `subscription` first if the feed needs one.
+async fn connect_to_websocket(url: &str, subscription: Option<&str>, ws_config: WebSocketConfig, handshake: &Handshake) -> Result<WsStream, WsError> {
+    let mut request = url.into_client_request()?;
+    handshake.apply(&mut request);
+    let (mut ws_stream, _) = connect_async_with_config(request, Some(ws_config), false).await?;
+    if let Some(subscription) = subscription {
+        ws_stream.send(Message::Text(subscription.to_string())).await?;
+    }
+    Ok(ws_stream)
+}
+
+/// The fields of a Binance trade frame that [`process_message`] needs, and the event type
+/// that tells other frames apart. Everything else in the frame is skipped while parsing
+/// rather than built into a [`Value`]. Missing and `null` fields are `None`.
+#[derive(Debug, Deserialize)]
+struct TradeFrame<'a> {
+    /// Borrowed straight from the frame unless they contain escapes, which these don't.
+    #[serde(rename = "e", borrow)]
+    event: Option<std::borrow::Cow<'a, str>>,
+    #[serde(rename = "p", borrow)]
+    price: Option<std::borrow::Cow<'a, str>>,
+    #[serde(rename = "q", borrow)]
+    qty: Option<std::borrow::Cow<'a, str>>,
+    #[serde(rename = "T")]
+    time: Option<i64>,
+    #[serde(rename = "t")]
+    id: Option<u64>,
+    #[serde(rename = "E")]
+    event_time: Option<i64>,
+}
+
+/// Process WebSocket message to extract the trade price and time. Only a frame that
+/// isn't JSON, or says it is a trade and isn't one, is an error.
+fn process_message(text: &str) -> Result<Frame, Box<dyn std::error::Error>> {
+    // A frame the struct can't hold, e.g. one with a field of another type or that isn't
+    // an object, is parsed again into a `Value` and judged the same way.
+    let Ok(frame) = serde_json::from_str::<TradeFrame>(text) else {
+        return frame_from_value(&serde_json::from_str(text)?);
+    };
+    match frame {
+        TradeFrame { price: Some(price), qty: Some(qty), time: Some(time), id: Some(id), event_time: Some(event_time), .. } => {
+            binance_trade(&price, &qty, id, time, event_time)
+        }
+        frame if frame.event.as_deref() == Some("trade") => Err("Trade frame without all of p, q, T, t and E".into()),
+        _ => Ok(Frame::Other),
+    }
+}
+
+/// [`process_message`] for a frame already parsed into a [`Value`].
+fn frame_from_value(json: &Value) -> Result<Frame, Box<dyn std::error::Error>> {
+    let text_field = |key| json.get(key).and_then(Value::as_str);
+    let number_field = |key| json.get(key).and_then(Value::as_i64);
+    match (text_field("p"), text_field("q"), number_field("T"), json.get("t").and_then(Value::as_u64), number_field("E")) {
+        (Some(price), Some(qty), Some(time), Some(id), Some(event_time)) => binance_trade(price, qty, id, time, event_time),
+        _ if text_field("e") == Some("trade") => Err("Trade frame without all of p, q, T, t and E".into()),
+        _ => Ok(Frame::Other),
+    }
+}
+
+fn binance_trade(price: &str, qty: &str, id: u64, time: i64, event_time: i64) -> Result<Frame, Box<dyn std::error::Error>> {
+    Ok(Frame::Trade(Trade { id, price: price.parse()?, exact_price: price.parse()?, qty: qty.parse()?, time, event_time }))
+}
+
+/// Render `trade` as a Binance trade frame, the inverse of [`process_message`].
+fn binance_frame(trade: &Trade, symbol: &str) -> String {
+    format!(
+        r#"{{"e":"trade","E":{},"s":"{symbol}","t":{},"p":"{}","q":"{}","T":{},"m":false,"M":true}}"#,
+        trade.event_time, trade.id, trade.exact_price, trade.qty, trade.time
+    )
+}
+
+/// An `{"error":{"code":..,"msg":..}}` frame, which Binance sends instead of data when it
+/// rejects a request, e.g. a subscription to a stream that doesn't exist.
+#[derive(Debug, Clone, PartialEq)]
+struct BinanceError {
+    code: i64,
+    msg: String,
+}
+
+impl BinanceError {
+    /// Codes 1 (invalid value type) and 2 (invalid request) mean the stream itself was
+    /// rejected, so waiting longer won't produce any trades.
+    fn is_fatal(&self) -> bool {
+        matches!(self.code, 1 | 2)
+    }
+}
+
+impl std::fmt::Display for BinanceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "error {}: {}", self.code, self.msg)
+    }
+}
+
+/// The error carried by a Binance error frame, if `text` is one.
+fn binance_error(text: &str) -> Option<BinanceError> {
+    let json: Value = serde_json::from_str(text).ok()?;
+    let error = json.get("error")?;
+    Some(BinanceError {
+        code: error.get("code").and_then(Value::as_i64)?,
+        msg: error.get("msg").and_then(Value::as_str).unwrap_or_default().to_string(),
+    })
+}
+
+/// Combine `reports` into one average with the configured weighting. `label` names them
+/// in the log, e.g. `BTCUSDT` or `BTCUSDT group eu`.
+fn combine_reports(label: &str, reports: &[&ClientReport], weighting: Weighting) -> Option<f64> {
+    let averages: Vec<f64> = reports.iter().map(|report| report.average).collect();
+    match weighting {
+        Weighting::Equal => calculate_average(&averages),
+        // Sharded clients see disjoint trades, so only a trade-weighted average
+        // reconstructs the average of the whole stream.
+        Weighting::Trades => trade_weighted_average(reports),
+        Weighting::Recency { decay, half_life } => {
+            let weights = recency_weights(reports, decay, half_life);
+            let parts: Vec<String> = reports
+                .iter()
+                .zip(&weights)
+                .map(|(report, weight)| format!("client {} {weight:.3}", report.name))
+                .collect();
+            println!("Aggregator: {label} recency weights: {}", parts.join(", "));
+            weighted_average(&averages, &weights)
+        }
+    }
+}
+
+/// Average of the client averages, weighted by how many trades each one covers.
+fn trade_weighted_average(reports: &[&ClientReport]) -> Option<f64> {
+    let trades: usize = reports.iter().map(|report| report.trades).sum();
+    if trades == 0 {
+        return None;
+    }
+    let total: f64 = reports.iter().map(|report| report.average * report.trades as f64).sum();
+    Some(total / trades as f64)
+}
+
+/// Each client's weight by the age of its last trade relative to the freshest client's,
+/// so the freshest always weighs 1 and clocks only need to agree between clients.
+fn recency_weights(reports: &[&ClientReport], decay: Decay, half_life: Duration) -> Vec<f64> {
+    let newest = reports.iter().map(|report| report.last_event_time).max().unwrap_or_default();
+    reports
+        .iter()
+        .map(|report| {
+            let age = Duration::from_millis((newest - report.last_event_time).max(0) as u64);
+            decay.weight(age, half_life)
+        })
+        .collect()
+}
+
+/// Average of `values` weighted by `weights`. `None` when every weight is zero.
+fn weighted_average(values: &[f64], weights: &[f64]) -> Option<f64> {
+    let total_weight: f64 = weights.iter().sum();
+    if total_weight <= 0.0 {
+        return None;
+    }
+    let total: f64 = values.iter().zip(weights).map(|(value, weight)| value * weight).sum();
+    Some(total / total_weight)
+}
+
+/// Calculate the average of a vector of numbers.
+fn calculate_average(prices: &[f64]) -> Option<f64> {
+    if prices.is_empty() {
+        None
+    } else {
+        Some(prices.iter().sum::<f64>() / prices.len() as f64)
+    }
+}
+
+/// Exact decimal average of the quoted prices. Division rounds to the 28 significant
+/// digits a `Decimal` holds; `None` if there are no trades or the sum overflows.
+fn calculate_average_decimal(trades: &[Trade]) -> Option<Decimal> {
+    if trades.is_empty() {
+        return None;
+    }
+    let sum = trades.iter().try_fold(Decimal::ZERO, |sum, trade| sum.checked_add(trade.exact_price))?;
+    sum.checked_div(Decimal::from(trades.len()))
+}
+
+/// Average the trades falling in each second of trade time.
+fn per_second_averages(trades: &[Trade]) -> Vec<(i64, f64)> {
+    let mut buckets: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+    for trade in trades {
+        let bucket = buckets.entry(trade.time.div_euclid(1000)).or_default();
+        bucket.0 += trade.price;
+        bucket.1 += 1;
+    }
+    buckets.into_iter().map(|(second, (sum, count))| (second, sum / count as f64)).collect()
+}
+
+/// Per second, the average of the given clients' bucket averages.
+fn merged_buckets(clients: &[&ClientReport]) -> BTreeMap<i64, f64> {
+    let mut seconds: BTreeMap<i64, Vec<f64>> = BTreeMap::new();
+    for report in clients {
+        for &(second, avg) in &report.buckets {
+            seconds.entry(second).or_default().push(avg);
+        }
+    }
+    seconds
+        .into_iter()
+        .filter_map(|(second, values)| Some((second, calculate_average(&values)?)))
+        .collect()
+}
+
+/// Pearson correlation coefficient, or `None` with fewer than two points or no variance.
+fn pearson(xs: &[f64], ys: &[f64]) -> Option<f64> {
+    if xs.len() < 2 || xs.len() != ys.len() {
+        return None;
+    }
+    let (mean_x, mean_y) = (calculate_average(xs)?, calculate_average(ys)?);
+    let (mut covariance, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (x, y) in xs.iter().zip(ys) {
+        covariance += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x).powi(2);
+        var_y += (y - mean_y).powi(2);
+    }
+    if var_x == 0.0 || var_y == 0.0 {
+        return None;
+    }
+    Some(covariance / (var_x * var_y).sqrt())
+}
+
+/// Correlate two symbols' per-second average returns, over the seconds both traded in.
+fn correlate_symbols(first: &str, first_reports: &[ClientReport], second: &str, second_reports: &[ClientReport]) -> Correlation {
+    let first_buckets = merged_buckets(&first_reports.iter().collect::<Vec<_>>());
+    let second_buckets = merged_buckets(&second_reports.iter().collect::<Vec<_>>());
+    let aligned: Vec<(f64, f64)> = first_buckets
+        .iter()
+        .filter_map(|(second, a)| second_buckets.get(second).map(|b| (*a, *b)))
+        .collect();
+    // Returns between consecutive aligned seconds.
+    let (first_returns, second_returns): (Vec<f64>, Vec<f64>) = aligned
+        .windows(2)
+        .map(|pair| (pair[1].0 / pair[0].0 - 1.0, pair[1].1 / pair[0].1 - 1.0))
+        .unzip();
+    Correlation {
+        symbols: (first.to_string(), second.to_string()),
+        coefficient: pearson(&first_returns, &second_returns),
+        aligned_buckets: aligned.len(),
+    }
+}
+
+fn correlation_line(correlation: &Correlation) -> String {
+    let (first, second) = &correlation.symbols;
+    match correlation.coefficient {
+        Some(coefficient) => format!(
+            "Correlation ({first}, {second}): {coefficient:.4} over {} aligned seconds",
+            correlation.aligned_buckets
+        ),
+        None => format!(
+            "Correlation ({first}, {second}): undefined ({} aligned seconds)",
+            correlation.aligned_buckets
+        ),
+    }
+}
+
+/// Compare the first two sources present in `reports` (in client id order).
+/// Returns `None` when every client read from the same source.
+fn compare_sources(reports: &[ClientReport]) -> Option<SourceComparison> {
+    let mut sources: Vec<&'static str> = Vec::new();
+    for report in reports {
+        if !sources.contains(&report.source) {
+            sources.push(report.source);
+        }
+    }
+    let (first, second) = match sources.as_slice() {
+        [first, second, ..] => (*first, *second),
+        _ => return None,
+    };
+
+    // Per source: the average of its clients' averages, and per second the average
+    // of its clients' bucket averages.
+    let summarize = |source: &'static str| {
+        let clients: Vec<&ClientReport> = reports.iter().filter(|report| report.source == source).collect();
+        let averages: Vec<f64> = clients.iter().map(|report| report.average).collect();
+        (calculate_average(&averages).unwrap_or(f64::NAN), merged_buckets(&clients))
+    };
+    let (first_avg, first_seconds) = summarize(first);
+    let (second_avg, second_seconds) = summarize(second);
+
+    let spreads: Vec<f64> = first_seconds
+        .iter()
+        .filter_map(|(second, a)| second_seconds.get(second).map(|b| a - b))
+        .collect();
+    Some(SourceComparison {
+        first: (first, first_avg),
+        second: (second, second_avg),
+        mean_spread: calculate_average(&spreads),
+        max_spread: spreads.iter().map(|spread| spread.abs()).reduce(f64::max),
+        aligned_seconds: spreads.len(),
+    })
+}
+
+/// Find the trades with the highest and lowest prices.
+fn price_extremes(trades: &[Trade]) -> Option<(Trade, Trade)> {
+    let first = *trades.first()?;
+    Some(trades.iter().fold((first, first), |(high, low), &trade| {
+        (
+            if trade.price > high.price { trade } else { high },
+            if trade.price < low.price { trade } else { low },
+        )
+    }))
+}
+
+/// Render an epoch-millisecond timestamp in the given timezone.
+fn format_timestamp(millis: i64, tz: DisplayTz) -> String {
+    const FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f %Z";
+    let Some(utc) = DateTime::<Utc>::from_timestamp_millis(millis) else {
+        return millis.to_string();
+    };
+    match tz {
+        DisplayTz::Local => utc.with_timezone(&Local).format(FORMAT).to_string(),
+        DisplayTz::Named(tz) => utc.with_timezone(&tz).format(FORMAT).to_string(),
+    }
+}
+
+/// Parse a `--tz` value: `local` or an IANA timezone name.
+fn parse_timezone(name: &str) -> Result<DisplayTz, String> {
+    if name.eq_ignore_ascii_case("local") {
+        return Ok(DisplayTz::Local);
+    }
+    name.parse::<Tz>().map(DisplayTz::Named).map_err(|_| {
+        let expected: Vec<&str> = chrono_tz::TZ_VARIANTS.iter().map(|tz| tz.name()).collect();
+        format!("Invalid timezone: {name}. Expected `local` or one of: {}", expected.join(", "))
+    })
+}
+
+/// Name each client by its `--client-labels` entry, falling back to its id when it has
+/// none. Labels end up in file names, so anything outside `[A-Za-z0-9._-]` becomes `_`.
+fn client_names(num_clients: usize, labels: &[String]) -> Result<Vec<String>, String> {
+    let mut names: Vec<String> = Vec::with_capacity(num_clients);
+    for id in 1..=num_clients {
+        let label: String = labels
+            .get(id - 1)
+            .map(|label| label.trim())
+            .unwrap_or_default()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') { c } else { '_' })
+            .collect();
+        let name = if label.is_empty() { id.to_string() } else { label };
+        if names.contains(&name) {
+            return Err(format!("--client-labels: two clients would both be named `{name}`."));
+        }
+        names.push(name);
+    }
+    if labels.len() > num_clients {
+        eprintln!("Warning: {} client labels but only {num_clients} clients; the extra labels are ignored.", labels.len());
+    }
+    Ok(names)
+}
+
+/// `symbol` in Binance's form for `--normalize-symbols`: only letters and digits, so
+/// `btc/usdt`, `BTC-USDT` and `BTCUSDT` all name the same pair. Upper case like every
+/// symbol kept internally; stream URLs lowercase it.
+fn normalize_symbol(symbol: &str) -> String {
+    symbol.chars().filter(char::is_ascii_alphanumeric).collect::<String>().to_uppercase()
+}
+
+/// Each client's group from `--groups`, e.g. `eu:1,2 us:3,4,5`: space-separated groups,
+/// each a name and a comma-separated list of client ids or labels. Clients left out are
+/// in the `default` group.
+fn client_groups(spec: &str, names: &[String]) -> Result<Vec<String>, String> {
+    let mut groups: Vec<Option<String>> = vec![None; names.len()];
+    for entry in spec.split_whitespace() {
+        let Some((group, members)) = entry.split_once(':').filter(|(group, _)| !group.is_empty()) else {
+            return Err(format!("--groups: `{entry}` is not of the form NAME:CLIENT,CLIENT,..."));
+        };
+        for member in members.split(',').map(str::trim).filter(|member| !member.is_empty()) {
+            let Some(index) = names.iter().position(|name| name == member).or_else(|| {
+                member.parse::<usize>().ok().filter(|id| (1..=names.len()).contains(id)).map(|id| id - 1)
+            }) else {
+                return Err(format!("--groups: there is no client `{member}`."));
+            };
+            if let Some(other) = &groups[index] {
+                return Err(format!("--groups: client `{}` is in both `{other}` and `{group}`.", names[index]));
+            }
+            groups[index] = Some(group.to_string());
+        }
+    }
+    Ok(groups.into_iter().map(|group| group.unwrap_or_else(|| "default".to_string())).collect())
+}
+
+/// Each client's listening window in seconds: its `--durations` entry, or `times` when
+/// the entry is missing or empty.
+fn client_durations(num_clients: usize, times: u64, overrides: &[String]) -> Result<Vec<u64>, String> {
+    if overrides.len() > num_clients {
+        eprintln!("Warning: {} durations but only {num_clients} clients; the extra durations are ignored.", overrides.len());
+    }
+    (0..num_clients)
+        .map(|i| match overrides.get(i).map(|duration| duration.trim()).filter(|duration| !duration.is_empty()) {
+            None => Ok(times),
+            Some(duration) => match duration.parse::<u64>() {
+                Ok(secs) if secs > 0 => Ok(secs),
+                _ => Err(format!("--durations: `{duration}` for client {} is not a positive number of seconds.", i + 1)),
+            },
+        })
+        .collect()
+}
+
+/// Render percentiles as `Percentiles: p50=.., p90=.., p99=..`.
+fn format_percentiles(values: [f64; 3], precision: usize) -> String {
+    let parts: Vec<String> = QUANTILES
+        .iter()
+        .zip(values)
+        .map(|((name, _), value)| format!("{name}={value:.precision$}"))
+        .collect();
+    format!("Percentiles: {}", parts.join(", "))
+}
+
+/// Render a client's data (price points, average, high and low, connection lifecycle)
+/// as text lines.
+fn client_data_lines(trades: &[Trade], summary: &WindowSummary, stats: &ConnectionStats, config: &ClientConfig) -> Vec<String> {
+    let WindowSummary { average, normalized_open, percentiles, ticker, repeats, resumed_trades, truncated_at } = *summary;
+    let (tz, precision) = (config.tz, config.precision);
+    let prices: Vec<f64> = trades.iter().map(|trade| trade.price).collect();
+    let mut lines = Vec::new();
+    lines.push(format!("Method: {}", client_method(config)));
+    if let Some(scale) = config.price_scale {
+        lines.push(format!("Price Scale: {}", scale.factor));
+    }
+    if let Some(tick) = config.tick_size {
+        lines.push(format!("Tick Size: {tick}"));
+    }
+    if let Some(open) = normalized_open {
+        lines.push(format!("Normalized: prices are relative to the open of {open}"));
+    }
+    lines.push(format!("Prices: {:?}", prices));
+    if let Some(total) = truncated_at {
+        lines.push(format!("Truncated: prices kept for the first {} of {total} trades; the average covers all of them", prices.len()));
+    }
+    match calculate_average_decimal(trades).filter(|_| config.decimal) {
+        Some(exact) => lines.push(format!("Average: {exact} (exact decimal)")),
+        None => lines.push(format!("Average: {:.precision$}", average)),
+    }
+    if resumed_trades > 0 {
+        lines.push(format!("Resumed: the average includes {resumed_trades} trades from before a restart"));
+    }
+    if let Some(values) = percentiles {
+        lines.push(format_percentiles(values, precision));
+    }
+    if let Some((high, low)) = price_extremes(trades) {
+        lines.push(format!("High: {:.precision$} at {}", high.price, format_timestamp(high.time, tz)));
+        lines.push(format!("Low: {:.precision$} at {}", low.price, format_timestamp(low.time, tz)));
+    }
+    if let Some(ticker) = ticker {
+        lines.push(format_ticker(&ticker, precision, tz));
+    }
+    lines.push(format!(
+        "Longest Identical Run: {} trades ({} runs over {})",
+        repeats.longest, repeats.stale_runs, config.stale_threshold
+    ));
+    if let Some(time_to_connect) = stats.time_to_connect {
+        lines.push(format!("Connected After: {:.3}s", time_to_connect.as_secs_f64()));
+    }
+    if stats.reconnect_cap_reached {
+        lines.push(format!("Reconnects: {} (limit reached)", stats.reconnects));
+    } else {
+        lines.push(format!("Reconnects: {}", stats.reconnects));
+    }
+    lines.push(format!("Connected Time: {:.3}s", stats.connected.as_secs_f64()));
+    lines.push(format!("Downtime: {:.3}s", stats.downtime.as_secs_f64()));
+    lines.push(format!("Disconnect Reasons: {:?}", stats.disconnect_reasons));
+    lines
+}
+
+/// How a client's average is computed: the statistic followed by any parameters that
+/// change the result, e.g. `twap, tick size 0.5, last 2s trimmed`.
+fn client_method(config: &ClientConfig) -> String {
+    let mut parts = vec![match config.stat {
+        Stat::Mean if config.decimal => "mean (exact decimal)".to_string(),
+        Stat::Mean => "mean".to_string(),
+        Stat::Twap => "twap".to_string(),
+        Stat::Geomean => "geometric mean".to_string(),
+        Stat::Vwap => "vwap".to_string(),
+    }];
+    if let Some(scale) = config.price_scale {
+        parts.push(format!("prices scaled by {}", scale.factor));
+    }
+    if let Some(tick) = config.tick_size {
+        parts.push(format!("tick size {tick}"));
+    }
+    if let Some(trim_end) = config.trim_end {
+        parts.push(format!("last {}s trimmed", trim_end.as_secs()));
+    }
+    if config.normalize {
+        parts.push("normalized to the open".to_string());
+    }
+    parts.join(", ")
+}
+
+/// How the aggregator combines client averages into a global average.
+fn global_method(weighting: Weighting) -> String {
+    match weighting {
+        Weighting::Equal => "mean of client averages".to_string(),
+        Weighting::Trades => "trade-weighted mean of client averages".to_string(),
+        Weighting::Recency { decay, half_life } => format!(
+            "recency-weighted mean of client averages ({} decay, half-life {}s)",
+            decay.name(),
+            half_life.as_secs_f64()
+        ),
+    }
+}
+
+/// The exchange's own 24h statistics from the last mini ticker of the window.
+fn format_ticker(ticker: &MiniTicker, precision: usize, tz: DisplayTz) -> String {
+    format!(
+        "Exchange 24h: open {:.precision$}, high {:.precision$}, low {:.precision$}, close {:.precision$}, volume {} (as of {})",
+        ticker.open,
+        ticker.high,
+        ticker.low,
+        ticker.close,
+        ticker.volume,
+        format_timestamp(ticker.event_time, tz)
+    )
+}
+
+/// Save individual client data to file.
+fn save_client_data(
+    trades: &[Trade],
+    summary: &WindowSummary,
+    stats: &ConnectionStats,
+    config: &ClientConfig,
+) -> std::io::Result<()> {
+    write_client_file(config, client_data_lines(trades, summary, stats, config))
+}
+
+/// Save a `--stats-interval` snapshot of a client still listening, in the same layout as
+/// its final data with an in-progress line at the top. `summary.average` is the running
+/// mean so far. The final save replaces it.
+fn save_client_snapshot(
+    trades: &[Trade],
+    summary: &WindowSummary,
+    stats: &ConnectionStats,
+    config: &ClientConfig,
+) -> std::io::Result<()> {
+    let mut lines = vec![format!(
+        "Status: in progress, snapshot at {}; the average is the running mean so far",
+        format_timestamp(Utc::now().timestamp_millis(), config.tz)
+    )];
+    lines.extend(client_data_lines(trades, summary, stats, config));
+    write_client_file(config, lines)
+}
+
+/// Save the running global average per symbol from the clients' `--checkpoint-on-signal`
+/// replies to `global_data.txt`, in the usual layout with an in-progress line at the top.
+/// The global average is the plain mean of the running client averages; the aggregator's
+/// final save replaces it.
+fn save_global_checkpoint(replies: &[checkpoint::ClientCheckpoint], tz: DisplayTz, precision: usize) -> std::io::Result<()> {
+    let mut by_symbol: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+    for reply in replies {
+        if let Some(average) = reply.average {
+            by_symbol.entry(&reply.symbol).or_default().push(average);
+        }
+    }
+    let mut lines = vec![format!(
+        "Status: in progress, checkpoint at {}; averages are running means so far",
+        format_timestamp(Utc::now().timestamp_millis(), tz)
+    )];
+    for (symbol, averages) in &by_symbol {
+        let prefix = if by_symbol.len() == 1 { String::new() } else { format!("{symbol} ") };
+        lines.push(format!("{prefix}Method: mean of running client averages"));
+        lines.push(format!("{prefix}Client Averages: {averages:?}"));
+        if let Some(average) = calculate_average(averages) {
+            lines.push(format!("{prefix}Global Average: {average:.precision$}"));
+        }
+    }
+    let mut contents = lines.join("\n");
+    contents.push('\n');
+    state::write_atomic("global_data.txt", contents.as_bytes())
+}
+
+/// Replace a client's data file in one step, so a crash never leaves a half-written file
+/// in place of a snapshot.
+fn write_client_file(config: &ClientConfig, lines: Vec<String>) -> std::io::Result<()> {
+    let mut contents = lines.join("\n");
+    contents.push('\n');
+    state::write_atomic(&format!("client_{}_data.txt", config.name), contents.as_bytes())
+}
+
+/// Write a client's trades and average as InfluxDB line protocol.
+async fn save_client_influx(name: &str, symbol: &str, trades: &[Trade], average: f64, url: Option<&str>) -> batch::SinkResult {
+    let mut lines: Vec<String> = trades.iter().map(|trade| influx::trade_line(symbol, trade)).collect();
+    let last_time = trades.last().map_or_else(|| Utc::now().timestamp_millis(), |trade| trade.time);
+    lines.push(influx::client_average_line(symbol, name, average, last_time));
+    influx::write_lines(&lines, &format!("client_{name}_data.lp"), url).await
+}
+
+/// Build the Binance trade stream URL for a symbol.
+fn binance_url(symbol: &str) -> String {
+    format!("wss://stream.binance.com:9443/ws/{}@trade", symbol.to_lowercase())
+}
+
+/// Build the Binance 24h mini ticker stream URL for a symbol.
+fn binance_mini_ticker_url(symbol: &str) -> String {
+    format!("wss://stream.binance.com:9443/ws/{}@miniTicker", symbol.to_lowercase())
+}
+
+/// Number of decimals in the tick size of a Binance symbol, from its `PRICE_FILTER`,
+/// e.g. 2 for a tick size of `0.01000000`.
+async fn tick_decimals(symbol: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    let body = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?
+        .get(BINANCE_EXCHANGE_INFO_URL)
+        .query(&[("symbol", symbol)])
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let info: Value = serde_json::from_str(&body)?;
+    let tick_size = info["symbols"][0]["filters"]
+        .as_array()
+        .and_then(|filters| filters.iter().find(|filter| filter["filterType"] == "PRICE_FILTER"))
+        .and_then(|filter| filter["tickSize"].as_str())
+        .ok_or("No tick size in exchange info")?;
+    let decimals = tick_size
+        .split_once('.')
+        .map_or(0, |(_, fraction)| fraction.trim_end_matches('0').len());
+    Ok(decimals)
+}
+
+/// Render the aggregator's data for one symbol as text lines, ending with the global average.
+fn global_data_lines(summary: &SymbolSummary) -> Vec<String> {
+    let precision = summary.precision;
+    let mut lines = vec![
+        format!("Method: {}", summary.method),
+        format!("Client Averages: {:?}", summary.averages),
+    ];
+    if let Some(comparison) = &summary.comparison {
+        let (first, first_avg) = comparison.first;
+        let (second, second_avg) = comparison.second;
+        lines.push(format!("Source Averages: {first}={first_avg:.precision$}, {second}={second_avg:.precision$}"));
+        lines.push(match (comparison.mean_spread, comparison.max_spread) {
+            (Some(mean), Some(max)) => format!(
+                "Spread ({first} - {second}): mean {mean:.precision$}, max {max:.precision$} over {} seconds",
+                comparison.aligned_seconds
+            ),
+            _ => format!("Spread ({first} - {second}): undefined (no overlapping seconds)"),
+        });
+    }
+    if !summary.groups.is_empty() {
+        let groups: Vec<String> = summary
+            .groups
+            .iter()
+            .map(|group| format!("{}={:.precision$} ({} clients)", group.name, group.average, group.clients))
+            .collect();
+        lines.push(format!("Group Averages: {}", groups.join(", ")));
+    }
+    if let Some(consensus) = summary.consensus {
+        lines.push(format!("Client Average Variance: {}", consensus.variance));
+        lines.push(format!("Client Average CV: {:.4}%", consensus.cv_pct));
+    }
+    lines.push(format!("Global Average: {:.precision$}", summary.global_average));
+    lines
+}
+
+/// Save global aggregator data to file. With several symbols, each line is prefixed
+/// with the symbol it belongs to. The file is replaced in one step, so a crash never
+/// leaves it half-written, nor its `--checksum` sidecar describing a partial file.
+fn save_global_data(summaries: &[SymbolSummary], correlation: Option<&Correlation>) -> std::io::Result<()> {
+    let mut lines: Vec<String> = correlation.map(correlation_line).into_iter().collect();
+    for summary in summaries {
+        for line in global_data_lines(summary) {
+            if summaries.len() == 1 {
+                lines.push(line);
+            } else {
+                lines.push(format!("{} {line}", summary.symbol));
+            }
+        }
+    }
+    let contents: String = lines.iter().map(|line| format!("{line}\n")).collect();
+    state::write_atomic("global_data.txt", contents.as_bytes())
+}
+
+/// Write every line sent by the clients and the aggregator to stdout, in arrival order.
+/// Finishes once all senders are dropped.
+async fn stdout_writer(mut rx: mpsc::Receiver<String>) {
+    let mut stdout = io::stdout();
+    while let Some(line) = rx.recv().await {
+        if let Err(e) = writeln!(stdout, "{line}") {
+            eprintln!("Failed to write to stdout: {e}");
+            return;
+        }
+    }
+    let _ = stdout.flush();
+}
+
+/// A `--price-scale` factor, kept as a decimal too so exact prices are scaled exactly.
+#[derive(Debug, Clone, Copy)]
+struct PriceScale {
+    factor: f64,
+    exact: Decimal,
+}
+
+impl PriceScale {
+    fn apply(&self, mut trade: Trade) -> Trade {
+        trade.price *= self.factor;
+        trade.exact_price = trade
+            .exact_price
+            .checked_mul(self.exact)
+            .or_else(|| Decimal::from_f64_retain(trade.price))
+            .unwrap_or_default();
+        trade
+    }
+}
+
+/// Parse `--price-scale`, a positive number such as `1e-8` or `0.001`.
+fn parse_price_scale(value: &str) -> Result<PriceScale, String> {
+    let factor: f64 = value.parse().map_err(|_| format!("`{value}` is not a number"))?;
+    if !(factor.is_finite() && factor > 0.0) {
+        return Err("price scale must be greater than zero".to_string());
+    }
+    let exact = value
+        .parse::<Decimal>()
+        .or_else(|_| Decimal::from_scientific(value))
+        .map_err(|_| format!("`{value}` can't be represented as a decimal"))?;
+    Ok(PriceScale { factor, exact })
+}
+
+/// Parse `--tick-size`, which must be a positive number.
+fn parse_tick_size(value: &str) -> Result<f64, String> {
+    let tick: f64 = value.parse().map_err(|_| format!("`{value}` is not a number"))?;
+    if tick.is_finite() && tick > 0.0 {
+        Ok(tick)
+    } else {
+        Err("tick size must be greater than zero".to_string())
+    }
+}
+
+/// Map a source name from the command line to a `Source`. `poll` is the REST polling
+/// interval and jitter; `stream` the Binance stream to subscribe to.
+fn parse_source(name: &str, seed: u64, mock_rate: u32, poll: (Duration, Duration), stream: StreamType) -> Option<Source> {
+    match name {
+        "binance" => Some(Source::Binance { stream }),
+        "coinbase" => Some(Source::Coinbase),
+        "mock" => Some(Source::Mock { seed, rate: mock_rate }),
+        "csv" => Some(Source::Csv),
+        "rest-poll" => Some(Source::RestPoll { interval: poll.0, jitter: poll.1 }),
+        _ => None,
+    }
+}
+
+/// Parse the command-line arguments
+fn parse_arguments() -> clap::ArgMatches {
+    let prefix = env_prefix(std::env::args());
+    Command::new("WebSocket Listener")
+        .version("1.0")
+        .author("Pruthvi Thakor")
+        .about("Listens to the WebSocket for BTC/USDT prices")
+        .arg(
+            Arg::new("mode")
+                .short('m')
+                .long("mode")
+                .value_name("MODE")
+                .help("Specifies the mode of operation")
+                .default_value("cache"),
+        )
+        .arg(
+            Arg::new("times")
+                .short('t')
+                .long("times")
+                .value_name("NUMBER")
+                .help("The number of seconds to listen")
+                .default_value("1"),
+            )
+        .arg(
+            Arg::new("print-every")
+                .short('p')
+                .long("print-every")
+                .value_name("N")
+                .help("Log the running average every N trades (0 = never)")
+                .default_value("0"),
+            )
+        .arg(
+            Arg::new("tz")
+                .long("tz")
+                .value_name("TIMEZONE")
+                .help("Timezone for saved timestamps: `local` or an IANA name such as America/New_York")
+                .default_value("UTC"),
+            )
+        .arg(
+            Arg::new("symbols")
+                .long("symbols")
+                .value_name("SYMBOLS")
+                .help("Comma-separated symbols to track; clients are assigned to them round-robin")
+                .value_delimiter(',')
+                .default_value(SYMBOL),
+            )
+        .arg(
+            Arg::new("normalize-symbols")
+                .long("normalize-symbols")
+                .help("Accept symbols typed as e.g. btc/usdt or BTC-USDT by dropping everything but letters and digits")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("client-labels")
+                .long("client-labels")
+                .value_name("LABELS")
+                .help("Comma-separated names for the clients, in client order, used in logs, file names and saved data instead of their ids")
+                .value_delimiter(','),
+            )
+        .arg(
+            Arg::new("groups")
+                .long("groups")
+                .value_name("GROUPS")
+                .help("Average clients within groups first, e.g. \"eu:1,2 us:3,4,5\" (client ids or labels); the global average is the mean of the group averages, and clients left out form a default group"),
+            )
+        .arg(
+            Arg::new("durations")
+                .long("durations")
+                .value_name("SECONDS")
+                .help("Comma-separated listening windows in seconds, in client order, overriding --times for those clients; leave an entry empty to keep --times")
+                .value_delimiter(',')
+                .conflicts_with("shard"),
+            )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .help("In cache mode, repeat the collection every --interval seconds and show how the global average moves")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("interval")
+                .long("interval")
+                .value_name("SECONDS")
+                .help("Pause between --watch cycles")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("60"),
+            )
+        .arg(
+            Arg::new("summary-only")
+                .long("summary-only")
+                .help("In read mode, print only each client's average and the global average")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("correlate")
+                .long("correlate")
+                .help("Report the correlation of the two --symbols' per-second returns")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("source")
+                .long("source")
+                .value_name("SOURCE")
+                .help("Where prices come from: a live feed, a seeded random walk, a CSV file or Binance REST polling")
+                .value_parser(["binance", "coinbase", "mock", "csv", "rest-poll"])
+                .default_value("binance"),
+            )
+        .arg(
+            Arg::new("input")
+                .long("input")
+                .value_name("PATH")
+                .help("CSV file of timestamp_ms,price[,qty] rows for --source csv"),
+            )
+        .arg(
+            Arg::new("seed-prices")
+                .long("seed-prices")
+                .value_name("PRICES")
+                .help("Feed every client these comma-separated prices instead of a live feed, e.g. 100,101,102, to check averaging and output end to end")
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(f64))
+                .conflicts_with_all(["source", "compare-sources", "input"]),
+            )
+        .arg(
+            Arg::new("compare-sources")
+                .long("compare-sources")
+                .value_name("SOURCES")
+                .help("Comma-separated sources to cross-check, e.g. binance,coinbase; clients are split between them")
+                .value_delimiter(',')
+                .value_parser(["binance", "coinbase", "mock", "csv", "rest-poll"])
+                .conflicts_with("source"),
+            )
+        .arg(
+            Arg::new("print-config")
+                .long("print-config")
+                .help("Print the effective configuration as JSON, with secrets redacted, and exit")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("shard")
+                .long("shard")
+                .help("Share one connection and route each trade to client trade_id % clients, so clients average disjoint subsets")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("compare-sources"),
+            )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("NUMBER")
+                .help("Seed for the mock source; the same seed produces identical output")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("0"),
+            )
+        .arg(
+            Arg::new("mock-rate")
+                .long("mock-rate")
+                .value_name("NUMBER")
+                .help("Trades per second generated by the mock source")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .default_value("10"),
+            )
+        .arg(
+            Arg::new("stream-type")
+                .long("stream-type")
+                .value_name("TYPE")
+                .help("Binance stream to read: every trade, or a 24h mini ticker snapshot about once a second")
+                .value_parser(["trade", "miniticker"])
+                .default_value("trade"),
+            )
+        .arg(
+            Arg::new("poll-interval-ms")
+                .long("poll-interval-ms")
+                .value_name("MS")
+                .help("How often the rest-poll source requests recent trades")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("1000"),
+            )
+        .arg(
+            Arg::new("poll-jitter-ms")
+                .long("poll-jitter-ms")
+                .value_name("MS")
+                .help("Random extra delay of up to MS added to each rest-poll interval")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("0"),
+            )
+        .arg(
+            Arg::new("sink")
+                .long("sink")
+                .visible_alias("format")
+                .value_name("SINK")
+                .help("Output format for saved data; text is another name for file, and html is file plus report.html (see --html-report)")
+                .value_parser([
+                    PossibleValue::new("file").alias("text"),
+                    PossibleValue::new("html"),
+                    PossibleValue::new("influx"),
+                    PossibleValue::new("msgpack"),
+                    PossibleValue::new("yaml"),
+                    PossibleValue::new("stdout"),
+                ])
+                .default_value("file"),
+            )
+        .arg(
+            Arg::new("aggregate-window")
+                .long("aggregate-window")
+                .value_name("SECONDS")
+                .help("Also write global_series.json: the global average across clients for every window of this many seconds")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+            )
+        .arg(
+            Arg::new("consensus")
+                .long("consensus")
+                .help("Report the variance and coefficient of variation of each symbol's client averages, and flag clients that disagree")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("consensus-max-cv")
+                .long("consensus-max-cv")
+                .value_name("PCT")
+                .help("With --consensus, flag a symbol whose client averages have a coefficient of variation above PCT percent")
+                .value_parser(clap::value_parser!(f64))
+                .requires("consensus")
+                .default_value("0.1"),
+            )
+        .arg(
+            Arg::new("html-report")
+                .long("html-report")
+                .help("Also write report.html, a self-contained report of the run with a table of client stats and a chart per symbol")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("no-save")
+                .long("no-save")
+                .help("Only print the averages; don't write any data files")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("sink"),
+            )
+        .arg(
+            Arg::new("batch-size")
+                .long("batch-size")
+                .value_name("NUMBER")
+                .help("With --sink influx, stream trades out in batches of NUMBER while the window is open")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .conflicts_with_all(["trim-end-secs", "normalize"]),
+            )
+        .arg(
+            Arg::new("batch-interval-ms")
+                .long("batch-interval-ms")
+                .value_name("MS")
+                .help("Also write out a partial batch after this long")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("1000"),
+            )
+        .arg(
+            Arg::new("sink-buffer")
+                .long("sink-buffer")
+                .value_name("TRADES")
+                .help("With --batch-size, most trades each client holds while the sink is unreachable; past it the oldest are dropped")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("100000"),
+            )
+        .arg(
+            Arg::new("aggregator-saves-per-client")
+                .long("aggregator-saves-per-client")
+                .help("Have clients send their data to the aggregator, which writes every client file in client order, instead of each client writing its own")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("merge-clients")
+                .long("merge-clients")
+                .help("Write every client's data and the global summary to one combined_data.json instead of per-client files")
+                .conflicts_with_all(["sink", "no-save"])
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("recency-half-life")
+                .long("recency-half-life")
+                .value_name("SECONDS")
+                .help("Weight each client's average by how recent its last trade is, losing half its weight per this many seconds behind the freshest client")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .conflicts_with("shard"),
+            )
+        .arg(
+            Arg::new("recency-decay")
+                .long("recency-decay")
+                .value_name("DECAY")
+                .help("How recency weights fall off: halving every half-life, or linearly to zero at twice the half-life")
+                .value_parser(["exponential", "linear"])
+                .default_value("exponential")
+                .requires("recency-half-life"),
+            )
+        .arg(
+            Arg::new("checksum")
+                .long("checksum")
+                .help("In cache mode, write a .sha256 file next to each saved data file; in read mode, verify them and warn on a mismatch")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("input-glob")
+                .long("input-glob")
+                .value_name("PATTERN")
+                .help("In read mode, print every saved data file matching PATTERN (e.g. \"archive/*.msgpack\") instead of the current run's files")
+                .conflicts_with("summary-only"),
+            )
+        .arg(
+            Arg::new("append-summary")
+                .long("append-summary")
+                .value_name("PATH")
+                .help("Append one JSON line summarizing the run (times, global averages, client counts, config hash) to this file, never overwriting it"),
+            )
+        .arg(
+            Arg::new("expect-avg")
+                .long("expect-avg")
+                .value_name("VALUE")
+                .help("After the run, check the global average against VALUE and exit with status 3 if it is outside --tolerance")
+                .value_parser(clap::value_parser!(f64)),
+            )
+        .arg(
+            Arg::new("max-spread-pct")
+                .long("max-spread-pct")
+                .value_name("PCT")
+                .help("With --compare-sources, exit with status 4 if a symbol's mean spread between the sources is above PCT percent of its price")
+                .value_parser(clap::value_parser!(f64))
+                .requires("compare-sources"),
+            )
+        .arg(
+            Arg::new("tolerance")
+                .long("tolerance")
+                .value_name("PCT")
+                .help("How far, in percent of --expect-avg, the global average may be from it")
+                .value_parser(clap::value_parser!(f64))
+                .requires("expect-avg")
+                .default_value("5"),
+            )
+        .arg(
+            Arg::new("summary-webhook")
+                .long("summary-webhook")
+                .value_name("URL")
+                .help("POST a JSON summary of the run (per-client and global averages) to URL at the end of the run, retrying once on failure"),
+            )
+        .arg(
+            Arg::new("json-style")
+                .long("json-style")
+                .value_name("STYLE")
+                .help("Layout of JSON output (combined_data.json, --export-metrics-json, --print-config) [default: pretty for files, compact on stdout]")
+                .value_parser(["pretty", "compact"]),
+            )
+        .arg(
+            Arg::new("influx-url")
+                .long("influx-url")
+                .value_name("URL")
+                .help("InfluxDB write endpoint to POST line protocol to instead of writing .lp files"),
+            )
+        .arg(
+            Arg::new("idle-timeout")
+                .long("idle-timeout")
+                .value_name("SECONDS")
+                .help("Reconnect if no trade arrives for this many seconds (disabled by default)")
+                .value_parser(clap::value_parser!(u64)),
+            )
+        .arg(
+            Arg::new("first-frame-timeout")
+                .long("first-frame-timeout")
+                .value_name("SECONDS")
+                .help("Stop a client with a \"connected but no data\" error if no trade arrives within this many seconds of connecting (0 disables)")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("15"),
+            )
+        .arg(
+            Arg::new("trim-end-secs")
+                .long("trim-end-secs")
+                .value_name("SECONDS")
+                .help("Leave trades from the last SECONDS of each client's window out of its average")
+                .value_parser(clap::value_parser!(u64)),
+            )
+        .arg(
+            Arg::new("aggregator-timeout")
+                .long("aggregator-timeout")
+                .value_name("SECONDS")
+                .help("Stop waiting for client reports after this many seconds (default: the longest client window plus 60)")
+                .value_parser(clap::value_parser!(u64)),
+            )
+        .arg(
+            Arg::new("drain-timeout")
+                .long("drain-timeout")
+                .value_name("SECONDS")
+                .help("After Ctrl-C, wait at most this long for clients, the aggregator and outputs to finish, then log what is still pending and exit (default: wait until they finish)")
+                .value_parser(clap::value_parser!(u64)),
+            )
+        .arg(
+            Arg::new("precision")
+                .long("precision")
+                .value_name("DECIMALS")
+                .help("Decimal places used to print prices")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("4"),
+            )
+        .arg(
+            Arg::new("decimals-from-symbol")
+                .long("decimals-from-symbol")
+                .help("Print each symbol's prices with as many decimals as its Binance tick size, falling back to --precision")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("stat")
+                .long("stat")
+                .value_name("STAT")
+                .help("Average each client reports: the mean of trade prices, the time-weighted or volume-weighted average price, or the geometric mean")
+                .value_parser(["mean", "twap", "vwap", "geomean"])
+                .default_value("mean"),
+            )
+        .arg(
+            Arg::new("percentile-mode")
+                .long("percentile-mode")
+                .value_name("MODE")
+                .help("Report p50/p90/p99 per client, exactly or from a bounded-memory t-digest")
+                .value_parser(["exact", "tdigest"]),
+            )
+        .arg(
+            Arg::new("tick-size")
+                .long("tick-size")
+                .value_name("TICK")
+                .help("Round every price to the nearest multiple of TICK before averaging")
+                .value_parser(parse_tick_size),
+            )
+        .arg(
+            Arg::new("price-scale")
+                .long("price-scale")
+                .value_name("FACTOR")
+                .help("Multiply every parsed price by FACTOR, e.g. 1e-8 for a feed quoted in satoshis, before bounds, rounding and statistics")
+                .value_parser(parse_price_scale),
+            )
+        .arg(
+            Arg::new("normalize")
+                .long("normalize")
+                .help("Express prices relative to each client's first trade (the series starts at 1.0)")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("decimal")
+                .long("decimal")
+                .help("Average the quoted prices with exact decimal arithmetic instead of f64")
+                .conflicts_with_all(["normalize", "tick-size"])
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("min-price")
+                .long("min-price")
+                .value_name("PRICE")
+                .help("Drop (or with --strict-range, stop on) any price below this, e.g. from a corrupted feed or the wrong symbol")
+                .value_parser(clap::value_parser!(f64)),
+            )
+        .arg(
+            Arg::new("max-price")
+                .long("max-price")
+                .value_name("PRICE")
+                .help("Drop (or with --strict-range, stop on) any price above this")
+                .value_parser(clap::value_parser!(f64)),
+            )
+        .arg(
+            Arg::new("strict-range")
+                .long("strict-range")
+                .help("Stop a client and discard its data on the first price outside --min-price/--max-price")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("filter")
+                .long("filter")
+                .value_name("EXPR")
+                .help("Only count trades matching EXPR, e.g. \"qty > 0.5 && price < 65000\"; fields are price, qty, id, time and event_time")
+            )
+        .arg(
+            Arg::new("pausable")
+                .long("pausable")
+                .help("Let SIGUSR2 pause and resume collection: while paused, clients stay connected but ignore trades, and the paused time is left out of --stat twap")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("strict-json")
+                .long("strict-json")
+                .help("Stop a client and discard its data once more than --max-parse-errors frames fail to parse")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("max-parse-errors")
+                .long("max-parse-errors")
+                .value_name("NUMBER")
+                .help("Frames each client may fail to parse under --strict-json")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("10")
+                .requires("strict-json"),
+            )
+        .arg(
+            Arg::new("latency-warn-ms")
+                .long("latency-warn-ms")
+                .value_name("MILLISECONDS")
+                .help("Track feed latency from event times and warn when a trade arrives later than this")
+                .value_parser(clap::value_parser!(u64)),
+            )
+        .arg(
+            Arg::new("stale-threshold")
+                .long("stale-threshold")
+                .value_name("TRADES")
+                .help("Warn that the feed may be frozen when more than this many trades in a row have the same price")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("100"),
+            )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .help("Time each client's socket reads, parsing and statistics updates, and report the breakdown in the summary")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("dedupe-across-clients")
+                .long("dedupe-across-clients")
+                .help("Count each trade id once across all clients, for clients reading the same trades; the global average is then trade-weighted")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("dedupe-capacity")
+                .long("dedupe-capacity")
+                .value_name("IDS")
+                .help("Trade ids per symbol --dedupe-across-clients remembers before evicting the oldest")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("100000"),
+            )
+        .arg(
+            Arg::new("correct-clock")
+                .long("correct-clock")
+                .help("Measure the local clock's offset from Binance server time and correct latency measurements by it")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("clock-resync-secs")
+                .long("clock-resync-secs")
+                .value_name("SECONDS")
+                .help("How often --correct-clock re-measures the offset")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("300"),
+            )
+        .arg(
+            Arg::new("max-samples-per-client")
+                .long("max-samples-per-client")
+                .value_name("TRADES")
+                .help("Keep at most this many trades in memory per client; past it only the running mean, count and range are updated")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .conflicts_with_all(["decimal", "normalize", "trim-end-secs", "percentile-mode"]),
+            )
+        .arg(
+            Arg::new("max-trades-total")
+                .long("max-trades-total")
+                .value_name("TRADES")
+                .help("Finish every client early once this many trades have been counted across all clients, then aggregate and save as usual")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+            )
+        .arg(
+            Arg::new("compute-on-the-fly")
+                .long("compute-on-the-fly")
+                .value_name("TRADES")
+                .help("Every TRADES trades, send the client's cumulative average to the aggregator, which prints the evolving global average")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+            )
+        .arg(
+            Arg::new("parse-workers")
+                .long("parse-workers")
+                .value_name("N")
+                .help("Parse each client's frames on a pool of N worker tasks instead of in its read loop, for very busy pairs")
+                .value_parser(clap::value_parser!(u64).range(1..=64)),
+            )
+        .arg(
+            Arg::new("stats-interval")
+                .long("stats-interval")
+                .value_name("SECONDS")
+                .help("Every SECONDS, atomically save each client's data so far, marked as in progress, so a crash loses at most one interval")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+            )
+        .arg(
+            Arg::new("checkpoint-on-signal")
+                .long("checkpoint-on-signal")
+                .help("On SIGUSR1, atomically save every client's data so far and the running global average, without stopping")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("symbols-stats-csv")
+                .long("symbols-stats-csv")
+                .value_name("PATH")
+                .help("Write one CSV row per symbol with its global average, min, max, trade count and spread"),
+            )
+        .arg(
+            Arg::new("state-file")
+                .long("state-file")
+                .value_name("PATH")
+                .help("Checkpoint each client's trade count and running mean to PATH, so a restarted run can --resume")
+                .conflicts_with_all(["decimal", "normalize", "trim-end-secs", "watch", "shard"]),
+            )
+        .arg(
+            Arg::new("resume")
+                .long("resume")
+                .help("Carry on from the counts and means in --state-file instead of starting over")
+                .requires("state-file")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("checkpoint-secs")
+                .long("checkpoint-secs")
+                .value_name("SECONDS")
+                .help("How often clients write their state to --state-file")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("10"),
+            )
+        .arg(
+            Arg::new("live-window")
+                .long("live-window")
+                .value_name("SECONDS")
+                .help("Every second, print a live global average over only the last SECONDS of trades")
+                .value_parser(clap::value_parser!(u64)),
+            )
+        .arg(
+            Arg::new("control-socket")
+                .long("control-socket")
+                .value_name("PATH")
+                .help("Serve the latest global average of each symbol, one line per symbol, to every client connecting to the Unix socket at PATH"),
+            )
+        .arg(
+            Arg::new("max-frame-kb")
+                .long("max-frame-kb")
+                .value_name("KB")
+                .help("Largest WebSocket frame accepted, in KiB; messages may be up to four times this")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("16384"),
+            )
+        .arg(
+            Arg::new("ws-subprotocol")
+                .long("ws-subprotocol")
+                .value_name("PROTOCOL")
+                .help("WebSocket subprotocol to request in the handshake (Sec-WebSocket-Protocol)")
+                .value_parser(handshake::parse_subprotocol),
+            )
+        .arg(
+            Arg::new("origin")
+                .long("origin")
+                .value_name("ORIGIN")
+                .help("Origin header to send with the WebSocket handshake, e.g. https://example.com")
+                .value_parser(handshake::parse_origin),
+            )
+        .arg(
+            Arg::new("max-reconnects")
+                .long("max-reconnects")
+                .value_name("COUNT")
+                .help("Stop reconnecting after this many reconnects and finish with the data collected so far")
+                .value_parser(clap::value_parser!(u32))
+                .default_value("10"),
+            )
+        .arg(
+            Arg::new("fail-fast")
+                .long("fail-fast")
+                .help("Abort the whole run with a nonzero exit if any client cannot connect")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("backoff-strategy")
+                .long("backoff-strategy")
+                .value_name("STRATEGY")
+                .help("How the delay between reconnect attempts grows: fixed waits --backoff-base every time, linear adds it after each failure, exponential doubles")
+                .value_parser(["fixed", "linear", "exponential"])
+                .default_value("exponential"),
+            )
+        .arg(
+            Arg::new("backoff-base")
+                .long("backoff-base")
+                .value_name("MS")
+                .help("Delay before the first reconnect attempt, in milliseconds")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("500"),
+            )
+        .arg(
+            Arg::new("backoff-max")
+                .long("backoff-max")
+                .value_name("MS")
+                .help("Upper bound on the delay between reconnect attempts, in milliseconds")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("30000"),
+            )
+        .arg(
+            Arg::new("no-jitter")
+                .long("no-jitter")
+                .help("Retry after exactly the backoff delay instead of a random fraction of it")
+                .action(clap::ArgAction::SetTrue),
+            )
+        .arg(
+            Arg::new("export-metrics-json")
+                .long("export-metrics-json")
+                .value_name("PATH")
+                .help("Write per-client counters, timings and the global average to a JSON file at the end of the run"),
+            )
+        .arg(
+            Arg::new("record-frames")
+                .long("record-frames")
+                .value_name("PATH")
+                .help("Append every raw text frame the clients receive to PATH"),
+            )
+        .arg(
+            Arg::new("rotate-size")
+                .long("rotate-size")
+                .value_name("SIZE")
+                .help("Roll the --record-frames and --append-summary files over to a new timestamped file once they reach SIZE, e.g. 100MB")
+                .value_parser(rotate::parse_size),
+            )
+        .arg(
+            Arg::new("rotate-interval")
+                .long("rotate-interval")
+                .value_name("INTERVAL")
+                .help("Roll the --record-frames and --append-summary files over every INTERVAL, e.g. 1h, 30m or 1d")
+                .value_parser(rotate::parse_interval),
+            )
+        .arg(
+            Arg::new("flush-interval-ms")
+                .long("flush-interval-ms")
+                .value_name("MS")
+                .help("How often the --record-frames buffer is written out")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("1000"),
+            )
+        .arg(
+            Arg::new("failover-url")
+                .long("failover-url")
+                .value_name("URL")
+                .help("Backup WebSocket endpoint kept connected as a warm standby"),
+            )
+        .arg(
+            Arg::new("env-prefix")
+                .long("env-prefix")
+                .value_name("PREFIX")
+                .help("Prefix of the environment variables options are read from when not given on the command line, e.g. RMC_TIMES for --times")
+                .default_value(ENV_PREFIX),
+            )
+            .mut_args(|arg| with_env(arg, &prefix))
+            .get_matches()
+        }
+
+/// The `--env-prefix` value, which has to be known before the arguments are parsed.
+fn env_prefix(mut args: impl Iterator<Item = String>) -> String {
+    while let Some(arg) = args.next() {
+        if arg == "--env-prefix" {
+            return args.next().unwrap_or_default();
+        }
+        if let Some(prefix) = arg.strip_prefix("--env-prefix=") {
+            return prefix.to_string();
+        }
+    }
+    ENV_PREFIX.to_string()
+}
+
+/// Let `arg` fall back to an environment variable named after its long flag, e.g.
+/// `RMC_PRINT_EVERY` for `--print-every`. Flags accept `1`/`true`/`yes` and `0`/`false`/`no`.
+fn with_env(arg: Arg, prefix: &str) -> Arg {
+    let Some(long) = arg.get_long().filter(|&long| long != "env-prefix") else {
+        return arg;
+    };
+    let name = format!("{prefix}{}", long.to_uppercase().replace('-', "_"));
+    match arg.get_action() {
+        clap::ArgAction::SetTrue => {
+            // clap counts a flag read from the environment as given even when it is off,
+            // which would trip `conflicts_with`, so an off flag is left unset instead.
+            let off = std::env::var(&name)
+                .is_ok_and(|value| matches!(value.trim().to_lowercase().as_str(), "0" | "false" | "no" | "n" | "f" | "off"));
+            if off {
+                arg
+            } else {
+                arg.env(name).value_parser(clap::builder::BoolishValueParser::new())
+            }
+        }
+        _ => arg.env(name),
+    }
+}
+        
+/// Write a client's or the aggregator's data as YAML when `path` ends in `.yaml`, and as
+/// MessagePack otherwise.
+fn write_structured<T: Serialize>(path: &str, value: &T) -> Result<(), Box<dyn std::error::Error>> {
+    if path.ends_with(".yaml") {
+        yaml::write(path, value)
+    } else {
+        msgpack::write(path, value)
+    }
+}
+
+/// Read a file written by [`write_structured`], picking the format the same way.
+fn read_structured<T: serde::de::DeserializeOwned>(path: &str) -> Result<T, Box<dyn std::error::Error>> {
+    if path.ends_with(".yaml") || path.ends_with(".yml") {
+        yaml::read(path)
+    } else {
+        msgpack::read(path)
+    }
+}
+
+/// Print a `.msgpack` or `.yaml` file saved by a client or the aggregator in the same
+/// layout as the text files. Which one it is comes from its contents, not its name.
+fn print_structured(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if let Ok(data) = read_structured::<Vec<msgpack::GlobalData>>(path) {
+        for symbol in &data {
+            let prefix = if data.len() == 1 { String::new() } else { format!("{} ", symbol.symbol) };
+            println!("{prefix}Method: {}", symbol.method);
+            println!("{prefix}Client Averages: {:?}", symbol.client_averages);
+            if let (Some(variance), Some(cv_pct)) = (symbol.client_variance, symbol.client_cv_pct) {
+                println!("{prefix}Client Average Variance: {variance}");
+                println!("{prefix}Client Average CV: {cv_pct:.4}%");
+            }
+            println!("{prefix}Global Average: {:.4}", symbol.global_average);
+        }
+    } else {
+        let data: msgpack::ClientData = read_structured(path)?;
+        let prices: Vec<f64> = data.trades.iter().map(|trade| trade.price).collect();
+        println!("Symbol: {} ({})", data.symbol, data.source);
+        println!("Method: {}", data.method);
+        println!("Prices: {:?}", prices);
+        if let Some(total) = data.truncated_at {
+            println!("Truncated: prices kept for the first {} of {total} trades", prices.len());
+        }
+        if let Some(scale) = data.price_scale {
+            println!("Price Scale: {scale}");
+        }
+        if let Some(tick) = data.tick_size {
+            println!("Tick Size: {tick}");
+        }
+        if let Some(open) = data.normalized_open {
+            println!("Normalized: prices are relative to the open of {open}");
+        }
+        println!("Average: {:.4}", data.average);
+        if let Some(ticker) = data.ticker {
+            println!("{}", format_ticker(&ticker, 4, DisplayTz::Named(chrono_tz::UTC)));
+        }
+    }
+    Ok(())
+}
+
+/// Print a `combined_data.json` saved with `--merge-clients`, section by section in the
+/// same layout as the MessagePack files.
+fn print_combined(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let data = combined::read(path)?;
+    for client in &data.clients {
+        let prices: Vec<f64> = client.trades.iter().map(|trade| trade.price).collect();
+        match &client.label {
+            Some(label) => println!("\nClient {label} (id {}):\n", client.id),
+            None => println!("\nClient {}:\n", client.id),
+        }
+        println!("Symbol: {} ({})", client.symbol, client.source);
+        println!("Method: {}", client.method);
+        println!("Prices: {:?}", prices);
+        if let Some(total) = client.truncated_at {
+            println!("Truncated: prices kept for the first {} of {total} trades", prices.len());
+        }
+        if let Some(open) = client.normalized_open {
+            println!("Normalized: prices are relative to the open of {open}");
+        }
+        println!("Average: {:.4}", client.average);
+        if let Some(values) = client.percentiles {
+            println!("{}", format_percentiles(values, 4));
+        }
+        if let Some(ticker) = client.ticker {
+            println!("{}", format_ticker(&ticker, 4, DisplayTz::Named(chrono_tz::UTC)));
+        }
+        println!("Longest Identical Run: {} trades", client.longest_identical_run);
+        println!("Reconnects: {}", client.reconnects);
+        println!("Downtime: {:.3}s", client.downtime_secs);
+    }
+    println!("\nGlobal:\n");
+    if let Some(correlation) = data.correlation {
+        let correlation = Correlation {
+            symbols: correlation.symbols,
+            coefficient: correlation.coefficient,
+            aligned_buckets: correlation.aligned_seconds,
+        };
+        println!("{}", correlation_line(&correlation));
+    }
+    for symbol in &data.global {
+        let prefix = if data.global.len() == 1 { String::new() } else { format!("{} ", symbol.symbol) };
+        println!("{prefix}Method: {}", symbol.method);
+        println!("{prefix}Client Averages: {:?}", symbol.client_averages);
+        if let (Some(variance), Some(cv_pct)) = (symbol.client_variance, symbol.client_cv_pct) {
+            println!("{prefix}Client Average Variance: {variance}");
+            println!("{prefix}Client Average CV: {cv_pct:.4}%");
+        }
+        println!("{prefix}Global Average: {:.4}", symbol.global_average);
+    }
+    Ok(())
+}
+
+/// Compare `symbol`'s global average with `--expect-avg` and log the outcome. True when it
+/// is within `tolerance` percent of `expected`; a run without a global average fails.
+fn check_reference(global_averages: &BTreeMap<String, f64>, symbol: &str, expected: f64, tolerance: f64, precision: usize) -> bool {
+    let Some(&average) = global_averages.get(symbol) else {
+        eprintln!("Check: FAILED: no global average for {symbol} to compare with the expected {expected}.");
+        return false;
+    };
+    let deviation = (average - expected) / expected * 100.0;
+    if deviation.abs() <= tolerance {
+        println!("Check: OK: {symbol} global average {average:.precision$} is {deviation:+.2}% from the expected {expected}, within {tolerance}%.");
+        true
+    } else {
+        eprintln!("Check: FAILED: {symbol} global average {average:.precision$} is {deviation:+.2}% from the expected {expected}, outside {tolerance}%.");
+        false
+    }
+}
+
+/// Compare each symbol's cross-source spread with `--max-spread-pct` and log the outcome.
+/// True when every symbol's spread is within `max`; a symbol whose sources never traded
+/// in the same second has no spread and fails.
+fn check_spread(spread_pcts: &BTreeMap<String, f64>, symbols: &[String], max: f64) -> bool {
+    let mut ok = true;
+    for symbol in symbols {
+        match spread_pcts.get(symbol) {
+            Some(&pct) if pct <= max => println!("Spread check: OK: {symbol} mean spread between sources is {pct:.4}%, within {max}%."),
+            Some(&pct) => {
+                eprintln!("Spread check: FAILED: {symbol} mean spread between sources is {pct:.4}%, above {max}%.");
+                ok = false;
+            }
+            None => {
+                eprintln!("Spread check: FAILED: no spread for {symbol}; its sources never traded in the same second.");
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+/// The last few global averages of a watch, each with its change from the cycle before.
+fn format_watch_history(averages: &[f64], precision: usize) -> String {
+    const SHOWN: usize = 10;
+    let skip = averages.len().saturating_sub(SHOWN);
+    averages
+        .iter()
+        .enumerate()
+        .skip(skip)
+        .map(|(i, average)| match i.checked_sub(1).map(|prev| average - averages[prev]) {
+            Some(delta) => format!("{average:.precision$} ({delta:+.precision$})"),
+            None => format!("{average:.precision$}"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Hide passwords and tokens in a URL before it is printed.
+fn redact_url(url: &str) -> String {
+    let Ok(mut parsed) = reqwest::Url::parse(url) else {
+        return "<unparseable, redacted>".to_string();
+    };
+    if parsed.password().is_some() {
+        let _ = parsed.set_password(Some("REDACTED"));
+    }
+    let query: Vec<(String, String)> = parsed
+        .query_pairs()
+        .map(|(key, value)| {
+            let secret = ["p", "password", "token", "auth", "authorization"].contains(&key.to_lowercase().as_str());
+            (key.into_owned(), if secret { "REDACTED".to_string() } else { value.into_owned() })
+        })
+        .collect();
+    if !query.is_empty() {
+        parsed.query_pairs_mut().clear().extend_pairs(query);
+    }
+    parsed.to_string()
+}
+
+/// What `--print-config` prints, one field per setting. `None` for settings that are
+/// off or don't apply.
+#[derive(Serialize)]
+struct EffectiveConfig<'a> {
+    mode: Option<&'a String>,
+    env_prefix: Option<&'a String>,
+    clients: usize,
+    client_names: &'a [String],
+    times: u64,
+    durations: &'a [u64],
+    groups: Option<&'a String>,
+    print_every: u64,
+    precision: usize,
+    decimals_from_symbol: bool,
+    tz: Option<&'a String>,
+    symbols: &'a [String],
+    normalize_symbols: bool,
+    sources: Vec<&'static str>,
+    seed_prices: Option<Vec<f64>>,
+    urls: Vec<String>,
+    failover_url: Option<String>,
+    sink: Option<&'a String>,
+    merge_clients: bool,
+    aggregator_saves_per_client: bool,
+    checksum: bool,
+    append_summary: Option<&'a String>,
+    rotate_size: Option<u64>,
+    rotate_interval_secs: Option<u64>,
+    expect_avg: Option<f64>,
+    max_spread_pct: Option<f64>,
+    tolerance_pct: Option<f64>,
+    summary_webhook: Option<String>,
+    json_style: Option<&'a String>,
+    batch_size: Option<usize>,
+    batch_interval_ms: Option<u64>,
+    sink_buffer: Option<usize>,
+    influx_url: Option<String>,
+    retry: RetrySettings,
+    max_reconnects: u32,
+    max_frame_kb: Option<usize>,
+    ws_subprotocol: Option<&'a str>,
+    origin: Option<&'a str>,
+    live_window_secs: Option<u64>,
+    control_socket: Option<&'a String>,
+    idle_timeout_secs: Option<u64>,
+    first_frame_timeout_secs: Option<u64>,
+    trim_end_secs: Option<u64>,
+    normalize: bool,
+    decimal: bool,
+    strict_json_max_parse_errors: Option<u64>,
+    tick_size: Option<f64>,
+    price_scale: Option<f64>,
+    stat: Option<&'a String>,
+    percentile_mode: Option<&'a String>,
+    shard: bool,
+    correlate: bool,
+    recency_half_life_secs: Option<u64>,
+    recency_decay: Option<&'a String>,
+    stream_type: Option<&'a String>,
+    watch_interval_secs: Option<u64>,
+    latency_warn_ms: Option<i64>,
+    stale_threshold: u64,
+    profile: bool,
+    dedupe_across_clients: bool,
+    dedupe_capacity: Option<u64>,
+    correct_clock: bool,
+    clock_resync_secs: Option<u64>,
+    min_price: Option<f64>,
+    max_price: Option<f64>,
+    strict_range: bool,
+    filter: Option<&'a str>,
+    pausable: bool,
+    max_samples_per_client: Option<usize>,
+    max_trades_total: Option<u64>,
+    compute_on_the_fly: Option<u64>,
+    parse_workers: Option<usize>,
+    stats_interval_secs: Option<u64>,
+    checkpoint_on_signal: bool,
+    aggregate_window: Option<u64>,
+    consensus_max_cv_pct: Option<f64>,
+    html_report: bool,
+    symbols_stats_csv: Option<&'a String>,
+    state_file: Option<&'a String>,
+    resume: bool,
+    checkpoint_secs: u64,
+    aggregator_timeout_secs: u64,
+    drain_timeout_secs: Option<u64>,
+    fail_fast: bool,
+    export_metrics_json: Option<&'a String>,
+    record_frames: Option<&'a String>,
+    flush_interval_ms: Option<u64>,
+}
+
+/// The `retry` section of [`EffectiveConfig`].
+#[derive(Serialize)]
+struct RetrySettings {
+    strategy: &'static str,
+    initial_backoff_ms: u64,
+    max_backoff_ms: u64,
+    jitter: bool,
+}
+
+/// Whether the aggregator writes `report.html`: with `--html-report`, or with
+/// `--format html`, which saves the usual text files as well.
+fn wants_html_report(matches: &clap::ArgMatches) -> bool {
+    matches.get_flag("html-report") || matches.get_one::<String>("sink").is_some_and(|sink| sink == "html")
+}
+
+/// The settings a cache-mode run would use, after defaults are applied, for `--print-config`.
+fn effective_config(
+    matches: &clap::ArgMatches,
+    config: &ClientConfig,
+    sources: &[Source],
+    symbols: &[String],
+    names: &[String],
+    durations: &[u64],
+    aggregator_timeout: Duration,
+) -> Value {
+    let settings = EffectiveConfig {
+        mode: matches.get_one::<String>("mode"),
+        env_prefix: matches.get_one::<String>("env-prefix"),
+        clients: names.len(),
+        client_names: names,
+        times: config.duration,
+        durations,
+        groups: matches.get_one::<String>("groups"),
+        print_every: config.print_every,
+        precision: config.precision,
+        decimals_from_symbol: matches.get_flag("decimals-from-symbol"),
+        tz: matches.get_one::<String>("tz"),
+        symbols,
+        normalize_symbols: matches.get_flag("normalize-symbols"),
+        sources: sources.iter().map(Source::name).collect(),
+        seed_prices: matches.get_many::<f64>("seed-prices").map(|prices| prices.copied().collect()),
+        urls: sources.iter().map(|source| source.url(&symbols[0])).collect(),
+        failover_url: config.failover_url.as_deref().map(redact_url),
+        sink: if matches.get_flag("no-save") { None } else { matches.get_one::<String>("sink") },
+        merge_clients: matches.get_flag("merge-clients"),
+        aggregator_saves_per_client: matches.get_flag("aggregator-saves-per-client"),
+        checksum: matches.get_flag("checksum"),
+        append_summary: matches.get_one::<String>("append-summary"),
+        rotate_size: matches.get_one::<u64>("rotate-size").copied(),
+        rotate_interval_secs: matches.get_one::<Duration>("rotate-interval").map(Duration::as_secs),
+        expect_avg: matches.get_one::<f64>("expect-avg").copied(),
+        max_spread_pct: matches.get_one::<f64>("max-spread-pct").copied(),
+        tolerance_pct: matches.get_one::<f64>("tolerance").copied().filter(|_| matches.contains_id("expect-avg")),
+        summary_webhook: matches.get_one::<String>("summary-webhook").map(|url| redact_url(url)),
+        json_style: matches.get_one::<String>("json-style"),
+        batch_size: config.batch_size,
+        batch_interval_ms: config.batch_size.map(|_| config.batch_interval.as_millis() as u64),
+        sink_buffer: config.batch_size.map(|_| config.sink_buffer),
+        influx_url: matches.get_one::<String>("influx-url").map(|url| redact_url(url)),
+        retry: RetrySettings {
+            strategy: config.retry.strategy.name(),
+            initial_backoff_ms: config.retry.initial_backoff.as_millis() as u64,
+            max_backoff_ms: config.retry.max_backoff.as_millis() as u64,
+            jitter: config.retry.jitter,
+        },
+        max_reconnects: config.max_reconnects,
+        max_frame_kb: config.ws_config.max_frame_size.map(|size| size / 1024),
+        ws_subprotocol: config.handshake.subprotocol.as_deref(),
+        origin: config.handshake.origin.as_deref(),
+        live_window_secs: matches.get_one::<u64>("live-window").copied(),
+        control_socket: matches.get_one::<String>("control-socket"),
+        idle_timeout_secs: config.idle_timeout.map(|timeout| timeout.as_secs()),
+        first_frame_timeout_secs: config.first_frame_timeout.map(|timeout| timeout.as_secs()),
+        trim_end_secs: config.trim_end.map(|trim| trim.as_secs()),
+        normalize: config.normalize,
+        decimal: config.decimal,
+        strict_json_max_parse_errors: config.strict_json,
+        tick_size: config.tick_size,
+        price_scale: config.price_scale.map(|scale| scale.factor),
+        stat: matches.get_one::<String>("stat"),
+        percentile_mode: matches.get_one::<String>("percentile-mode"),
+        shard: matches.get_flag("shard"),
+        correlate: matches.get_flag("correlate"),
+        recency_half_life_secs: matches.get_one::<u64>("recency-half-life").copied(),
+        recency_decay: matches.get_one::<u64>("recency-half-life").and(matches.get_one::<String>("recency-decay")),
+        stream_type: matches.get_one::<String>("stream-type"),
+        watch_interval_secs: matches.get_one::<u64>("interval").copied().filter(|_| matches.get_flag("watch")),
+        latency_warn_ms: config.latency_warn_ms,
+        stale_threshold: config.stale_threshold,
+        profile: config.profile,
+        dedupe_across_clients: matches.get_flag("dedupe-across-clients"),
+        dedupe_capacity: matches.get_one::<u64>("dedupe-capacity").copied(),
+        correct_clock: matches.get_flag("correct-clock"),
+        clock_resync_secs: matches.get_one::<u64>("clock-resync-secs").copied(),
+        min_price: config.bounds.min,
+        max_price: config.bounds.max,
+        strict_range: config.strict_range,
+        filter: config.filter.as_ref().map(TradeExpr::source),
+        pausable: config.pause.is_some(),
+        max_samples_per_client: config.max_samples,
+        max_trades_total: matches.get_one::<u64>("max-trades-total").copied(),
+        compute_on_the_fly: matches.get_one::<u64>("compute-on-the-fly").copied(),
+        parse_workers: config.parse_workers,
+        stats_interval_secs: config.stats_interval.map(|interval| interval.as_secs()),
+        checkpoint_on_signal: config.checkpoint.is_some(),
+        aggregate_window: matches.get_one::<u64>("aggregate-window").copied(),
+        consensus_max_cv_pct: matches.get_one::<f64>("consensus-max-cv").copied().filter(|_| matches.get_flag("consensus")),
+        html_report: wants_html_report(matches),
+        symbols_stats_csv: matches.get_one::<String>("symbols-stats-csv"),
+        state_file: matches.get_one::<String>("state-file"),
+        resume: matches.get_flag("resume"),
+        checkpoint_secs: config.checkpoint_interval.as_secs(),
+        aggregator_timeout_secs: aggregator_timeout.as_secs(),
+        drain_timeout_secs: matches.get_one::<u64>("drain-timeout").copied(),
+        fail_fast: config.abort.is_some(),
+        export_metrics_json: matches.get_one::<String>("export-metrics-json"),
+        record_frames: matches.get_one::<String>("record-frames"),
+        flush_interval_ms: matches.get_one::<u64>("flush-interval-ms").copied().filter(|_| matches.contains_id("record-frames")),
+    };
+    serde_json::to_value(settings).unwrap()
+}
+
+/// Prints the data after reading it from file
+fn read_mode(names: &[String], input_glob: Option<&str>, verify: bool) -> io::Result<()> {
+    println!("Reading prices data ...\n");
+    if let Some(pattern) = input_glob {
+        return read_glob(pattern, verify);
+    }
+    // A --merge-clients run saved everything in one file.
+    if Path::new(COMBINED_PATH).exists() {
+        println!("\nReading file: {}", COMBINED_PATH);
+        if verify {
+            check_integrity(COMBINED_PATH);
+        }
+        if let Err(err) = print_combined(COMBINED_PATH) {
+            eprintln!("Failed to read {}: {}", COMBINED_PATH, err);
+        }
+        return Ok(());
+    }
+    let mut files: Vec<String> = Vec::with_capacity(names.len()+1);
+    for name in names {
+        files.push(format!("client_{}_data.txt", name));
+    }
+    files.push(String::from("global_data.txt"));
+    'file_loop: for file_path in files.iter() {
+        // Prefer the MessagePack or YAML file when a run saved one.
+        let structured_path = [".msgpack", ".yaml"]
+            .into_iter()
+            .map(|extension| file_path.replace(".txt", extension))
+            .find(|path| Path::new(path).exists());
+        if let Some(structured_path) = structured_path {
+            println!("\nReading file: {}\n", structured_path);
+            if verify {
+                check_integrity(&structured_path);
+            }
+            if let Err(err) = print_structured(&structured_path) {
+                eprintln!("Failed to read {}: {}", structured_path, err);
+                break 'file_loop;
+            }
+            continue;
+        }
+        // Attempt to open the file
+        let file = match File::open(file_path) {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("Failed to open {}: {}", file_path, err);
+                break 'file_loop; // Exit the loop on error
+            }
+        };
+        println!("\nReading file: {}\n", file_path);
+        if verify {
+            check_integrity(file_path);
+        }
+        let reader = BufReader::new(file);
+
+        // Read the file line by line
+        for line in reader.lines() {
+            match line {
+                Ok(content) => println!("{}", content),
+                Err(err) => {
+                    eprintln!("Error reading a line in {}: {}", file_path, err);
+                    break 'file_loop; // Exit the loop on error
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// With `--checksum` in read mode, warn when a file no longer matches the hash saved
+/// next to it.
+fn check_integrity(path: &str) {
+    match checksum::verify(path) {
+        Ok(Verification::Match) => {}
+        Ok(Verification::Mismatch) => eprintln!("Warning: {path} does not match its .sha256 checksum; the file may be corrupt."),
+        Ok(Verification::NoSidecar) => eprintln!("Warning: {path} has no .sha256 checksum to verify."),
+        Err(e) => eprintln!("Warning: Failed to verify {path}: {e}"),
+    }
+}
+
+/// Print every file matching `pattern`, whatever it was named, in path order. The kind of
+/// file is taken from its extension: `.txt` client or global files, `.msgpack` and `.yaml` files and
+/// `combined_data.json` layouts. Files that aren't one of these are skipped with a warning.
+fn read_glob(pattern: &str, verify: bool) -> io::Result<()> {
+    let paths = glob::glob(pattern).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid --input-glob: {e}")))?;
+    let mut printed = 0;
+    for entry in paths {
+        let path = match entry {
+            Ok(path) if path.is_file() => path,
+            Ok(_) => continue,
+            Err(e) => {
+                eprintln!("Skipping {}: {}", e.path().display(), e.error());
+                continue;
+            }
+        };
+        let path = path.to_string_lossy().into_owned();
+        // Checksum sidecars belong to the file next to them.
+        if path.ends_with(".sha256") {
+            continue;
+        }
+        let extension = Path::new(&path).extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+        println!("\nReading file: {path}\n");
+        let result: Result<(), Box<dyn std::error::Error>> = match extension {
+            "msgpack" | "yaml" | "yml" => print_structured(&path).map_err(|e| format!("not a saved client or global file ({e})").into()),
+            "json" => print_combined(&path).map_err(|e| format!("not a combined_data.json layout ({e})").into()),
+            "txt" => print_text(&path),
+            _ => Err("unknown file type".into()),
+        };
+        match result {
+            Ok(()) => {
+                if verify {
+                    check_integrity(&path);
+                }
+                printed += 1;
+            }
+            Err(e) => eprintln!("Skipping {path}: {e}."),
+        }
+    }
+    if printed == 0 {
+        eprintln!("No saved data files match {pattern}.");
+    }
+    Ok(())
+}
+
+/// Print a client or global text file, if it has the `Average:` line every one of them
+/// ends its summary with.
+fn print_text(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let lines: Vec<String> = BufReader::new(File::open(path)?).lines().collect::<io::Result<_>>()?;
+    if !lines.iter().any(|line| line.starts_with("Average: ") || line.contains("Global Average: ")) {
+        return Err("no Average line, so not a saved client or global file".into());
+    }
+    for line in lines {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// Values of the first `Average:` and `Method:` lines in a text client file. The method
+/// of a `--stats-interval` snapshot is marked as in progress.
+fn text_average(path: &str) -> io::Result<(Option<String>, Option<String>)> {
+    let reader = BufReader::new(File::open(path)?);
+    let (mut average, mut method) = (None, None);
+    let mut in_progress = false;
+    for line in reader.lines() {
+        let line = line?;
+        if let Some(value) = line.strip_prefix("Average: ") {
+            average.get_or_insert_with(|| value.to_string());
+        } else if let Some(value) = line.strip_prefix("Method: ") {
+            method.get_or_insert_with(|| value.to_string());
+        } else if line.starts_with("Status: in progress") {
+            in_progress = true;
+        }
+    }
+    Ok((average, method.map(|method| if in_progress { format!("{method} (in progress)") } else { method })))
+}
+
+/// `(symbol, global average, method)` from the `Global Average:` and `Method:` lines of
+/// `global_data.txt`.
+fn text_global_averages(path: &str) -> io::Result<Vec<(String, String, String)>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut averages = Vec::new();
+    let mut method = "-".to_string();
+    for line in reader.lines() {
+        let line = line?;
+        if let Some((_, value)) = line.split_once("Method: ") {
+            method = value.to_string();
+        } else if let Some((symbol, average)) = line.split_once("Global Average: ") {
+            let symbol = symbol.trim();
+            let symbol = if symbol.is_empty() { "-" } else { symbol };
+            averages.push((symbol.to_string(), average.to_string(), std::mem::replace(&mut method, "-".to_string())));
+        }
+    }
+    Ok(averages)
+}
+
+/// One `(client, symbol, average, method)` row of the `--summary-only` table.
+type SummaryRow = (String, String, String, String);
+
+/// Prints each client's average and the global average as a compact table, without the
+/// raw prices. Reads `combined_data.json` or the MessagePack files when they exist,
+/// falling back to the `Average:` lines of the text files.
+fn read_summary(names: &[String], verify: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let rows = if Path::new(COMBINED_PATH).exists() {
+        if verify {
+            check_integrity(COMBINED_PATH);
+        }
+        combined_summary_rows()?
+    } else {
+        file_summary_rows(names, verify)?
+    };
+
+    let symbol_width = rows.iter().map(|(_, symbol, _, _)| symbol.len()).max().unwrap_or(0).max("Symbol".len());
+    let average_width = rows.iter().map(|(_, _, average, _)| average.len()).max().unwrap_or(0).max("Average".len());
+    println!("{:<8} {:<symbol_width$} {:<average_width$} Method", "Client", "Symbol", "Average");
+    for (client, symbol, average, method) in rows {
+        println!("{client:<8} {symbol:<symbol_width$} {average:<average_width$} {method}");
+    }
+    Ok(())
+}
+
+/// Summary rows from a `combined_data.json` saved with `--merge-clients`.
+fn combined_summary_rows() -> Result<Vec<SummaryRow>, Box<dyn std::error::Error>> {
+    let data = combined::read(COMBINED_PATH)?;
+    let mut rows: Vec<SummaryRow> = Vec::with_capacity(data.clients.len() + data.global.len());
+    for client in data.clients {
+        let name = client.label.unwrap_or_else(|| client.id.to_string());
+        rows.push((name, client.symbol, format!("{:.4}", client.average), client.method));
+    }
+    for symbol in data.global {
+        rows.push(("global".to_string(), symbol.symbol, format!("{:.4}", symbol.global_average), symbol.method));
+    }
+    Ok(rows)
+}
+
+/// Summary rows from the per-client and global MessagePack, YAML or text files.
+fn file_summary_rows(names: &[String], verify: bool) -> Result<Vec<SummaryRow>, Box<dyn std::error::Error>> {
+    let mut rows: Vec<SummaryRow> = Vec::with_capacity(names.len() + 1);
+    let check = |path: &str| {
+        if verify {
+            check_integrity(path);
+        }
+    };
+    for name in names {
+        let structured_path = [format!("client_{name}_data.msgpack"), format!("client_{name}_data.yaml")]
+            .into_iter()
+            .find(|path| Path::new(path).exists());
+        let text_path = format!("client_{name}_data.txt");
+        let (symbol, average, method) = if let Some(structured_path) = structured_path {
+            check(&structured_path);
+            let data: msgpack::ClientData = read_structured(&structured_path)?;
+            (data.symbol, format!("{:.4}", data.average), data.method)
+        } else if Path::new(&text_path).exists() {
+            check(&text_path);
+            let (average, method) = text_average(&text_path)?;
+            (
+                "-".to_string(),
+                average.unwrap_or_else(|| "no average".to_string()),
+                method.unwrap_or_default(),
+            )
+        } else {
+            ("-".to_string(), "missing".to_string(), String::new())
+        };
+        rows.push((name.clone(), symbol, average, method));
+    }
+    if let Some(global_path) = ["global_data.msgpack", "global_data.yaml"].into_iter().find(|path| Path::new(path).exists()) {
+        check(global_path);
+        let data: Vec<msgpack::GlobalData> = read_structured(global_path)?;
+        for symbol in data {
+            rows.push(("global".to_string(), symbol.symbol, format!("{:.4}", symbol.global_average), symbol.method));
+        }
+    } else if Path::new("global_data.txt").exists() {
+        check("global_data.txt");
+        for (symbol, average, method) in text_global_averages("global_data.txt")? {
+            rows.push(("global".to_string(), symbol, average, method));
+        }
+    } else {
+        rows.push(("global".to_string(), "-".to_string(), "missing".to_string(), String::new()));
+    }
+    Ok(rows)
+}
+
+#[tokio::main]
+async fn main() {
+    let num_clients: usize = 5;
+    // let duration = 10; // seconds
+    let matches = parse_arguments();
+
+    // Extract the mode and times arguments
+    let mode = matches.get_one::<String>("mode").unwrap();
+    let times: u64 = matches
+        .get_one::<String>("times")
+        .unwrap()
+        .parse()
+        .unwrap_or(1);
+    let overrides: Vec<String> = matches.get_many::<String>("durations").map_or_else(Vec::new, |durations| durations.cloned().collect());
+    let durations = match client_durations(num_clients, times, &overrides) {
+        Ok(durations) => durations,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    let longest = durations.iter().copied().max().unwrap_or(times);
+    // By default, allow clients their whole window plus a generous margin for saving.
+    let aggregator_timeout = Duration::from_secs(
+        matches
+            .get_one::<u64>("aggregator-timeout")
+            .copied()
+            .unwrap_or(longest + DEFAULT_AGGREGATOR_GRACE_SECS),
+    );
+    let print_every: u64 = matches
+        .get_one::<String>("print-every")
+        .unwrap()
+        .parse()
+        .unwrap_or(0);
+    let tz = match parse_timezone(matches.get_one::<String>("tz").unwrap()) {
+        Ok(tz) => tz,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    let labels: Vec<String> = matches.get_many::<String>("client-labels").map_or_else(Vec::new, |labels| labels.cloned().collect());
+    let names = match client_names(num_clients, &labels) {
+        Ok(names) => names,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    let groups = match matches.get_one::<String>("groups").map(|spec| client_groups(spec, &names)).transpose() {
+        Ok(groups) => groups,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    let filter = match matches.get_one::<String>("filter").map(|source| TradeExpr::parse(source)).transpose() {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    let chosen_style = matches.get_one::<String>("json-style").map(|style| match style.as_str() {
+        "compact" => JsonStyle::Compact,
+        _ => JsonStyle::Pretty,
+    });
+    // Files are indented for reading; JSON on stdout is one line per document, ndjson style.
+    let json_style = chosen_style.unwrap_or(JsonStyle::Pretty);
+    let stdout_json_style = chosen_style.unwrap_or(JsonStyle::Compact);
+    let mut stdout_task = None;
+    let sink = match matches.get_one::<String>("sink").unwrap().as_str() {
+        _ if matches.get_flag("no-save") => Sink::Discard,
+        _ if matches.get_flag("merge-clients") => Sink::Merged(json_style),
+        "influx" => Sink::Influx { url: matches.get_one::<String>("influx-url").cloned() },
+        "msgpack" => Sink::MessagePack,
+        "yaml" => Sink::Yaml,
+        "stdout" => {
+            let (out, lines) = mpsc::channel(1024);
+            stdout_task = Some(task::spawn(stdout_writer(lines)));
+            Sink::Stdout(out)
+        }
+        _ => Sink::File,
+    };
+    if matches.get_flag("aggregator-saves-per-client") && !matches!(sink, Sink::File | Sink::MessagePack | Sink::Yaml) {
+        eprintln!("--aggregator-saves-per-client needs a sink that saves per-client files: file, msgpack or yaml.");
+        std::process::exit(2);
+    }
+    if matches.get_flag("checkpoint-on-signal") && !matches!(sink, Sink::File) {
+        eprintln!("--checkpoint-on-signal saves text files and needs the default file sink.");
+        std::process::exit(2);
+    }
+    let seed = *matches.get_one::<u64>("seed").unwrap();
+    let mock_rate = *matches.get_one::<u32>("mock-rate").unwrap();
+    let stream = match matches.get_one::<String>("stream-type").unwrap().as_str() {
+        "miniticker" => StreamType::MiniTicker,
+        _ => StreamType::Trade,
+    };
+    let poll = (
+        Duration::from_millis(*matches.get_one::<u64>("poll-interval-ms").unwrap()),
+        Duration::from_millis(*matches.get_one::<u64>("poll-jitter-ms").unwrap()),
+    );
+    // With --compare-sources, clients are spread round-robin over the listed sources.
+    let sources: Vec<Source> = match matches.get_many::<String>("compare-sources") {
+        Some(names) => {
+            let sources: Vec<Source> = names.filter_map(|name| parse_source(name, seed, mock_rate, poll, stream)).collect();
+            let mut distinct: Vec<&str> = sources.iter().map(Source::name).collect();
+            distinct.dedup();
+            if distinct.len() < 2 {
+                eprintln!("--compare-sources needs at least two different sources.");
+                std::process::exit(2);
+            }
+            sources
+        }
+        None if matches.contains_id("seed-prices") => vec![Source::Seed],
+        None => vec![parse_source(matches.get_one::<String>("source").unwrap(), seed, mock_rate, poll, stream).unwrap()],
+    };
+    let source = sources[0];
+    if stream == StreamType::MiniTicker && !sources.iter().any(|source| matches!(source, Source::Binance { .. })) {
+        eprintln!("--stream-type miniticker only applies to the binance source.");
+        std::process::exit(2);
+    }
+    if matches.get_many::<f64>("seed-prices").is_some_and(|mut prices| prices.any(|price| !price.is_finite())) {
+        eprintln!("--seed-prices must all be finite numbers.");
+        std::process::exit(2);
+    }
+    if sources.iter().any(|source| matches!(source, Source::Csv)) && !matches.contains_id("input") {
+        eprintln!("--source csv needs --input <PATH>.");
+        std::process::exit(2);
+    }
+    let mut symbols: Vec<String> = Vec::new();
+    for given in matches.get_many::<String>("symbols").unwrap().map(|symbol| symbol.trim()).filter(|symbol| !symbol.is_empty()) {
+        if !matches.get_flag("normalize-symbols") {
+            symbols.push(given.to_uppercase());
+            continue;
+        }
+        let symbol = normalize_symbol(given);
+        if symbol.is_empty() {
+            eprintln!("--symbols entry {given:?} has no letters or digits left after --normalize-symbols.");
+            std::process::exit(2);
+        }
+        if symbol != given.to_uppercase() {
+            eprintln!("Symbol {given} normalized to {symbol}.");
+        }
+        symbols.push(symbol);
+    }
+    if symbols.is_empty() {
+        eprintln!("--symbols needs at least one symbol.");
+        std::process::exit(2);
+    }
+    if matches.get_flag("decimal") && matches.get_one::<String>("stat").is_some_and(|stat| stat != "mean") {
+        eprintln!("--decimal only supports --stat mean.");
+        std::process::exit(2);
+    }
+    if let (Some(min), Some(max)) = (matches.get_one::<f64>("min-price"), matches.get_one::<f64>("max-price")) {
+        if min > max {
+            eprintln!("--min-price {min} is above --max-price {max}.");
+            std::process::exit(2);
+        }
+    }
+    let backoff_base = *matches.get_one::<u64>("backoff-base").unwrap();
+    let backoff_max = *matches.get_one::<u64>("backoff-max").unwrap();
+    if backoff_base > backoff_max {
+        eprintln!("--backoff-base {backoff_base} is above --backoff-max {backoff_max}.");
+        std::process::exit(2);
+    }
+    if matches.contains_id("max-samples-per-client") && matches.get_one::<String>("stat").is_some_and(|stat| stat != "mean") {
+        eprintln!("--max-samples-per-client only supports --stat mean.");
+        std::process::exit(2);
+    }
+    if matches.contains_id("state-file") && matches.get_one::<String>("stat").is_some_and(|stat| stat != "mean") {
+        eprintln!("--state-file only supports --stat mean.");
+        std::process::exit(2);
+    }
+    if matches.contains_id("compute-on-the-fly") && matches.get_one::<String>("stat").is_some_and(|stat| stat == "twap") {
+        eprintln!("--compute-on-the-fly doesn't support --stat twap, which is only defined once the window has ended.");
+        std::process::exit(2);
+    }
+    if matches.get_one::<f64>("consensus-max-cv").is_some_and(|&max| max.is_nan() || max < 0.0) {
+        eprintln!("--consensus-max-cv can't be negative.");
+        std::process::exit(2);
+    }
+    if matches.get_one::<f64>("max-spread-pct").is_some_and(|&max| max.is_nan() || max < 0.0) {
+        eprintln!("--max-spread-pct can't be negative.");
+        std::process::exit(2);
+    }
+    if let Some(&expected) = matches.get_one::<f64>("expect-avg") {
+        if expected <= 0.0 || symbols.len() != 1 {
+            eprintln!("--expect-avg needs a positive value and exactly one symbol.");
+            std::process::exit(2);
+        }
+        if *matches.get_one::<f64>("tolerance").unwrap() < 0.0 {
+            eprintln!("--tolerance can't be negative.");
+            std::process::exit(2);
+        }
+    }
+    if matches.get_flag("correlate") && symbols.len() != 2 {
+        eprintln!("--correlate needs exactly two symbols, e.g. --symbols BTCUSDT,ETHUSDT.");
+        std::process::exit(2);
+    }
+    let shard = matches.get_flag("shard");
+    if shard && symbols.len() > 1 {
+        eprintln!("--shard splits a single symbol's stream; pass only one symbol.");
+        std::process::exit(2);
+    }
+    if symbols.len() > num_clients {
+        eprintln!("Warning: {} symbols but only {num_clients} clients; only the first {num_clients} will be tracked.", symbols.len());
+    }
+    let mut precision = Precision { default: *matches.get_one::<usize>("precision").unwrap(), by_symbol: HashMap::new() };
+    if matches.get_flag("decimals-from-symbol") && mode == "cache" {
+        if matches.get_flag("normalize") {
+            eprintln!("--decimals-from-symbol is ignored with --normalize; using --precision {}.", precision.default);
+        } else {
+            for symbol in &symbols {
+                match tick_decimals(symbol).await {
+                    Ok(decimals) => {
+                        println!("Precision for {symbol}: {decimals} decimals (from its tick size).");
+                        precision.by_symbol.insert(symbol.clone(), decimals);
+                    }
+                    Err(e) => eprintln!(
+                        "Failed to look up the tick size of {symbol}: {e}. Using --precision {}.",
+                        precision.default
+                    ),
+                }
+            }
+        }
+    }
+    let dedupe_capacity = matches
+        .get_flag("dedupe-across-clients")
+        .then(|| *matches.get_one::<u64>("dedupe-capacity").unwrap() as usize);
+    if dedupe_capacity.is_some() && sources.iter().any(|source| matches!(source, Source::Mock { .. } | Source::Csv | Source::Seed)) {
+        eprintln!("--dedupe-across-clients needs live sources; the offline sources reuse trade ids across clients.");
+        std::process::exit(2);
+    }
+    let weighting = match matches.get_one::<u64>("recency-half-life") {
+        Some(&secs) => Weighting::Recency {
+            decay: match matches.get_one::<String>("recency-decay").unwrap().as_str() {
+                "linear" => Decay::Linear,
+                _ => Decay::Exponential,
+            },
+            half_life: Duration::from_secs(secs),
+        },
+        // Both leave each client with a disjoint share of the trades.
+        None if shard || dedupe_capacity.is_some() => Weighting::Trades,
+        None => Weighting::Equal,
+    };
+    let abort = matches.get_flag("fail-fast").then(CancellationToken::new);
+    let shutdown = abort.as_ref().map_or_else(CancellationToken::new, CancellationToken::child_token);
+    let metrics = RunMetrics::start();
+    let (checkpoint_trigger, checkpoint) = if matches.get_flag("checkpoint-on-signal") {
+        let (trigger, checkpoints) = checkpoint::channel();
+        (Some(trigger), Some(checkpoints))
+    } else {
+        (None, None)
+    };
+    let rotation = rotate::Rotation {
+        max_bytes: matches.get_one::<u64>("rotate-size").copied(),
+        interval: matches.get_one::<Duration>("rotate-interval").copied(),
+    };
+    let recorder = match matches.get_one::<String>("record-frames") {
+        Some(path) if mode == "cache" && !matches.get_flag("print-config") => {
+            let flush_interval = Duration::from_millis(*matches.get_one::<u64>("flush-interval-ms").unwrap());
+            match Recorder::create(path, flush_interval, rotation) {
+                Ok(recorder) => Some(recorder),
+                Err(e) => {
+                    eprintln!("Failed to create {path}: {e}");
+                    std::process::exit(2);
+                }
+            }
+        }
+        _ => None,
+    };
+    let clock = ServerClock::default();
+    if matches.get_flag("correct-clock") && mode == "cache" && !matches.get_flag("print-config") {
+        match clock.sync().await {
+            Ok(offset) => println!("Clock: Local clock is {} Binance server time; correcting event-time comparisons.", clock::describe(offset)),
+            Err(e) => eprintln!("Clock: Failed to fetch Binance server time: {e}; not correcting the clock."),
+        }
+        clock.resync_periodically(Duration::from_secs(*matches.get_one::<u64>("clock-resync-secs").unwrap()));
+    }
+    let state_file = matches.get_one::<String>("state-file");
+    let resumed = match state_file {
+        Some(path) if matches.get_flag("resume") => match state::load(path) {
+            Ok(state) => state,
+            Err(e) if Path::new(path).exists() => {
+                eprintln!("Failed to read {path}: {e}");
+                std::process::exit(2);
+            }
+            Err(_) => {
+                eprintln!("{path} doesn't exist yet; starting from scratch.");
+                State::default()
+            }
+        },
+        _ => State::default(),
+    };
+    let client_config = ClientConfig {
+        name: String::new(),
+        duration: times,
+        print_every,
+        tz,
+        precision: precision.of(&symbols[0]),
+        symbol: symbols[0].clone(),
+        source,
+        sink: sink.clone(),
+        url: source.url(&symbols[0]),
+        failover_url: matches.get_one::<String>("failover-url").cloned(),
+        retry: RetryPolicy {
+            strategy: match matches.get_one::<String>("backoff-strategy").unwrap().as_str() {
+                "fixed" => BackoffStrategy::Fixed,
+                "linear" => BackoffStrategy::Linear,
+                _ => BackoffStrategy::Exponential,
+            },
+            initial_backoff: Duration::from_millis(backoff_base),
+            max_backoff: Duration::from_millis(backoff_max),
+            jitter: !matches.get_flag("no-jitter"),
+        },
+        live: None,
+        ws_config: {
+            let max_frame_size = *matches.get_one::<usize>("max-frame-kb").unwrap() * 1024;
+            WebSocketConfig {
+                max_frame_size: Some(max_frame_size),
+                // A message may span several frames.
+                max_message_size: Some(max_frame_size.saturating_mul(4)),
+                ..WebSocketConfig::default()
+            }
+        },
+        handshake: Handshake {
+            subprotocol: matches.get_one::<String>("ws-subprotocol").cloned(),
+            origin: matches.get_one::<String>("origin").cloned(),
+        },
+        max_reconnects: *matches.get_one::<u32>("max-reconnects").unwrap(),
+        idle_timeout: matches
+            .get_one::<u64>("idle-timeout")
+            .filter(|&&secs| secs > 0)
+            .map(|&secs| Duration::from_secs(secs)),
+        first_frame_timeout: matches
+            .get_one::<u64>("first-frame-timeout")
+            .filter(|&&secs| secs > 0)
+            .map(|&secs| Duration::from_secs(secs)),
+        trim_end: matches
+            .get_one::<u64>("trim-end-secs")
+            .filter(|&&secs| secs > 0)
+            .map(|&secs| Duration::from_secs(secs)),
+        normalize: matches.get_flag("normalize"),
+        decimal: matches.get_flag("decimal"),
+        tick_size: matches.get_one::<f64>("tick-size").copied(),
+        price_scale: matches.get_one::<PriceScale>("price-scale").copied(),
+        percentile_mode: matches.get_one::<String>("percentile-mode").map(|mode| match mode.as_str() {
+            "tdigest" => PercentileMode::TDigest,
+            _ => PercentileMode::Exact,
+        }),
+        stat: match matches.get_one::<String>("stat").unwrap().as_str() {
+            "twap" => Stat::Twap,
+            "vwap" => Stat::Vwap,
+            "geomean" => Stat::Geomean,
+            _ => Stat::Mean,
+        },
+        shard: None,
+        input: matches.get_one::<String>("input").cloned(),
+        seed_prices: matches.get_many::<f64>("seed-prices").map(|prices| prices.copied().collect()).unwrap_or_default(),
+        latency_warn_ms: matches.get_one::<u64>("latency-warn-ms").map(|&ms| ms as i64),
+        stale_threshold: *matches.get_one::<u64>("stale-threshold").unwrap(),
+        shutdown: shutdown.clone(),
+        abort: abort.clone(),
+        batch_size: matches.get_one::<u64>("batch-size").map(|&size| size as usize),
+        batch_interval: Duration::from_millis(*matches.get_one::<u64>("batch-interval-ms").unwrap()),
+        sink_buffer: *matches.get_one::<u64>("sink-buffer").unwrap() as usize,
+        strict_json: matches
+            .get_flag("strict-json")
+            .then(|| *matches.get_one::<u64>("max-parse-errors").unwrap()),
+        recorder,
+        checksum: matches.get_flag("checksum"),
+        aggregator_saves: matches.get_flag("aggregator-saves-per-client"),
+        profile: matches.get_flag("profile"),
+        state_file: state_file
+            .filter(|_| mode == "cache" && !matches.get_flag("print-config"))
+            .map(|path| StateFile::new(path, resumed.clone())),
+        checkpoint_interval: Duration::from_secs(*matches.get_one::<u64>("checkpoint-secs").unwrap()),
+        resume: None,
+        clock,
+        dedupe: None,
+        bounds: PriceBounds {
+            min: matches.get_one::<f64>("min-price").copied(),
+            max: matches.get_one::<f64>("max-price").copied(),
+        },
+        filter,
+        strict_range: matches.get_flag("strict-range"),
+        max_samples: matches.get_one::<u64>("max-samples-per-client").map(|&max| max as usize),
+        trade_cap: matches.get_one::<u64>("max-trades-total").map(|&max| TradeCap::new(max)),
+        on_the_fly: matches.get_one::<u64>("compute-on-the-fly").copied(),
+        pause: matches.get_flag("pausable").then(PauseSwitch::default),
+        group: None,
+        parse_workers: matches.get_one::<u64>("parse-workers").map(|&workers| workers as usize),
+        stats_interval: matches.get_one::<u64>("stats-interval").map(|&secs| Duration::from_secs(secs)),
+        checkpoint,
+        metrics: metrics.clone(),
+    };
+
+    if matches.get_flag("print-config") {
+        let config = effective_config(&matches, &client_config, &sources, &symbols, &names, &durations, aggregator_timeout);
+        println!("{}", stdout_json_style.format(&config).unwrap());
+        return;
+    }
+
+    // Print the parsed arguments
+    println!("Mode: {}", mode);
+
+
+    // Start the WebSocket listener in the "cache" mode
+    match mode.as_str() {
+        "cache" => {
+            let socket_path = matches.get_one::<String>("control-socket").cloned();
+            let latest = socket_path.as_ref().map(|_| LatestAverages::new(&symbols[..symbols.len().min(num_clients)]));
+            let control_socket = socket_path.as_ref().zip(latest.clone()).map(|(path, latest)| {
+                match ControlSocket::bind(path, latest, precision.clone()) {
+                    Ok(socket) => {
+                        println!("Serving the latest global averages on {path}.");
+                        socket
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to listen on --control-socket {path}: {e}");
+                        std::process::exit(2);
+                    }
+                }
+            });
+
+            // SIGUSR1 checkpoints every client and the global average.
+            if let Some(mut trigger) = checkpoint_trigger {
+                let tz = client_config.tz;
+                let precision = client_config.precision;
+                let mut signals = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1()) {
+                    Ok(signals) => signals,
+                    Err(e) => {
+                        eprintln!("Failed to listen for SIGUSR1 for --checkpoint-on-signal: {e}");
+                        std::process::exit(2);
+                    }
+                };
+                println!("Checkpoints can be saved with: kill -USR1 {}", std::process::id());
+                task::spawn(async move {
+                    while signals.recv().await.is_some() {
+                        let replies = trigger.collect(num_clients).await;
+                        let mut saved: Vec<String> = replies.iter().map(|reply| format!("client_{}_data.txt", reply.name)).collect();
+                        match save_global_checkpoint(&replies, tz, precision) {
+                            Ok(()) => saved.push("global_data.txt".to_string()),
+                            Err(e) => eprintln!("Checkpoint: Failed to save global_data.txt: {e}"),
+                        }
+                        println!("Checkpoint: Saved {} of {num_clients} clients' running statistics to {}.", replies.len(), saved.join(", "));
+                    }
+                });
+            }
+
+            // SIGUSR2 pauses and resumes collection for every client.
+            if let Some(pause) = client_config.pause.clone() {
+                let clock = client_config.clock.clone();
+                let tz = client_config.tz;
+                let mut signals = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined2()) {
+                    Ok(signals) => signals,
+                    Err(e) => {
+                        eprintln!("Failed to listen for SIGUSR2 for --pausable: {e}");
+                        std::process::exit(2);
+                    }
+                };
+                println!("Collection can be paused and resumed with: kill -USR2 {}", std::process::id());
+                task::spawn(async move {
+                    while signals.recv().await.is_some() {
+                        let now = clock.now_millis();
+                        if pause.toggle(now) {
+                            println!("Paused collection at {}; send SIGUSR2 again to resume.", format_timestamp(now, tz));
+                        } else {
+                            let paused = pause.intervals(now).last().map_or(0, |&(start, end)| end - start);
+                            println!("Resumed collection at {} after {:.3}s.", format_timestamp(now, tz), paused as f64 / 1000.0);
+                        }
+                    }
+                });
+            }
+
+            // First Ctrl-C: let every client finish early and save what it has.
+            // Second Ctrl-C, or running past --drain-timeout: exit immediately.
+            let interrupt = shutdown.clone();
+            let pending = drain::Pending::default();
+            let still_pending = pending.clone();
+            let drain_timeout = matches.get_one::<u64>("drain-timeout").copied();
+            task::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    eprintln!("Interrupted; finishing up. Press Ctrl-C again to exit immediately.");
+                    interrupt.cancel();
+                    let drained = async {
+                        match drain_timeout {
+                            Some(secs) => time::sleep(Duration::from_secs(secs)).await,
+                            None => future::pending().await,
+                        }
+                    };
+                    tokio::select! {
+                        Ok(()) = tokio::signal::ctrl_c() => {}
+                        _ = drained => eprintln!(
+                            "Drain timeout of {}s reached; exiting without waiting for: {}.",
+                            drain_timeout.unwrap_or_default(),
+                            still_pending.names().join(", ")
+                        ),
+                    }
+                    // Exiting skips destructors, so remove the socket here.
+                    if let Some(path) = &socket_path {
+                        let _ = std::fs::remove_file(path);
+                    }
+                    std::process::exit(130);
+                }
+            });
+
+            let watch = matches
+                .get_flag("watch")
+                .then(|| Duration::from_secs(*matches.get_one::<u64>("interval").unwrap()));
+            let mut history: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+            let mut cycle = 1;
+            let mut off_reference = false;
+            let mut wide_spread = false;
+            let append_summary = matches.get_one::<String>("append-summary");
+            // When the current --append-summary file was started, for --rotate-interval.
+            let mut summary_started = Instant::now();
+            let config_hash = append_summary.map(|_| {
+                let config = effective_config(&matches, &client_config, &sources, &symbols, &names, &durations, aggregator_timeout);
+                checksum::sha256_hex(config.to_string().as_bytes())
+            });
+            let failed_to_connect = loop {
+                let cycle_started = metrics::now();
+                // Room for --compute-on-the-fly partials as well as each client's final report.
+                let (tx, rx) = mpsc::channel(num_clients * 4);
+                let aggregating = pending.track("aggregator".to_string());
+                let aggregator = task::spawn(tracked(
+                    aggregating,
+                    aggregator_process(
+                        rx,
+                        AggregatorConfig {
+                            num_clients,
+                            timeout: aggregator_timeout,
+                            weighting,
+                            correlate: matches.get_flag("correlate"),
+                            precision: precision.clone(),
+                            sink: sink.clone(),
+                            checksum: matches.get_flag("checksum"),
+                            symbols: symbols.iter().take(num_clients).cloned().collect(),
+                            stats_csv: matches.get_one::<String>("symbols-stats-csv").cloned(),
+                            aggregate_window: matches.get_one::<u64>("aggregate-window").copied(),
+                            consensus_max_cv: matches
+                                .get_flag("consensus")
+                                .then(|| *matches.get_one::<f64>("consensus-max-cv").unwrap()),
+                            json_style,
+                            html_report: wants_html_report(&matches),
+                            metrics: metrics.clone(),
+                        },
+                    ),
+                ));
+
+                let live_tx = matches.get_one::<u64>("live-window").filter(|&&secs| secs > 0).map(|&secs| {
+                    let window = Duration::from_secs(secs);
+                    // Clients broadcast their live averages, so the printer and the control
+                    // socket each get every update. The final reports stay on the mpsc
+                    // channel above, one per client, for the aggregator alone.
+                    let (live_tx, live_rx) = broadcast::channel(num_clients * 4);
+                    if let Some(latest) = latest.clone() {
+                        task::spawn(follow_live_averages(live_tx.subscribe(), move |symbol, global, _| latest.set(symbol, global)));
+                    }
+                    task::spawn(live_aggregator_process(live_rx, window, precision.clone()));
+                    (window, live_tx)
+                });
+
+                let mut shard_feeds = Vec::new();
+                if shard {
+                    let (senders, receivers): (Vec<_>, Vec<_>) = (0..num_clients).map(|_| mpsc::channel(1024)).unzip();
+                    task::spawn(shard_dispatcher(client_config.clone(), senders));
+                    shard_feeds = receivers.into_iter().map(|rx| Arc::new(tokio::sync::Mutex::new(rx))).collect();
+                }
+
+                let dedupe = dedupe_capacity.map(TradeFilter::new);
+                let mut clients = Vec::new();
+                for id in 1..=num_clients {
+                    let tx_clone = tx.clone();
+                    // Symbols rotate fastest, so every symbol gets a client from each source
+                    // when there are enough clients.
+                    let symbol = symbols[(id - 1) % symbols.len()].clone();
+                    let source = sources[((id - 1) / symbols.len()) % sources.len()];
+                    // A client only picks up where it left off if it still tracks the same feed.
+                    let resume = resumed
+                        .clients
+                        .get(&names[id - 1])
+                        .filter(|resume| resume.symbol == symbol && resume.source == source.name())
+                        .cloned();
+                    let config = ClientConfig {
+                        name: names[id - 1].clone(),
+                        duration: durations[id - 1],
+                        source,
+                        url: source.url(&symbol),
+                        precision: precision.of(&symbol),
+                        symbol,
+                        shard: shard_feeds.get(id - 1).cloned(),
+                        live: live_tx.clone(),
+                        resume,
+                        dedupe: dedupe.clone(),
+                        group: groups.as_ref().map(|groups| groups[id - 1].clone()),
+                        ..client_config.clone()
+                    };
+                    let listening = pending.track(format!("client {}", names[id - 1]));
+                    clients.push(task::spawn(tracked(listening, client_process(id, tx_clone, config))));
+                }
+                // Only clients hold senders now, so the aggregator sees the channel close
+                // if a client gives up without reporting.
+                drop(tx);
+                drop(live_tx);
+                match durations.iter().copied().min() {
+                    Some(shortest) if shortest != longest => println!("Will listen for {shortest} to {longest} seconds, depending on the client."),
+                    _ => println!("Will listen for {} seconds.", times),
+                }
+                let mut results = Vec::new();
+                let mut failed_to_connect = Vec::new();
+                for (id, client) in (1..).zip(clients) {
+                    match client.await {
+                        Ok(Ok(result)) => results.push(result),
+                        Ok(Err(PriceError::ConnectFailed)) => failed_to_connect.push(names[id - 1].clone()),
+                        Ok(Err(e)) => eprintln!("Client {}: {e}", names[id - 1]),
+                        Err(e) => eprintln!("Client {}: Task failed: {e}", names[id - 1]),
+                    }
+                }
+
+                let AggregateOutcome { global_averages, spread_pcts } = aggregator.await.unwrap_or_default();
+                let spread_exceeded = matches
+                    .get_one::<f64>("max-spread-pct")
+                    .is_some_and(|&max| !check_spread(&spread_pcts, &symbols, max));
+                if let Some(latest) = &latest {
+                    for (symbol, average) in &global_averages {
+                        latest.set(symbol, *average);
+                    }
+                }
+                // The stdout sink promises the global average as its last line.
+                if !matches!(client_config.sink, Sink::Stdout(_)) {
+                    println!("Summary: {} of {num_clients} clients produced an average.", results.len());
+                    for result in &results {
+                        println!(
+                            "Summary: client {} ({} {}): average {:.prec$} from {} trades, {} parse errors, {} out of range, {} reconnects, {:.3}s downtime",
+                            result.name,
+                            result.source,
+                            result.symbol,
+                            result.average,
+                            result.trades,
+                            result.parse_errors,
+                            result.out_of_range,
+                            result.stats.reconnects,
+                            result.stats.downtime.as_secs_f64(),
+                            prec = precision.of(&result.symbol)
+                        );
+                        if let Some(profile) = &result.profile {
+                            println!("Summary: client {} profile: {}", result.name, profile.summary());
+                        }
+                    }
+                    if let Some(filter) = &dedupe {
+                        println!("Summary: {} trades seen by more than one client were counted once.", filter.suppressed());
+                    }
+                }
+
+                if let (Some(path), Some(config_hash)) = (append_summary, &config_hash) {
+                    let summary = run_log::RunSummary {
+                        started_at: cycle_started.clone(),
+                        finished_at: metrics::now(),
+                        symbols: symbols.clone(),
+                        global_average: global_averages.values().next().copied().filter(|_| global_averages.len() == 1),
+                        global_averages: global_averages.clone(),
+                        clients: num_clients,
+                        clients_reported: results.len(),
+                        config_hash: config_hash.clone(),
+                        interrupted: shutdown.is_cancelled(),
+                    };
+                    run_log::append(path, &summary, &rotation, &mut summary_started).unwrap_or_else(|e| eprintln!("Failed to append the run summary to {path}: {e}"));
+                }
+
+                if let Some(url) = matches.get_one::<String>("summary-webhook") {
+                    let summary = webhook::Summary {
+                        started_at: cycle_started.clone(),
+                        finished_at: metrics::now(),
+                        interrupted: shutdown.is_cancelled(),
+                        global_average: global_averages.values().next().copied().filter(|_| global_averages.len() == 1),
+                        global_averages: global_averages.clone(),
+                        spread_pcts: spread_pcts.clone(),
+                        spread_exceeded,
+                        clients: results
+                            .iter()
+                            .map(|result| webhook::ClientSummary {
+                                name: result.name.clone(),
+                                symbol: result.symbol.clone(),
+                                source: result.source.to_string(),
+                                average: result.average,
+                                trades: result.trades,
+                                parse_errors: result.parse_errors,
+                                out_of_range: result.out_of_range,
+                                reconnects: result.stats.reconnects,
+                            })
+                            .collect(),
+                    };
+                    let _posting = pending.track("summary webhook".to_string());
+                    webhook::deliver(url, &summary).await;
+                }
+
+                if let Some(&expected) = matches.get_one::<f64>("expect-avg") {
+                    let tolerance = *matches.get_one::<f64>("tolerance").unwrap();
+                    if !check_reference(&global_averages, &symbols[0], expected, tolerance, precision.of(&symbols[0])) {
+                        off_reference = true;
+                        break failed_to_connect;
+                    }
+                }
+                if spread_exceeded {
+                    wide_spread = true;
+                    break failed_to_connect;
+                }
+
+                let Some(interval) = watch.filter(|_| failed_to_connect.is_empty()) else {
+                    break failed_to_connect;
+                };
+                for (symbol, average) in global_averages {
+                    history.entry(symbol).or_default().push(average);
+                }
+                for (symbol, averages) in &history {
+                    println!("Watch: {symbol} global averages (cycle {cycle}): {}", format_watch_history(averages, precision.of(symbol)));
+                }
+                if shutdown.is_cancelled() {
+                    break Vec::new();
+                }
+                if client_config.trade_cap.as_ref().is_some_and(TradeCap::is_reached) {
+                    println!("Watch: --max-trades-total reached; stopping.");
+                    break Vec::new();
+                }
+                println!("Watch: Next cycle in {}s; press Ctrl-C to stop.", interval.as_secs());
+                tokio::select! {
+                    _ = time::sleep(interval) => {}
+                    _ = shutdown.cancelled() => break Vec::new(),
+                }
+                if let Ok(mut metrics) = metrics.lock() {
+                    metrics.begin_cycle();
+                }
+                cycle += 1;
+            };
+
+            let _flushing = pending.track("output flush".to_string());
+            if let Some(recorder) = &client_config.recorder {
+                recorder.flush().unwrap_or_else(|e| eprintln!("Failed to flush the frame recording: {e}"));
+            }
+            // The writer finishes once the last sender, held by the shared config, is gone.
+            drop(client_config);
+            if let Some(stdout_task) = stdout_task {
+                let _ = stdout_task.await;
+            }
+
+            if let Some(path) = matches.get_one::<String>("export-metrics-json") {
+                let exported = match metrics.lock() {
+                    Ok(mut metrics) => metrics.export(path, shutdown.is_cancelled(), json_style),
+                    Err(_) => Err("metrics lock poisoned".into()),
+                };
+                match exported {
+                    Ok(()) => println!("Metrics written to {path}"),
+                    Err(e) => eprintln!("Failed to export metrics to {path}: {e}"),
+                }
+            }
+            drop(control_socket);
+            if !failed_to_connect.is_empty() {
+                eprintln!("Aborted (--fail-fast): clients {failed_to_connect:?} could not connect.");
+                std::process::exit(1);
+            }
+            if off_reference {
+                std::process::exit(3);
+            }
+            if wide_spread {
+                std::process::exit(4);
+            }
+        },
+        "read" if matches.get_flag("summary-only") => {
+            if let Err(err) = read_summary(&names, matches.get_flag("checksum")) {
+                eprintln!("Failed to read price data: {err}");
+                std::process::exit(1);
+            }
+        }
+        "read" => read_mode(&names, matches.get_one::<String>("input-glob").map(String::as_str), matches.get_flag("checksum"))
+            .expect("Failed to read price data"),
+        _ => eprintln!("Invalid mode: {mode}. Use --mode=cache or --mode=read.")
+    }
+    
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, binance_config};
+
+    fn http_error(status: u16) -> WsError {
+        WsError::Http(tokio_tungstenite::tungstenite::http::Response::builder().status(status).body(None).unwrap())
+    }
+
+    #[test]
+    fn network_errors_are_transient() {
+        for kind in [io::ErrorKind::ConnectionReset, io::ErrorKind::ConnectionRefused, io::ErrorKind::TimedOut] {
+            assert!(is_transient(&WsError::Io(kind.into())), "{kind:?}");
+        }
+        assert!(is_transient(&http_error(429)));
+        assert!(is_transient(&http_error(503)));
+        assert!(is_transient(&WsError::ConnectionClosed));
+    }
+
+    /// The delays `connect_with_retry` waits after each of its first six failed attempts.
+    fn delays(strategy: BackoffStrategy, jitter: bool) -> Vec<Duration> {
+        let retry = RetryPolicy { strategy, initial_backoff: Duration::from_millis(100), max_backoff: Duration::from_millis(1000), jitter };
+        let mut backoff = retry.initial_backoff;
+        let mut delays = Vec::new();
+        for _ in 0..6 {
+            delays.push(retry.delay(backoff));
+            backoff = retry.next_backoff(backoff);
+        }
+        delays
+    }
+
+    #[test]
+    fn each_backoff_strategy_grows_up_to_the_cap() {
+        let ms = |delays: Vec<Duration>| delays.iter().map(|delay| delay.as_millis() as u64).collect::<Vec<_>>();
+        assert_eq!(ms(delays(BackoffStrategy::Fixed, false)), [100, 100, 100, 100, 100, 100]);
+        assert_eq!(ms(delays(BackoffStrategy::Linear, false)), [100, 200, 300, 400, 500, 600]);
+        assert_eq!(ms(delays(BackoffStrategy::Exponential, false)), [100, 200, 400, 800, 1000, 1000]);
+
+        let retry = RetryPolicy { strategy: BackoffStrategy::Linear, initial_backoff: Duration::from_millis(300), max_backoff: Duration::from_millis(1000), jitter: false };
+        let mut backoff = retry.initial_backoff;
+        for expected in [600, 900, 1000, 1000] {
+            backoff = retry.next_backoff(backoff);
+            assert_eq!(backoff, Duration::from_millis(expected));
+        }
+    }
+
+    #[test]
+    fn jitter_stays_under_the_backoff() {
+        for strategy in [BackoffStrategy::Fixed, BackoffStrategy::Linear, BackoffStrategy::Exponential] {
+            for _ in 0..100 {
+                for (jittered, cap) in delays(strategy, true).into_iter().zip(delays(strategy, false)) {
+                    assert!(jittered <= cap, "{strategy:?}: {jittered:?} over {cap:?}");
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn misconfiguration_is_fatal() {
+        assert!(!is_transient(&http_error(400)));
+        assert!(!is_transient(&http_error(404)));
+        assert!(!is_transient(&WsError::Url(tokio_tungstenite::tungstenite::error::UrlError::NoHostName)));
+        let Err(tls) = native_tls::Certificate::from_pem(b"not a certificate") else {
+            panic!("parsed a certificate from garbage");
+        };
+        assert!(!is_transient(&WsError::Tls(tls.into())));
+        // However the platform words it, a name that doesn't resolve is fatal.
+        let Err(unresolved) = tokio::net::lookup_host("no-such-host.invalid:443").await else {
+            panic!("resolved a .invalid host");
+        };
+        assert!(!is_transient(&WsError::Io(unresolved)));
+    }
+
+    #[tokio::test]
+    async fn reconnecting_counts_against_the_deadline() {
+        // The first connection drops after three trades; the next one keeps trading.
+        let url = test_support::serve(|number, mut ws| async move {
+            for n in 0.. {
+                let id = number as u64 * 1000 + n + 1;
+                if ws.send(test_support::trade(id, 100.0)).await.is_err() || (number == 0 && n == 2) {
+                    return;
+                }
+                time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await;
+        let (tx, _rx) = mpsc::channel(4);
+        let started = Instant::now();
+        let result = client_process(1, tx, binance_config(&url, 2)).await.unwrap();
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_secs(2), "stopped early, after {elapsed:?}");
+        assert!(elapsed < Duration::from_millis(2300), "overran the deadline: {elapsed:?}");
+        assert_eq!(result.stats.reconnects, 1);
+        assert!(result.trades > 3, "only {} trades", result.trades);
+    }
+
+    #[tokio::test]
+    async fn a_server_close_reconnects_without_losing_trades() {
+        // The first connection closes cleanly after three trades, the way Binance ends a
+        // connection after 24 hours; the second sends three more and then goes quiet.
+        let url = test_support::serve(|number, mut ws| async move {
+            let prices = if number == 0 { [100.0, 101.0, 102.0] } else { [103.0, 104.0, 105.0] };
+            for (n, price) in (1..).zip(prices) {
+                let _ = ws.send(test_support::trade(number as u64 * 10 + n, price)).await;
+            }
+            if number == 0 {
+                let _ = ws.close(None).await;
+                while ws.next().await.is_some() {}
+            } else {
+                future::pending::<()>().await;
+            }
+        })
+        .await;
+        let (tx, _rx) = mpsc::channel(4);
+        let result = client_process(1, tx, binance_config(&url, 1)).await.unwrap();
+        assert_eq!(result.stats.reconnects, 1);
+        assert!(result.stats.disconnect_reasons[0].starts_with("closed by server"), "{:?}", result.stats.disconnect_reasons);
+        assert_eq!(result.trades, 6);
+        assert_eq!(result.average, 102.5);
+    }
+
+    #[tokio::test]
+    async fn trades_stream_into_an_in_memory_sink() {
+        let prices = vec![100.0, 101.5, 99.25, 102.0, 100.75];
+        let (sink, written) = batch::InMemorySink::new();
+        let mut config = test_support::client_config(Source::Seed, "", 1);
+        config.seed_prices = prices.clone();
+        config.sink = Sink::Memory(sink);
+        config.batch_size = Some(2);
+        let (tx, _rx) = mpsc::channel(4);
+        let result = client_process(1, tx, config).await.unwrap();
+        assert_eq!(result.trades, prices.len());
+        let written: Vec<f64> = written.lock().unwrap().iter().map(|trade| trade.price).collect();
+        assert_eq!(written, prices);
+    }
+
+    #[tokio::test]
+    async fn a_warm_standby_is_promoted_past_the_reconnect_cap() {
+        // The primary drops after three trades; the failover endpoint keeps trading.
+        let primary = test_support::serve(|_, mut ws| async move {
+            for id in 1..=3 {
+                let _ = ws.send(test_support::trade(id, 100.0)).await;
+                time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await;
+        let failover = test_support::serve(|_, mut ws| async move {
+            for id in 1000.. {
+                if ws.send(test_support::trade(id, 100.0)).await.is_err() {
+                    return;
+                }
+                time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await;
+        let mut config = binance_config(&primary, 1);
+        config.failover_url = Some(failover);
+        config.max_reconnects = 0;
+        let (tx, _rx) = mpsc::channel(4);
+        let result = client_process(1, tx, config).await.unwrap();
+        assert!(!result.stats.reconnect_cap_reached);
+        assert_eq!(result.stats.reconnects, 1);
+        assert!(result.trades > 10, "stopped after {} trades", result.trades);
+    }
+
+    #[tokio::test]
+    async fn shutdown_flushes_the_last_partial_batch() {
+        let url = test_support::serve(|_, mut ws| async move {
+            for id in 1..=10 {
+                let _ = ws.send(test_support::trade(id, 100.0 + id as f64)).await;
+            }
+            future::pending::<()>().await;
+        })
+        .await;
+        let (sink, written) = batch::InMemorySink::new();
+        let mut config = binance_config(&url, 30);
+        config.sink = Sink::Memory(sink);
+        config.batch_size = Some(4);
+        let shutdown = config.shutdown.clone();
+        tokio::spawn(async move {
+            time::sleep(Duration::from_millis(300)).await;
+            shutdown.cancel();
+        });
+        let (tx, _rx) = mpsc::channel(4);
+        let result = client_process(1, tx, config).await.unwrap();
+        assert_eq!(result.trades, 10);
+        let ids: Vec<u64> = written.lock().unwrap().iter().map(|trade| trade.id).collect();
+        assert_eq!(ids, (1..=10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn each_statistic_runs_through_the_averager_trait() {
+        // 100 for 1 and 110 for 3: mean 105, VWAP 107.5, geometric mean sqrt(11000).
+        let url = test_support::serve(|_, mut ws| async move {
+            let _ = ws.send(test_support::fill(1, 100.0, 1.0)).await;
+            let _ = ws.send(test_support::fill(2, 110.0, 3.0)).await;
+            future::pending::<()>().await;
+        })
+        .await;
+        for (stat, expected) in [(Stat::Mean, 105.0), (Stat::Vwap, 107.5), (Stat::Geomean, 11_000f64.sqrt())] {
+            let mut config = binance_config(&url, 1);
+            config.stat = stat;
+            let (tx, _rx) = mpsc::channel(4);
+            let result = client_process(1, tx, config).await.unwrap();
+            assert_eq!(result.trades, 2);
+            assert!((result.average - expected).abs() < 1e-9, "{stat:?}: {}, expected {expected}", result.average);
+        }
+    }
+
+    #[test]
+    fn binance_error_frames_are_recognized() {
+        let error = binance_error(r#"{"error":{"code":2,"msg":"Invalid request: unknown stream"},"id":1}"#).unwrap();
+        assert_eq!(error, BinanceError { code: 2, msg: "Invalid request: unknown stream".to_string() });
+        assert!(error.is_fatal());
+        assert!(!binance_error(r#"{"error":{"code":3,"msg":"Invalid JSON"}}"#).unwrap().is_fatal());
+        assert_eq!(binance_error(r#"{"result":null,"id":1}"#), None);
+        assert_eq!(binance_error("not json"), None);
+    }
+
+    #[tokio::test]
+    async fn a_rejected_subscription_stops_the_client() {
+        let url = test_support::serve(|_, mut ws| async move {
+            let _ = ws.send(Message::Text(r#"{"error":{"code":2,"msg":"Invalid request: unknown stream"},"id":1}"#.to_string())).await;
+            future::pending::<()>().await;
+        })
+        .await;
+        let (tx, _rx) = mpsc::channel(4);
+        let started = Instant::now();
+        let Err(PriceError::Rejected(error)) = client_process(1, tx, binance_config(&url, 5)).await else {
+            panic!("the error frame was not surfaced");
+        };
+        assert_eq!(error.code, 2);
+        assert!(started.elapsed() < Duration::from_secs(1), "waited out the window");
+    }
+
+    #[tokio::test]
+    async fn a_non_fatal_error_frame_is_not_a_parse_error() {
+        let url = test_support::serve(|_, mut ws| async move {
+            let _ = ws.send(test_support::trade(1, 100.0)).await;
+            let _ = ws.send(Message::Text(r#"{"error":{"code":3,"msg":"Invalid JSON"}}"#.to_string())).await;
+            let _ = ws.send(test_support::trade(2, 102.0)).await;
+            future::pending::<()>().await;
+        })
+        .await;
+        let (tx, _rx) = mpsc::channel(4);
+        let result = client_process(1, tx, binance_config(&url, 1)).await.unwrap();
+        assert_eq!(result.parse_errors, 0);
+        assert_eq!(result.trades, 2);
+        assert_eq!(result.average, 101.0);
+    }
+
+    #[tokio::test]
+    async fn a_reconnect_subscribes_again_and_trades_resume() {
+        // Like Coinbase, the server only sends trades once subscribed. The first connection
+        // drops after two trades.
+        let subscriptions = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = subscriptions.clone();
+        let url = test_support::serve(move |number, mut ws| {
+            let received = received.clone();
+            async move {
+                let Some(Ok(Message::Text(subscription))) = ws.next().await else {
+                    return;
+                };
+                received.lock().unwrap().push(subscription);
+                for n in 1..=2 {
+                    let _ = ws.send(test_support::coinbase_match(number as u64 * 10 + n, 100.0 + n as f64)).await;
+                }
+                if number > 0 {
+                    future::pending::<()>().await;
+                }
+            }
+        })
+        .await;
+        let (tx, _rx) = mpsc::channel(4);
+        let result = client_process(1, tx, test_support::client_config(Source::Coinbase, &url, 1)).await.unwrap();
+        let expected = coinbase::subscription("BTCUSDT");
+        assert_eq!(*subscriptions.lock().unwrap(), [expected.clone(), expected]);
+        assert_eq!(result.stats.reconnects, 1);
+        assert_eq!(result.trades, 4);
+        assert_eq!(result.average, 101.5);
+    }
+
+    #[tokio::test]
+    async fn a_recording_keeps_every_connection_in_one_file() {
+        let url = test_support::serve(|number, mut ws| async move {
+            for n in 1..=2 {
+                let _ = ws.send(test_support::trade(number as u64 * 10 + n, 100.0)).await;
+            }
+            if number > 0 {
+                future::pending::<()>().await;
+            }
+        })
+        .await;
+        let path = test_support::temp_path("frames.log");
+        std::fs::write(&path, "# an earlier run\n").unwrap();
+        let recorder = Recorder::create(&path, Duration::from_secs(10), rotate::Rotation::default()).unwrap();
+        let mut config = binance_config(&url, 1);
+        config.recorder = Some(recorder.clone());
+        let (tx, _rx) = mpsc::channel(4);
+        let result = client_process(1, tx, config).await.unwrap();
+        assert_eq!(result.stats.reconnects, 1);
+        recorder.flush().unwrap();
+
+        let recording = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = recording.lines().collect();
+        assert_eq!(lines.len(), 6, "{recording}");
+        assert_eq!(lines[0], "# an earlier run");
+        assert!(lines[3].starts_with("# reconnect at ") && lines[3].ends_with("\t1"), "{}", lines[3]);
+        for (line, id) in [(lines[1], 1), (lines[2], 2), (lines[4], 11), (lines[5], 12)] {
+            assert!(line.contains(&format!("\"t\":{id},")), "expected trade {id}: {line}");
+        }
+    }
+
+    #[tokio::test]
+    async fn an_on_trade_hook_sees_a_client_run() {
+        let mut config = test_support::client_config(Source::Seed, "", 1);
+        config.seed_prices = vec![64_000.0, 66_000.0, 65_500.0, 64_900.0, 70_000.0];
+        let deadline = Instant::now() + Duration::from_secs(config.duration);
+        let above = Arc::new(AtomicU64::new(0));
+        let counter = above.clone();
+        let feed = TradeStream::new("Client 1", 1, config.clone(), deadline).on_trade(move |trade| {
+            if trade.price > 65_000.0 {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        let (tx, _rx) = mpsc::channel(10);
+        let result = run_client(1, tx, config, feed).await.unwrap();
+        assert_eq!(above.load(Ordering::Relaxed), 3);
+        assert_eq!(result.trades, 5);
+        assert_eq!(result.average, 66_080.0);
+    }
+
+    /// `count` trade frames with varied prices, quantities and ids.
+    fn trade_texts(count: u64) -> Vec<String> {
+        (0..count)
+            .map(|id| match test_support::fill(id, 60_000.0 + (id * 7919 % 10_007) as f64 / 100.0, (id % 97 + 1) as f64 / 1000.0) {
+                Message::Text(text) => text,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    /// The frame parsed the way `process_message` did before `TradeFrame`: into a `Value`,
+    /// then field by field.
+    fn parsed_through_value(text: &str) -> Result<Frame, Box<dyn std::error::Error>> {
+        frame_from_value(&serde_json::from_str(text)?)
+    }
+
+    /// Trade frames with each field missing, null, of the wrong type or out of range, next
+    /// to frames that aren't trades or aren't JSON.
+    const ODD_FRAMES: [&str; 28] = [
+        r#"{"e":"trade","E":1,"s":"BTCUSDT","t":7,"p":"60000.10","q":"0.5","T":2,"m":false,"M":true}"#,
+        r#"{"E":1,"t":7,"p":"60000.10","q":"0.5","T":2}"#,
+        r#"{"e":"aggTrade","E":1,"t":7,"p":"60000.10","q":"0.5","T":2}"#,
+        r#"{"e":"trade","E":1,"t":7,"p":"6\u0030000.1","q":"0.5","T":2}"#,
+        r#"{"e":"trade","E":1,"t":7,"q":"0.5","T":2}"#,
+        r#"{"e":"trade","E":1,"t":7,"p":null,"q":"0.5","T":2}"#,
+        r#"{"e":"trade","E":1,"t":7,"p":60000.1,"q":"0.5","T":2}"#,
+        r#"{"E":1,"t":7,"p":60000.1,"q":"0.5","T":2}"#,
+        r#"{"e":"trade","E":1,"t":-7,"p":"60000.10","q":"0.5","T":2}"#,
+        r#"{"e":"trade","E":1,"t":7.5,"p":"60000.10","q":"0.5","T":2}"#,
+        r#"{"e":"trade","E":1,"t":18446744073709551615,"p":"60000.10","q":"0.5","T":9223372036854775808}"#,
+        r#"{"e":"trade","E":1,"t":7,"p":"abc","q":"0.5","T":2}"#,
+        r#"{"e":"trade","E":1,"t":7,"p":"1e3","q":"0.5","T":2}"#,
+        r#"{"e":"trade","E":1,"t":7,"p":"60000.10","q":"","T":2}"#,
+        r#"{"e":"trade","E":1,"t":7,"t":8,"p":"60000.10","q":"0.5","T":2}"#,
+        r#"{"e":5,"E":1}"#,
+        r#"{"e":"trade"}"#,
+        r#"{"result":null,"id":1}"#,
+        r#"{"error":{"code":2,"msg":"Invalid request"}}"#,
+        r#"{"e":"24hrMiniTicker","E":1,"s":"BTCUSDT","c":"60000.1","o":"59000","h":"61000","l":"58000","v":"10","q":"600000"}"#,
+        "[1,2,3]",
+        "5",
+        r#""trade""#,
+        "null",
+        "{}",
+        "",
+        "not json",
+        r#"{"e":"trade","#,
+    ];
+
+    #[test]
+    fn the_trade_frame_struct_matches_the_value_path() {
+        let frames = trade_texts(10_000).into_iter().chain(ODD_FRAMES.map(str::to_string));
+        let mut trades = 0;
+        for text in frames {
+            let fast = process_message(&text).map(|frame| format!("{frame:?}")).map_err(drop);
+            let reference = parsed_through_value(&text).map(|frame| format!("{frame:?}")).map_err(drop);
+            assert_eq!(fast, reference, "{text}");
+            trades += usize::from(matches!(process_message(&text), Ok(Frame::Trade(_))));
+        }
+        // The generated frames, the first four odd ones, the exponent price and the
+        // duplicate key.
+        assert_eq!(trades, 10_006);
+        let Ok(Frame::Trade(trade)) = process_message(ODD_FRAMES[3]) else {
+            panic!("an escaped price is still a price");
+        };
+        assert_eq!((trade.price, trade.exact_price.to_string()), (60_000.1, "60000.1".to_string()));
+    }
+
+    /// Frames per second through `process_message` and through a `Value`, for trade frames
+    /// and for miniticker frames, which aren't trades. Run with `cargo test --release
+    /// bench_trade_frame_parsing -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_trade_frame_parsing() {
+        type Parser = fn(&str) -> Result<Frame, Box<dyn std::error::Error>>;
+        let trades = trade_texts(200_000);
+        let tickers: Vec<String> = (0..200_000)
+            .map(|time| format!(r#"{{"e":"24hrMiniTicker","E":{time},"s":"BTCUSDT","c":"60000.1","o":"59000","h":"61000","l":"58000","v":"10","q":"600000"}}"#))
+            .collect();
+        for (kind, frames) in [("trade", &trades), ("miniticker", &tickers)] {
+            for (path, parse) in [("struct", process_message as Parser), ("Value", parsed_through_value)] {
+                let started = Instant::now();
+                let parsed = frames.iter().filter(|text| parse(text).is_ok()).count();
+                let elapsed = started.elapsed();
+                assert_eq!(parsed, frames.len());
+                println!("{kind:>10} frames through {path:>6}: {:>9.0} frames/s", frames.len() as f64 / elapsed.as_secs_f64());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn a_shutdown_mid_window_still_saves_and_aggregates_every_client() {
+        let prices = [vec![100.0, 102.0, 104.0], vec![200.0, 201.0], vec![300.0, 330.0, 360.0, 390.0]];
+        let shutdown = CancellationToken::new();
+        let (tx, rx) = mpsc::channel(100);
+        let aggregator = task::spawn(aggregator_process(
+            rx,
+            AggregatorConfig {
+                num_clients: prices.len(),
+                timeout: Duration::from_secs(10),
+                weighting: Weighting::Equal,
+                correlate: false,
+                precision: Precision { default: 4, by_symbol: HashMap::new() },
+                sink: Sink::Discard,
+                checksum: false,
+                symbols: vec!["BTCUSDT".to_string()],
+                stats_csv: None,
+                html_report: false,
+                aggregate_window: None,
+                consensus_max_cv: None,
+                json_style: JsonStyle::Pretty,
+                metrics: RunMetrics::start(),
+            },
+        ));
+        let mut clients = Vec::new();
+        for (id, prices) in (1..).zip(&prices) {
+            let (sink, saved) = batch::InMemorySink::new();
+            let mut config = test_support::client_config(Source::Seed, "", 60);
+            config.name = id.to_string();
+            config.seed_prices = prices.clone();
+            config.sink = Sink::Memory(sink);
+            config.on_the_fly = Some(1);
+            config.shutdown = shutdown.clone();
+            clients.push((task::spawn(client_process(id, tx.clone(), config)), saved));
+        }
+        drop(tx);
+
+        // The seeded trades all arrive at once; the 60s window is then cut short.
+        time::sleep(Duration::from_millis(300)).await;
+        let cancelled = Instant::now();
+        shutdown.cancel();
+        for ((client, saved), prices) in clients.into_iter().zip(&prices) {
+            let result = client.await.unwrap().unwrap();
+            assert_eq!((result.trades, result.average), (prices.len(), calculate_average(prices).unwrap()));
+            let saved: Vec<f64> = saved.lock().unwrap().iter().map(|trade| trade.price).collect();
+            assert_eq!(&saved, prices);
+        }
+        let outcome = aggregator.await.unwrap();
+        assert!(cancelled.elapsed() < Duration::from_secs(5));
+        assert_eq!(outcome.global_averages["BTCUSDT"], (102.0 + 200.5 + 345.0) / 3.0);
+    }
+
+    #[tokio::test]
+    async fn a_decimal_sum_that_overflows_is_its_own_error() {
+        let mut config = test_support::client_config(Source::Seed, "", 1);
+        // Each fits in a Decimal, which tops out near 7.9e28; their sum doesn't.
+        config.seed_prices = vec![5e28, 5e28];
+        config.decimal = true;
+        let (tx, _rx) = mpsc::channel(4);
+        let error = client_process(1, tx, config).await.unwrap_err();
+        assert!(matches!(error, PriceError::DecimalOverflow), "{error}");
+    }
+
+    #[tokio::test]
+    async fn every_live_consumer_sees_every_update() {
+        let (live, first) = broadcast::channel(8);
+        let consumers: Vec<_> = [first, live.subscribe()]
+            .into_iter()
+            .map(|rx| {
+                task::spawn(async move {
+                    let mut ticks = Vec::new();
+                    follow_live_averages(rx, |symbol, global, clients| ticks.push((symbol.to_string(), global, clients))).await;
+                    ticks
+                })
+            })
+            .collect();
+        for (id, symbol, average) in [(1, "BTCUSDT", Some(100.0)), (2, "BTCUSDT", Some(102.0)), (3, "ETHUSDT", Some(5.0)), (4, "ETHUSDT", None)] {
+            live.send(LiveUpdate { id, symbol: symbol.to_string(), average }).unwrap();
+        }
+        time::sleep(LIVE_INTERVAL + Duration::from_millis(200)).await;
+        drop(live);
+
+        for consumer in consumers {
+            let ticks = consumer.await.unwrap();
+            assert_eq!(ticks, [("BTCUSDT".to_string(), 101.0, 2), ("ETHUSDT".to_string(), 5.0, 1)]);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::{Arc, Mutex};
+
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+
+use crate::JsonStyle;
+
+/// Version of the exported JSON layout. Bump it whenever a field is renamed, removed or
+/// changes meaning; adding fields is backwards compatible.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Operational counters for one client over its window.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClientMetrics {
+    pub id: usize,
+    /// The client's `--client-labels` label, if it was given one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub source: String,
+    /// Trades counted toward the client's average.
+    pub trades: u64,
+    /// Text frames that could not be parsed into a trade.
+    pub parse_errors: u64,
+    /// Prices dropped for falling outside `--min-price`/`--max-price`.
+    pub out_of_range: u64,
+    pub reconnects: u32,
+    /// Whether the client stopped early after `--max-reconnects` reconnects.
+    pub reconnect_cap_reached: bool,
+    pub time_to_connect_secs: Option<f64>,
+    pub connected_secs: f64,
+    pub downtime_secs: f64,
+    /// Feed latency, only measured with `--latency-warn-ms`.
+    pub latency_avg_ms: Option<f64>,
+    pub latency_max_ms: Option<i64>,
+    pub latency_warnings: u64,
+    /// Trades received before their event time (clock skew).
+    pub negative_skew_trades: u64,
+    /// Most consecutive trades at the same price.
+    pub longest_identical_run: u64,
+    /// Runs of identical prices longer than `--stale-threshold`.
+    pub stale_runs: u64,
+    pub average: Option<f64>,
+}
+
+/// Everything collected during a run, exported with `--export-metrics-json`.
+#[derive(Debug, Serialize)]
+pub struct RunMetrics {
+    pub schema_version: u32,
+    /// RFC 3339 UTC timestamps.
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    /// Whether the run was cut short by Ctrl-C.
+    pub interrupted: bool,
+    /// One entry per client, ordered by id.
+    pub clients: Vec<ClientMetrics>,
+    /// Global average when a single symbol was tracked.
+    pub global_average: Option<f64>,
+    /// Global average per symbol.
+    pub global_averages: BTreeMap<String, f64>,
+}
+
+/// Metrics shared between the clients, the aggregator and `main`.
+pub type SharedMetrics = Arc<Mutex<RunMetrics>>;
+
+/// Current time as an RFC 3339 UTC timestamp.
+pub fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+impl RunMetrics {
+    /// Start collecting metrics for a run beginning now.
+    pub fn start() -> SharedMetrics {
+        Arc::new(Mutex::new(RunMetrics {
+            schema_version: SCHEMA_VERSION,
+            started_at: now(),
+            finished_at: None,
+            interrupted: false,
+            clients: Vec::new(),
+            global_average: None,
+            global_averages: BTreeMap::new(),
+        }))
+    }
+
+    /// Record a client's final counters.
+    pub fn record_client(&mut self, client: ClientMetrics) {
+        self.clients.push(client);
+        self.clients.sort_by_key(|client| client.id);
+    }
+
+    /// Start over for the next `--watch` cycle, so the export describes the latest one.
+    pub fn begin_cycle(&mut self) {
+        self.started_at = now();
+        self.clients.clear();
+        self.global_average = None;
+        self.global_averages.clear();
+    }
+
+    /// Mark the run as finished and write the metrics to `path` as JSON in the given style.
+    pub fn export(&mut self, path: &str, interrupted: bool, style: JsonStyle) -> Result<(), Box<dyn std::error::Error>> {
+        self.finished_at = Some(now());
+        self.interrupted = interrupted;
+        let file = BufWriter::new(File::create(path)?);
+        style.write(file, self)?;
+        Ok(())
+    }
+}
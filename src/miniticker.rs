@@ -0,0 +1,109 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::Trade;
+
+/// Binance `@miniTicker` snapshot: the exchange's rolling 24h open, high, low, close and
+/// volume, pushed about once a second.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MiniTicker {
+    /// Event time in milliseconds since the Unix epoch.
+    pub event_time: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Base asset volume over the last 24h.
+    pub volume: f64,
+    /// Quote asset volume over the last 24h.
+    pub quote_volume: f64,
+    #[serde(skip)]
+    exact_close: Decimal,
+}
+
+impl MiniTicker {
+    /// The snapshot as a trade at the close price, so it can be averaged like one. The
+    /// event time doubles as the id, since it increases from one snapshot to the next;
+    /// the quantity is zero because a snapshot isn't a fill.
+    pub fn trade(&self) -> Trade {
+        Trade {
+            id: self.event_time as u64,
+            price: self.close,
+            exact_price: self.exact_close,
+            qty: 0.0,
+            time: self.event_time,
+            event_time: self.event_time,
+        }
+    }
+}
+
+fn field<'a>(json: &'a Value, name: &str) -> Result<&'a str, Box<dyn std::error::Error>> {
+    Ok(json
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("No {name} field found"))?)
+}
+
+/// Parse a `24hrMiniTicker` event. `None` for valid JSON that is some other message.
+pub fn process_message(text: &str) -> Result<Option<MiniTicker>, Box<dyn std::error::Error>> {
+    let json: Value = serde_json::from_str(text)?;
+    if json.get("e").and_then(Value::as_str) != Some("24hrMiniTicker") {
+        return Ok(None);
+    }
+    let event_time = json
+        .get("E")
+        .and_then(Value::as_i64)
+        .ok_or("No event time field found")?;
+    let close = field(&json, "c")?;
+    Ok(Some(MiniTicker {
+        event_time,
+        open: field(&json, "o")?.parse()?,
+        high: field(&json, "h")?.parse()?,
+        low: field(&json, "l")?.parse()?,
+        close: close.parse()?,
+        volume: field(&json, "v")?.parse()?,
+        quote_volume: field(&json, "q")?.parse()?,
+        exact_close: close.parse()?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_mini_ticker_frame() {
+        let text = r#"{"e":"24hrMiniTicker","E":1700000000123,"s":"BTCUSDT","c":"60017.96000000","o":"59012.50000000","h":"60500.00000000","l":"58800.01000000","v":"12345.678","q":"740000000.12"}"#;
+        let ticker = process_message(text).unwrap().unwrap();
+        assert_eq!(ticker.event_time, 1_700_000_000_123);
+        assert_eq!(ticker.open, 59_012.5);
+        assert_eq!(ticker.high, 60_500.0);
+        assert_eq!(ticker.low, 58_800.01);
+        assert_eq!(ticker.close, 60_017.96);
+        assert_eq!(ticker.volume, 12_345.678);
+        assert_eq!(ticker.quote_volume, 740_000_000.12);
+        assert_eq!(ticker.exact_close, "60017.96".parse::<Decimal>().unwrap());
+
+        let trade = ticker.trade();
+        assert_eq!((trade.id, trade.price, trade.qty), (1_700_000_000_123, 60_017.96, 0.0));
+    }
+
+    #[test]
+    fn other_json_is_not_a_ticker() {
+        for text in [
+            r#"{"result":null,"id":1}"#,
+            r#"{"e":"trade","E":1700000000123,"s":"BTCUSDT","t":1,"p":"60000.0","q":"1","T":1700000000123}"#,
+            r#"{"code":-1121,"msg":"Invalid symbol."}"#,
+            "[]",
+        ] {
+            assert!(process_message(text).unwrap().is_none(), "{text}");
+        }
+    }
+
+    #[test]
+    fn a_ticker_missing_a_field_is_an_error() {
+        assert!(process_message(r#"{"e":"24hrMiniTicker","E":1700000000123,"s":"BTCUSDT","c":"60017.96","o":"59012.5"}"#).is_err());
+        assert!(process_message("not json").is_err());
+    }
+}
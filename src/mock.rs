@@ -0,0 +1,66 @@
+use futures::stream::{self, BoxStream, StreamExt};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::time::{self, Duration, MissedTickBehavior};
+use tokio_tungstenite::tungstenite::{protocol::Message, Error as WsError};
+
+/// Trade time of the first synthetic frame (2024-01-01T00:00:00Z), so output does not
+/// depend on the wall clock.
+const START_TIME_MS: i64 = 1_704_067_200_000;
+/// Price the random walk starts from.
+const START_PRICE: f64 = 60_000.0;
+/// Largest relative price move between consecutive trades.
+const MAX_STEP: f64 = 0.0005;
+
+/// Generate `count` Binance-style trade frames for `symbol`, paced at `rate` frames per
+/// second, from a random walk seeded with `seed`. The same seed always yields the same
+/// frames. After the last frame the stream stays open without producing anything.
+pub fn frames(symbol: &str, seed: u64, rate: u32, count: u64) -> BoxStream<'static, Result<Message, WsError>> {
+    let symbol = symbol.to_uppercase();
+    let rate = rate.max(1);
+    let period_ms = 1000.0 / rate as f64;
+    let mut interval = time::interval(Duration::from_secs_f64(period_ms / 1000.0));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Burst);
+    let state = (interval, StdRng::seed_from_u64(seed), START_PRICE, 0u64);
+
+    stream::unfold(state, move |(mut interval, mut rng, price, n)| {
+        let symbol = symbol.clone();
+        async move {
+            if n >= count {
+                return None;
+            }
+            interval.tick().await;
+            let step: f64 = rng.gen_range(-MAX_STEP..=MAX_STEP);
+            let price = (price * (1.0 + step) * 100.0).round() / 100.0;
+            let qty: f64 = (rng.gen_range(0.0001..1.0) * 10_000.0_f64).round() / 10_000.0;
+            let time = START_TIME_MS + (n as f64 * period_ms) as i64;
+            let frame = format!(
+                r#"{{"e":"trade","E":{time},"s":"{symbol}","t":{id},"p":"{price:.2}","q":"{qty:.4}","T":{time},"m":false,"M":true}}"#,
+                id = n + 1,
+            );
+            Some((Ok(Message::Text(frame)), (interval, rng, price, n + 1)))
+        }
+    })
+    .chain(stream::pending())
+    .boxed()
+}
+
+/// Binance-style trade frames for `symbol` with exactly `prices`, in order, one second of
+/// trade time apart, for `--seed-prices`. They are all available at once; after the last
+/// one the stream stays open without producing anything.
+pub fn fixed(symbol: &str, prices: &[f64]) -> BoxStream<'static, Result<Message, WsError>> {
+    let symbol = symbol.to_uppercase();
+    let frames: Vec<_> = prices
+        .iter()
+        .enumerate()
+        .map(|(n, price)| {
+            let time = START_TIME_MS + n as i64 * 1000;
+            // `{price}` is the shortest form that reads back as the same f64, so exact
+            // decimal averaging sees the number as it was given.
+            Message::Text(format!(
+                r#"{{"e":"trade","E":{time},"s":"{symbol}","t":{id},"p":"{price}","q":"1","T":{time},"m":false,"M":true}}"#,
+                id = n + 1,
+            ))
+        })
+        .collect();
+    stream::iter(frames).map(Ok).chain(stream::pending()).boxed()
+}
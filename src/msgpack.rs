@@ -0,0 +1,98 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{MiniTicker, Trade};
+
+/// A client's window as saved by `--sink msgpack`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientData {
+    pub id: usize,
+    /// The client's `--client-labels` label, if it was given one.
+    #[serde(default)]
+    pub label: Option<String>,
+    pub symbol: String,
+    pub source: String,
+    pub trades: Vec<Trade>,
+    pub average: f64,
+    /// How `average` was computed, e.g. `twap, tick size 0.5`. Empty in files saved
+    /// before it was recorded.
+    #[serde(default)]
+    pub method: String,
+    /// The open prices were divided by, when saved with `--normalize`.
+    #[serde(default)]
+    pub normalized_open: Option<f64>,
+    /// The tick size prices were rounded to, when saved with `--tick-size`.
+    #[serde(default)]
+    pub tick_size: Option<f64>,
+    /// The factor parsed prices were multiplied by, when saved with `--price-scale`.
+    #[serde(default)]
+    pub price_scale: Option<f64>,
+    /// The last 24h mini ticker, when saved with `--stream-type miniticker`.
+    #[serde(default)]
+    pub ticker: Option<MiniTicker>,
+    /// Most consecutive trades at the same price.
+    #[serde(default)]
+    pub longest_identical_run: u64,
+    /// Trades counted when saved with prices truncated by `--max-samples-per-client`.
+    #[serde(default)]
+    pub truncated_at: Option<u64>,
+}
+
+/// The aggregator's result for one symbol as saved by `--sink msgpack`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GlobalData {
+    pub symbol: String,
+    /// Client averages in client id order.
+    pub client_averages: Vec<f64>,
+    pub global_average: f64,
+    /// How `global_average` was computed from the client averages.
+    #[serde(default)]
+    pub method: String,
+    /// Sample variance of the client averages, when saved with `--consensus`.
+    #[serde(default)]
+    pub client_variance: Option<f64>,
+    /// Coefficient of variation of the client averages in percent, with `--consensus`.
+    #[serde(default)]
+    pub client_cv_pct: Option<f64>,
+}
+
+/// Write `value` to `path` as MessagePack. Structs are encoded as maps keyed by field
+/// name so files stay readable if fields are added later; floats are stored as f64 and
+/// round-trip exactly.
+pub fn write<T: Serialize>(path: &str, value: &T) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = BufWriter::new(File::create(path)?);
+    rmp_serde::encode::write_named(&mut file, value)?;
+    Ok(())
+}
+
+/// Read a value written by [`write`].
+pub fn read<T: DeserializeOwned>(path: &str) -> Result<T, Box<dyn std::error::Error>> {
+    Ok(rmp_serde::decode::from_read(BufReader::new(File::open(path)?))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn saved_data_reads_back_exactly() {
+        let path = test_support::temp_path("client.msgpack");
+        let client = test_support::client_data();
+        write(&path, &client).unwrap();
+        let read_back: ClientData = read(&path).unwrap();
+        let prices: Vec<u64> = read_back.trades.iter().map(|trade| trade.price.to_bits()).collect();
+        assert_eq!(prices, test_support::AWKWARD_PRICES.map(f64::to_bits));
+        assert_eq!(read_back, client);
+
+        let path = test_support::temp_path("global.msgpack");
+        let global = test_support::global_data();
+        write(&path, &global).unwrap();
+        let read_back: GlobalData = read(&path).unwrap();
+        let averages: Vec<u64> = read_back.client_averages.iter().map(|average| average.to_bits()).collect();
+        assert_eq!(averages, test_support::AWKWARD_PRICES.map(f64::to_bits));
+        assert_eq!(read_back, global);
+    }
+}
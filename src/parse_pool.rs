@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use futures::future;
+use tokio::sync::{mpsc, Mutex};
+
+use crate::dedupe::TradeFilter;
+use crate::{ClientConfig, Frame, Role};
+
+/// Frames queued per worker before the read loop waits for the pool to catch up.
+const QUEUE_PER_WORKER: usize = 256;
+/// Trade ids the workers remember for dropping duplicates, e.g. the same trade from the
+/// primary and the standby connection.
+const SEEN_CAPACITY: usize = 100_000;
+
+/// A frame the pool has parsed, handed back to the client.
+pub struct Parsed {
+    pub role: Role,
+    /// The frame's text, for telling error frames from garbage when it isn't a trade.
+    pub text: String,
+    /// `None` if the frame didn't parse.
+    pub frame: Option<Frame>,
+}
+
+/// A client's `--parse-workers` tasks. The read loop submits raw text frames and gets
+/// them back parsed, in whatever order the workers finish. Duplicate trades are dropped
+/// by the workers through a set of trade ids they share, since the order the loop sees
+/// trades in no longer tells which came first. The workers stop when the pool is closed
+/// or dropped.
+pub struct ParsePool {
+    /// `None` once the pool is closed.
+    frames: Option<mpsc::Sender<(Role, String)>>,
+    parsed: mpsc::UnboundedReceiver<Parsed>,
+}
+
+impl ParsePool {
+    /// Start `workers` tasks parsing frames the way `config` does. Trades at or below
+    /// `floor`, the last trade id of a resumed run, are dropped too.
+    pub fn new(workers: usize, config: &ClientConfig, floor: Option<u64>) -> ParsePool {
+        let (frames, queue) = mpsc::channel(workers * QUEUE_PER_WORKER);
+        // Unbounded, so a worker never waits on a read loop that is itself waiting to
+        // submit. The loop reads parsed frames before new ones, which keeps it short.
+        let (results, parsed) = mpsc::unbounded_channel();
+        let queue = Arc::new(Mutex::new(queue));
+        let seen = TradeFilter::new(SEEN_CAPACITY);
+        for _ in 0..workers {
+            tokio::spawn(work(queue.clone(), results.clone(), config.clone(), seen.clone(), floor));
+        }
+        ParsePool { frames: Some(frames), parsed }
+    }
+
+    /// Queue a frame for parsing, waiting if the workers are behind.
+    pub async fn submit(&self, role: Role, text: String) {
+        if let Some(frames) = &self.frames {
+            let _ = frames.send((role, text)).await;
+        }
+    }
+
+    /// Take no more frames. The workers finish the ones already queued and then stop, after
+    /// which [`next_parsed`] returns `None`.
+    pub fn close(&mut self) {
+        self.frames = None;
+    }
+}
+
+/// The next parsed frame, pending forever without a pool. `None` once a closed pool has
+/// handed back every frame.
+pub async fn next_parsed(pool: &mut Option<ParsePool>) -> Option<Parsed> {
+    match pool {
+        Some(pool) => pool.parsed.recv().await,
+        None => future::pending().await,
+    }
+}
+
+async fn work(
+    queue: Arc<Mutex<mpsc::Receiver<(Role, String)>>>,
+    results: mpsc::UnboundedSender<Parsed>,
+    config: ClientConfig,
+    seen: TradeFilter,
+    floor: Option<u64>,
+) {
+    loop {
+        // Only waiting for a frame holds the lock; parsing runs in parallel.
+        let Some((role, text)) = queue.lock().await.recv().await else {
+            return;
+        };
+        let frame = config.parse(&text).ok();
+        if let Some(Frame::Trade(trade)) = frame {
+            if floor.is_some_and(|floor| trade.id <= floor) || !seen.claim(&config.symbol, trade.id) {
+                continue;
+            }
+        }
+        if results.send(Parsed { role, text, frame }).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use tokio::time::{Duration, Instant};
+
+    use crate::test_support;
+    use crate::trade_stream::TradeStream;
+    use crate::Source;
+
+    use super::*;
+
+    /// A feed of `count` seeded trades, parsed by `workers` workers or in the read loop.
+    fn seeded(count: u32, workers: Option<usize>) -> ClientConfig {
+        let mut config = test_support::client_config(Source::Seed, "", 60);
+        config.seed_prices = (1..=count).map(f64::from).collect();
+        config.parse_workers = workers;
+        config
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn frames_queued_at_the_deadline_are_still_yielded() {
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let mut feed = TradeStream::new("Client 1", 1, seeded(200_000, Some(1)), deadline);
+        let mut trades = 0;
+        while let Some(trade) = feed.next().await {
+            trade.unwrap();
+            trades += 1;
+        }
+        let frames = feed.stats().profile.frames;
+        assert!(frames < 200_000, "the window should end before the feed does");
+        assert_eq!(trades, frames);
+    }
+
+    /// Trades per second through a client's feed, parsed in the read loop and by pools of
+    /// one to four workers. Run with `cargo test --release bench_parse_workers -- --ignored
+    /// --nocapture`.
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore]
+    async fn bench_parse_workers() {
+        const TRADES: usize = 500_000;
+        for workers in [None, Some(1), Some(2), Some(4)] {
+            let deadline = Instant::now() + Duration::from_secs(60);
+            let mut feed = TradeStream::new("Client 1", 1, seeded(TRADES as u32, workers), deadline);
+            // The first trade waits for the seeded frames to be generated.
+            feed.next().await.unwrap().unwrap();
+            let started = Instant::now();
+            let count = feed.take(TRADES - 1).count().await;
+            let elapsed = started.elapsed();
+            assert_eq!(count, TRADES - 1);
+            let path = workers.map_or_else(|| "read loop".to_string(), |workers| format!("{workers} workers"));
+            println!("{path:>10}: {:>9.0} trades/s", count as f64 / elapsed.as_secs_f64());
+        }
+    }
+}
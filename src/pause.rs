@@ -0,0 +1,47 @@
+use std::sync::{Arc, Mutex};
+
+/// One pause in epoch milliseconds, with `end` unset while it lasts.
+#[derive(Debug, Clone, Copy)]
+struct Pause {
+    start: i64,
+    end: Option<i64>,
+}
+
+/// Whether collection is paused with `--pausable`, and every pause so far. Clones share
+/// the state, so one signal handler pauses every client.
+#[derive(Debug, Clone, Default)]
+pub struct PauseSwitch {
+    pauses: Arc<Mutex<Vec<Pause>>>,
+}
+
+impl PauseSwitch {
+    /// Pause if running and resume if paused, at `now` in epoch milliseconds. Returns
+    /// whether collection is now paused.
+    pub fn toggle(&self, now: i64) -> bool {
+        let Ok(mut pauses) = self.pauses.lock() else {
+            return false;
+        };
+        match pauses.last_mut() {
+            Some(pause) if pause.end.is_none() => {
+                pause.end = Some(now);
+                false
+            }
+            _ => {
+                pauses.push(Pause { start: now, end: None });
+                true
+            }
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pauses.lock().is_ok_and(|pauses| pauses.last().is_some_and(|pause| pause.end.is_none()))
+    }
+
+    /// Every pause as `(start, end)`, with one still going treated as ending at `now`.
+    pub fn intervals(&self, now: i64) -> Vec<(i64, i64)> {
+        self.pauses
+            .lock()
+            .map(|pauses| pauses.iter().map(|pause| (pause.start, pause.end.unwrap_or(now))).collect())
+            .unwrap_or_default()
+    }
+}
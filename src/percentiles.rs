@@ -0,0 +1,106 @@
+use tdigest::TDigest;
+
+/// Percentiles reported for each client.
+pub const QUANTILES: [(&str, f64); 3] = [("p50", 0.50), ("p90", 0.90), ("p99", 0.99)];
+
+/// Centroids kept by the t-digest; more means better accuracy and more memory.
+const DIGEST_SIZE: usize = 200;
+/// Prices buffered before they are merged into the t-digest.
+const DIGEST_BUFFER: usize = 1000;
+
+/// Tracks prices as they arrive so percentiles are available at the end of the window.
+#[derive(Debug, Clone)]
+pub enum Percentiles {
+    /// Keeps every price and sorts them at the end.
+    Exact(Vec<f64>),
+    /// Keeps a t-digest, whose memory stays bounded however many trades arrive.
+    TDigest { digest: TDigest, buffer: Vec<f64> },
+}
+
+impl Percentiles {
+    pub fn exact() -> Self {
+        Percentiles::Exact(Vec::new())
+    }
+
+    pub fn tdigest() -> Self {
+        Percentiles::TDigest {
+            digest: TDigest::new_with_size(DIGEST_SIZE),
+            buffer: Vec::with_capacity(DIGEST_BUFFER),
+        }
+    }
+
+    pub fn push(&mut self, price: f64) {
+        match self {
+            Percentiles::Exact(prices) => prices.push(price),
+            Percentiles::TDigest { digest, buffer } => {
+                buffer.push(price);
+                if buffer.len() >= DIGEST_BUFFER {
+                    *digest = digest.merge_unsorted(std::mem::take(buffer));
+                }
+            }
+        }
+    }
+
+    /// The [`QUANTILES`] of everything pushed so far, or `None` if nothing was.
+    pub fn finish(self) -> Option<[f64; 3]> {
+        match self {
+            Percentiles::Exact(mut prices) => {
+                if prices.is_empty() {
+                    return None;
+                }
+                prices.sort_by(f64::total_cmp);
+                // Nearest rank.
+                Some(QUANTILES.map(|(_, q)| {
+                    let rank = (q * prices.len() as f64).ceil() as usize;
+                    prices[rank.clamp(1, prices.len()) - 1]
+                }))
+            }
+            Percentiles::TDigest { digest, buffer } => {
+                let digest = digest.merge_unsorted(buffer);
+                if digest.is_empty() {
+                    return None;
+                }
+                Some(QUANTILES.map(|(_, q)| digest.estimate_quantile(q)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn tdigest_tracks_exact_percentiles() {
+        // A day's worth of a random walk around 60000, with the odd jump.
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut price = 60_000.0;
+        let mut exact = Percentiles::exact();
+        let mut tdigest = Percentiles::tdigest();
+        let mut prices = Vec::new();
+        for _ in 0..100_000 {
+            price += rng.gen_range(-5.0..5.0) + if rng.gen_bool(0.001) { rng.gen_range(-200.0..200.0) } else { 0.0 };
+            exact.push(price);
+            tdigest.push(price);
+            prices.push(price);
+        }
+        prices.sort_by(f64::total_cmp);
+        let exact = exact.finish().unwrap();
+        let estimated = tdigest.finish().unwrap();
+        for (((label, q), exact), estimated) in QUANTILES.iter().zip(exact).zip(estimated) {
+            // Tolerance: the estimate ranks within 0.5 percentage points of the quantile
+            // among the prices, and is within 0.05% of the exact value.
+            let rank = prices.partition_point(|&price| price <= estimated) as f64 / prices.len() as f64;
+            assert!((rank - q).abs() < 0.005, "{label}: estimate {estimated} ranks at {rank}");
+            assert!((estimated - exact).abs() / exact < 0.0005, "{label}: estimate {estimated}, exact {exact}");
+        }
+    }
+
+    #[test]
+    fn nothing_pushed_has_no_percentiles() {
+        assert_eq!(Percentiles::exact().finish(), None);
+        assert_eq!(Percentiles::tdigest().finish(), None);
+    }
+}
@@ -0,0 +1,112 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::sync::{Arc, Mutex, Weak};
+
+use chrono::Utc;
+use tokio::time::{self, Duration, Instant};
+
+use crate::rotate::{self, Rotation};
+
+/// Shared handle to the raw-frame recording. Frames are buffered in memory and a timer
+/// task flushes the buffer every `flush_interval`, so a busy feed doesn't pay for a write
+/// per frame and a crash loses at most one interval of frames.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    recording: Arc<Mutex<Recording>>,
+}
+
+#[derive(Debug)]
+struct Recording {
+    writer: BufWriter<File>,
+    path: String,
+    rotation: Rotation,
+    /// Bytes written to the current file, and when it was started.
+    written: u64,
+    started: Instant,
+}
+
+impl Recorder {
+    /// Open the recording at `path` for appending, creating it if needed, and start
+    /// flushing it periodically. Nothing already in the file is lost. With `rotation`, the
+    /// file is rolled over to a timestamped one as it grows. The flush task stops once
+    /// every handle has been dropped.
+    pub fn create(path: &str, flush_interval: Duration, rotation: Rotation) -> io::Result<Recorder> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        let recording = Recording {
+            written: file.metadata()?.len(),
+            writer: BufWriter::new(file),
+            path: path.to_string(),
+            rotation,
+            started: Instant::now(),
+        };
+        let recording = Arc::new(Mutex::new(recording));
+        tokio::spawn(flush_periodically(Arc::downgrade(&recording), flush_interval));
+        Ok(Recorder { recording })
+    }
+
+    /// Append one frame as `<receive time ms>\t<client>\t<frame>`.
+    pub fn write(&self, client: &str, frame: &str) {
+        self.append(client, format!("{}\t{client}\t{frame}\n", Utc::now().timestamp_millis()));
+    }
+
+    /// Append a `# reconnect at <time ms>\t<client>` line where the client's new
+    /// connection starts, so the frames of each connection can be told apart. The file
+    /// stays open across reconnects; the `#` lets readers skip the marker.
+    pub fn mark_reconnect(&self, client: &str) {
+        self.append(client, format!("# reconnect at {}\t{client}\n", Utc::now().timestamp_millis()));
+    }
+
+    fn append(&self, client: &str, line: String) {
+        let Ok(mut recording) = self.recording.lock() else {
+            return;
+        };
+        if let Err(e) = recording.writer.write_all(line.as_bytes()) {
+            eprintln!("Client {client}: Failed to record frame: {e}");
+            return;
+        }
+        recording.written += line.len() as u64;
+        if recording.rotation.due(recording.written, recording.started) {
+            match recording.rotate() {
+                Ok(rotated) => println!("Rotated the frame recording to {rotated}."),
+                Err(e) => eprintln!("Failed to rotate the frame recording: {e}"),
+            }
+        }
+    }
+
+    /// Write out everything buffered so far.
+    pub fn flush(&self) -> io::Result<()> {
+        match self.recording.lock() {
+            Ok(mut recording) => recording.writer.flush(),
+            Err(_) => Err(io::Error::other("recorder lock poisoned")),
+        }
+    }
+}
+
+impl Recording {
+    /// Move the frames so far to a timestamped file and carry on in an empty one.
+    fn rotate(&mut self) -> io::Result<String> {
+        self.writer.flush()?;
+        let (file, rotated) = rotate::rotate(&self.path)?;
+        self.writer = BufWriter::new(file);
+        self.written = 0;
+        self.started = Instant::now();
+        Ok(rotated)
+    }
+}
+
+async fn flush_periodically(recording: Weak<Mutex<Recording>>, flush_interval: Duration) {
+    let mut tick = time::interval_at(Instant::now() + flush_interval, flush_interval);
+    loop {
+        tick.tick().await;
+        let Some(shared) = recording.upgrade() else {
+            return;
+        };
+        let flushed = match shared.lock() {
+            Ok(mut recording) => recording.writer.flush(),
+            Err(_) => continue,
+        };
+        if let Err(e) = flushed {
+            eprintln!("Failed to flush the frame recording: {e}");
+        }
+    }
+}
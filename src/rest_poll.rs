@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+
+use chrono::Utc;
+use futures::stream::{self, StreamExt};
+use rand::Rng;
+use serde::Deserialize;
+use tokio::time::{self, Duration};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use crate::{FrameStream, RetryPolicy};
+
+/// Binance REST endpoint listing a symbol's most recent trades.
+pub const TRADES_URL: &str = "https://api.binance.com/api/v3/trades";
+/// Most trades a single request returns.
+const LIMIT: u32 = 1000;
+
+/// One entry of the `/api/v3/trades` response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RestTrade {
+    id: u64,
+    price: String,
+    qty: String,
+    time: i64,
+}
+
+struct PollState {
+    /// Client the frames are for, to tag log lines.
+    client: String,
+    http: reqwest::Client,
+    symbol: String,
+    /// Highest trade id handed out so far.
+    last_id: Option<u64>,
+    /// Trades older than this predate the window and are left out.
+    since: i64,
+    backoff: Duration,
+    pending: VecDeque<Message>,
+}
+
+/// Poll the recent trades of `symbol` every `interval` (plus up to `jitter`) and turn each
+/// new trade into a Binance-style trade frame, so REST prices go through the same parsing
+/// as the WebSocket feed. Failed requests are retried with `retry`'s backoff. Only trades
+/// made after the stream is created are produced; more than 1000 trades between two polls
+/// leave a gap, which is logged.
+pub fn frames(client: &str, symbol: &str, interval: Duration, jitter: Duration, retry: RetryPolicy) -> FrameStream {
+    let state = PollState {
+        client: client.to_string(),
+        http: reqwest::Client::new(),
+        symbol: symbol.to_uppercase(),
+        last_id: None,
+        since: Utc::now().timestamp_millis(),
+        backoff: retry.initial_backoff,
+        pending: VecDeque::new(),
+    };
+    stream::unfold(state, move |mut state| async move {
+        while state.pending.is_empty() {
+            let pause = if jitter.is_zero() { interval } else { interval + rand::thread_rng().gen_range(Duration::ZERO..=jitter) };
+            time::sleep(pause).await;
+            match poll(&state.http, &state.symbol).await {
+                Ok(trades) => {
+                    state.backoff = retry.initial_backoff;
+                    queue_new_trades(&mut state, trades);
+                }
+                Err(e) => {
+                    let delay = retry.delay(state.backoff);
+                    eprintln!("Client {}: REST poll failed: {e}; retrying in {:.1}s.", state.client, delay.as_secs_f64());
+                    time::sleep(delay).await;
+                    state.backoff = retry.next_backoff(state.backoff);
+                }
+            }
+        }
+        let frame = state.pending.pop_front()?;
+        Some((Ok(frame), state))
+    })
+    .boxed()
+}
+
+async fn poll(client: &reqwest::Client, symbol: &str) -> Result<Vec<RestTrade>, Box<dyn std::error::Error + Send + Sync>> {
+    let body = client
+        .get(TRADES_URL)
+        .query(&[("symbol", symbol.to_string()), ("limit", LIMIT.to_string())])
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    Ok(serde_json::from_str(&body)?)
+}
+
+/// Queue frames for the trades newer than any seen so far, oldest first.
+fn queue_new_trades(state: &mut PollState, mut trades: Vec<RestTrade>) {
+    trades.sort_by_key(|trade| trade.id);
+    if let (Some(last_id), Some(oldest)) = (state.last_id, trades.first()) {
+        if oldest.id > last_id + 1 {
+            eprintln!(
+                "Client {}: Missed {} trades between REST polls; poll more often.",
+                state.client,
+                oldest.id - last_id - 1
+            );
+        }
+    }
+    for trade in trades {
+        if state.last_id.is_some_and(|last_id| trade.id <= last_id) || trade.time < state.since {
+            continue;
+        }
+        state.last_id = Some(trade.id);
+        state.pending.push_back(Message::Text(format!(
+            r#"{{"e":"trade","E":{time},"s":"{symbol}","t":{id},"p":"{price}","q":"{qty}","T":{time},"m":false,"M":true}}"#,
+            time = trade.time,
+            symbol = state.symbol,
+            id = trade.id,
+            price = trade.price,
+            qty = trade.qty,
+        )));
+    }
+}
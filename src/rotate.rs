@@ -0,0 +1,83 @@
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+use chrono::Utc;
+use tokio::time::{Duration, Instant};
+
+/// When `--rotate-size` and `--rotate-interval` roll a growing file over to a new one.
+/// Unset limits never trigger.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rotation {
+    pub max_bytes: Option<u64>,
+    pub interval: Option<Duration>,
+}
+
+impl Rotation {
+    /// Whether a file that has grown to `size` bytes since `started` is due to be rotated.
+    pub fn due(&self, size: u64, started: Instant) -> bool {
+        self.max_bytes.is_some_and(|max| size >= max) || self.interval.is_some_and(|interval| started.elapsed() >= interval)
+    }
+}
+
+/// Move what has been written to `path` into a timestamped file next to it, e.g.
+/// `frames.20240101T120000.000Z.log`, and start `path` over empty. The old contents are
+/// hard-linked to the new name and an empty file is renamed over `path`, both atomic, so
+/// a reader opening `path` gets either the complete old file or the new one. Returns the
+/// new file, open for appending, and the name the old contents were moved to.
+pub fn rotate(path: &str) -> io::Result<(File, String)> {
+    let rotated = rotated_name(path);
+    fs::hard_link(path, &rotated)?;
+    let fresh = format!("{path}.tmp");
+    let file = OpenOptions::new().append(true).create(true).truncate(false).open(&fresh)?;
+    file.set_len(0)?;
+    fs::rename(&fresh, path)?;
+    Ok((file, rotated))
+}
+
+/// `path` with the current UTC time inserted before its extension.
+fn rotated_name(path: &str) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%S%.3fZ");
+    let file = Path::new(path);
+    match (file.file_stem(), file.extension()) {
+        (Some(stem), Some(extension)) => file
+            .with_file_name(format!("{}.{stamp}.{}", stem.to_string_lossy(), extension.to_string_lossy()))
+            .to_string_lossy()
+            .into_owned(),
+        _ => format!("{path}.{stamp}"),
+    }
+}
+
+/// Parse a `--rotate-size` such as `100MB`, `512K` or `2048`: bytes, or a number with a
+/// K, M or G suffix (binary multiples, an optional trailing `B` and any case).
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let upper = value.trim().to_uppercase();
+    let digits = upper.strip_suffix('B').unwrap_or(&upper);
+    let (number, multiplier) = match digits.char_indices().last() {
+        Some((at, 'K')) => (&digits[..at], 1 << 10),
+        Some((at, 'M')) => (&digits[..at], 1 << 20),
+        Some((at, 'G')) => (&digits[..at], 1 << 30),
+        _ => (digits, 1),
+    };
+    match number.trim().parse::<u64>() {
+        Ok(number) if number > 0 => number.checked_mul(multiplier).ok_or_else(|| format!("`{value}` is too large")),
+        _ => Err(format!("`{value}` is not a size like 100MB, 512K or 4096")),
+    }
+}
+
+/// Parse a `--rotate-interval` such as `1h`, `30m`, `90s` or `1d`.
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, unit) = value.split_at(value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len()));
+    let secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("`{value}` is not an interval like 90s, 30m, 1h or 1d")),
+    };
+    match number.parse::<u64>() {
+        Ok(number) if number > 0 => Ok(Duration::from_secs(number * secs)),
+        _ => Err(format!("`{value}` is not an interval like 90s, 30m, 1h or 1d")),
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::rotate::{self, Rotation};
+
+/// One line of the `--append-summary` file: what a run (or `--watch` cycle) produced.
+#[derive(Debug, Serialize)]
+pub struct RunSummary {
+    /// RFC 3339 UTC timestamps.
+    pub started_at: String,
+    pub finished_at: String,
+    pub symbols: Vec<String>,
+    /// Global average when a single symbol was tracked.
+    pub global_average: Option<f64>,
+    /// Global average per symbol.
+    pub global_averages: BTreeMap<String, f64>,
+    pub clients: usize,
+    /// Clients that produced an average.
+    pub clients_reported: usize,
+    /// SHA-256 of the effective configuration (as `--print-config` shows it), so runs
+    /// with the same settings can be grouped.
+    pub config_hash: String,
+    /// Whether the run was cut short by Ctrl-C.
+    pub interrupted: bool,
+}
+
+/// Append `summary` to `path` as one JSON line, creating the file if needed. The line is
+/// written with a single append, so concurrent runs sharing a file don't interleave.
+///
+/// When `rotation` says the file is due, given it was started at `started`, it is first
+/// rolled over to a timestamped file and `started` is reset.
+pub fn append(path: &str, summary: &RunSummary, rotation: &Rotation, started: &mut Instant) -> io::Result<()> {
+    let size = fs::metadata(path).map_or(0, |metadata| metadata.len());
+    if size > 0 && rotation.due(size, *started) {
+        let (_, rotated) = rotate::rotate(path)?;
+        println!("Rotated {path} to {rotated}.");
+        *started = Instant::now();
+    }
+    let mut line = serde_json::to_string(summary)?;
+    line.push('\n');
+    OpenOptions::new().append(true).create(true).open(path)?.write_all(line.as_bytes())
+}
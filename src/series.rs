@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+
+use chrono::DateTime;
+use serde::Serialize;
+
+use crate::JsonStyle;
+
+/// File `--aggregate-window` writes.
+pub const SERIES_PATH: &str = "global_series.json";
+
+/// The rolling global averages of a run, written to `global_series.json`.
+#[derive(Debug, Serialize)]
+pub struct GlobalSeries {
+    pub window_secs: u64,
+    /// One entry per symbol.
+    pub symbols: Vec<SymbolSeries>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SymbolSeries {
+    pub symbol: String,
+    /// Every window from the symbol's first trade to its last, oldest first.
+    pub windows: Vec<Window>,
+}
+
+/// One window of a symbol's series. A window no client traded in has `clients` 0 and a
+/// `null` average.
+#[derive(Debug, Serialize)]
+pub struct Window {
+    /// Start of the window in milliseconds since the Unix epoch, and as RFC 3339 UTC.
+    pub start_ms: i64,
+    pub start: String,
+    /// Clients with a trade in the window.
+    pub clients: usize,
+    /// Mean of those clients' averages over the window.
+    pub average: Option<f64>,
+}
+
+/// Split `clients`' per-second averages (`(unix second, average)`, one list per client)
+/// into windows of `window_secs` aligned to the epoch. Each client's average over a window
+/// is the mean of its per-second averages in it, and the window's average is the mean
+/// across clients.
+pub fn windows(clients: &[&[(i64, f64)]], window_secs: u64) -> Vec<Window> {
+    let window = window_secs.max(1) as i64;
+    // Per window start, each client's per-second averages.
+    let mut by_window: BTreeMap<i64, Vec<Vec<f64>>> = BTreeMap::new();
+    for (client, buckets) in clients.iter().enumerate() {
+        for &(second, average) in buckets.iter() {
+            let per_client = by_window.entry(second.div_euclid(window) * window).or_default();
+            per_client.resize(clients.len(), Vec::new());
+            per_client[client].push(average);
+        }
+    }
+    let (Some(&first), Some(&last)) = (by_window.keys().next(), by_window.keys().next_back()) else {
+        return Vec::new();
+    };
+    (first..=last)
+        .step_by(window as usize)
+        .map(|start| {
+            let averages: Vec<f64> = by_window
+                .get(&start)
+                .into_iter()
+                .flatten()
+                .filter(|seconds| !seconds.is_empty())
+                .map(|seconds| seconds.iter().sum::<f64>() / seconds.len() as f64)
+                .collect();
+            Window {
+                start_ms: start * 1000,
+                start: DateTime::from_timestamp(start, 0).map_or_else(String::new, |time| time.to_rfc3339()),
+                clients: averages.len(),
+                average: (!averages.is_empty()).then(|| averages.iter().sum::<f64>() / averages.len() as f64),
+            }
+        })
+        .collect()
+}
+
+/// Write `series` to `path` as JSON in the given style.
+pub fn write(path: &str, series: &GlobalSeries, style: JsonStyle) -> Result<(), Box<dyn std::error::Error>> {
+    style.write(BufWriter::new(File::create(path)?), series)?;
+    Ok(())
+}
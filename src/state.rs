@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Write};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+/// A client's accumulated statistics, checkpointed so a restarted run can carry on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientState {
+    pub symbol: String,
+    pub source: String,
+    /// Trades counted so far, across every resumed run.
+    pub count: u64,
+    /// Running mean price of those trades.
+    pub mean: f64,
+    /// Highest trade id counted, so trades seen before the restart aren't counted twice.
+    pub last_trade_id: Option<u64>,
+}
+
+/// Contents of the `--state-file`: one entry per client, keyed by client name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct State {
+    pub clients: BTreeMap<String, ClientState>,
+}
+
+/// Shared handle to the state file. Clients record their state into it and every
+/// checkpoint rewrites the whole file.
+#[derive(Debug, Clone)]
+pub struct StateFile {
+    path: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl StateFile {
+    /// Keep `state` at `path`: empty for a fresh run, or what `--resume` loaded, so clients
+    /// that haven't checkpointed yet keep their earlier entries. Nothing is written until
+    /// the first checkpoint.
+    pub fn new(path: &str, state: State) -> StateFile {
+        StateFile { path: path.to_string(), state: Arc::new(Mutex::new(state)) }
+    }
+
+    /// Replace `client`'s entry and write the file out.
+    pub fn checkpoint(&self, client: &str, client_state: ClientState) -> io::Result<()> {
+        let mut state = self.state.lock().map_err(|_| io::Error::other("state lock poisoned"))?;
+        state.clients.insert(client.to_string(), client_state);
+        write(&self.path, &state)
+    }
+}
+
+/// Read a state file written by an earlier run.
+pub fn load(path: &str) -> Result<State, Box<dyn std::error::Error>> {
+    Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+}
+
+fn write(path: &str, state: &State) -> io::Result<()> {
+    write_atomic(path, &serde_json::to_vec_pretty(state)?)
+}
+
+/// Write to a temporary file next to `path` and rename it over `path`, so a crash
+/// mid-write leaves the previous contents intact.
+pub fn write_atomic(path: &str, contents: &[u8]) -> io::Result<()> {
+    let tmp = format!("{path}.tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(tmp, path)
+}
@@ -0,0 +1,47 @@
+use serde::Serialize;
+
+/// One row of the `--symbols-stats-csv` table. A symbol no client produced an average for
+/// gets `status` `no_data` and empty statistics.
+#[derive(Debug, Serialize)]
+pub struct SymbolRow {
+    pub symbol: String,
+    /// `ok` or `no_data`.
+    pub status: &'static str,
+    /// Clients that reported an average for the symbol.
+    pub clients: usize,
+    /// Trades behind those averages.
+    pub trades: usize,
+    /// The global average.
+    pub average: Option<f64>,
+    /// Lowest and highest trade price any client saw.
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// `max - min`.
+    pub spread: Option<f64>,
+}
+
+impl SymbolRow {
+    pub fn new(symbol: &str, clients: usize, trades: usize, average: Option<f64>, extremes: Option<(f64, f64)>) -> SymbolRow {
+        SymbolRow {
+            symbol: symbol.to_string(),
+            status: if average.is_some() { "ok" } else { "no_data" },
+            clients,
+            trades,
+            average,
+            min: extremes.map(|(low, _)| low),
+            max: extremes.map(|(_, high)| high),
+            spread: extremes.map(|(low, high)| high - low),
+        }
+    }
+}
+
+/// Write `rows` to `path` as CSV, under the header
+/// `symbol,status,clients,trades,average,min,max,spread`.
+pub fn write(path: &str, rows: &[SymbolRow]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,185 @@
+//! Helpers for tests that run clients end to end: a local WebSocket server to point them
+//! at, and a client configuration with every optional feature turned off. Also sample
+//! saved data for the file formats to round-trip.
+
+use std::future::Future;
+
+use chrono::Utc;
+use rust_decimal::Decimal;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::{Message, WebSocketConfig};
+use tokio_tungstenite::WebSocketStream;
+use tokio_util::sync::CancellationToken;
+
+use crate::clock::ServerClock;
+use crate::handshake::Handshake;
+use crate::metrics::RunMetrics;
+use crate::msgpack::{ClientData, GlobalData};
+use crate::{BackoffStrategy, ClientConfig, DisplayTz, PriceBounds, RetryPolicy, Sink, Source, Stat, StreamType, Trade};
+
+/// Start a WebSocket server on a free local port and return its `ws://` URL. Every
+/// connection it accepts is handed to `session` along with its number, counting from 0.
+/// Returning from `session` drops the connection without a close handshake.
+pub async fn serve<F, Fut>(session: F) -> String
+where
+    F: Fn(usize, WebSocketStream<TcpStream>) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        for number in 0.. {
+            let Ok((socket, _)) = listener.accept().await else {
+                return;
+            };
+            if let Ok(ws) = tokio_tungstenite::accept_async(socket).await {
+                tokio::spawn(session(number, ws));
+            }
+        }
+    });
+    url
+}
+
+/// A Binance trade frame for trade `id` at `price`, stamped with the current time.
+pub fn trade(id: u64, price: f64) -> Message {
+    fill(id, price, 1.0)
+}
+
+/// [`trade`] for a quantity of `qty`.
+pub fn fill(id: u64, price: f64, qty: f64) -> Message {
+    let now = Utc::now().timestamp_millis();
+    Message::Text(format!(
+        r#"{{"e":"trade","E":{now},"s":"BTCUSDT","t":{id},"p":"{price}","q":"{qty}","T":{now},"m":false,"M":true}}"#
+    ))
+}
+
+/// A Coinbase `match` frame for trade `id` at `price`, stamped with the current time.
+pub fn coinbase_match(id: u64, price: f64) -> Message {
+    let now = Utc::now().to_rfc3339();
+    Message::Text(format!(
+        r#"{{"type":"match","trade_id":{id},"product_id":"BTC-USDT","price":"{price}","size":"1","side":"buy","time":"{now}"}}"#
+    ))
+}
+
+/// A client that reads `source` from `url` for `secs` seconds and saves nothing. Retries
+/// are quick and unjittered so reconnects don't slow tests down.
+pub fn client_config(source: Source, url: &str, secs: u64) -> ClientConfig {
+    ClientConfig {
+        name: "1".to_string(),
+        duration: secs,
+        print_every: 0,
+        tz: DisplayTz::Local,
+        precision: 4,
+        symbol: "BTCUSDT".to_string(),
+        source,
+        sink: Sink::Discard,
+        url: url.to_string(),
+        failover_url: None,
+        retry: RetryPolicy {
+            strategy: BackoffStrategy::Fixed,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_millis(50),
+            jitter: false,
+        },
+        ws_config: WebSocketConfig::default(),
+        handshake: Handshake::default(),
+        live: None,
+        max_reconnects: 10,
+        idle_timeout: None,
+        first_frame_timeout: None,
+        latency_warn_ms: None,
+        stale_threshold: 100,
+        trim_end: None,
+        normalize: false,
+        tick_size: None,
+        price_scale: None,
+        stat: Stat::Mean,
+        decimal: false,
+        percentile_mode: None,
+        input: None,
+        seed_prices: Vec::new(),
+        shard: None,
+        shutdown: CancellationToken::new(),
+        abort: None,
+        batch_size: None,
+        batch_interval: Duration::from_secs(1),
+        sink_buffer: 100_000,
+        strict_json: None,
+        recorder: None,
+        checksum: false,
+        aggregator_saves: false,
+        profile: false,
+        state_file: None,
+        checkpoint_interval: Duration::from_secs(10),
+        resume: None,
+        clock: ServerClock::default(),
+        dedupe: None,
+        bounds: PriceBounds::default(),
+        filter: None,
+        strict_range: false,
+        max_samples: None,
+        trade_cap: None,
+        on_the_fly: None,
+        pause: None,
+        group: None,
+        parse_workers: None,
+        stats_interval: None,
+        checkpoint: None,
+        metrics: RunMetrics::start(),
+    }
+}
+
+/// [`client_config`] for the Binance trade stream.
+pub fn binance_config(url: &str, secs: u64) -> ClientConfig {
+    client_config(Source::Binance { stream: StreamType::Trade }, url, secs)
+}
+
+/// A path for `name` in the system temp directory, unique to this test process, with
+/// any file left there by an earlier run removed.
+pub fn temp_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("multi_client_{}_{name}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path.to_str().unwrap().to_string()
+}
+
+/// Prices that only survive a save exactly if floats are written in full: a sum with
+/// rounding error, a repeating fraction, the smallest and largest f64 and a price with
+/// more digits than the exchange quotes.
+pub const AWKWARD_PRICES: [f64; 5] = [0.1 + 0.2, 1.0 / 3.0, 5e-324, f64::MAX, 60_000.123_456_789_01];
+
+/// A client's saved data with a trade at each of [`AWKWARD_PRICES`].
+pub fn client_data() -> ClientData {
+    let trades = AWKWARD_PRICES
+        .iter()
+        .zip(1..)
+        .map(|(&price, id)| Trade { id, price, exact_price: Decimal::ZERO, qty: 0.25, time: 1_700_000_000_000 + id as i64, event_time: 1_700_000_000_001 + id as i64 })
+        .collect();
+    ClientData {
+        id: 1,
+        label: Some("primary".to_string()),
+        symbol: "BTCUSDT".to_string(),
+        source: "binance".to_string(),
+        trades,
+        average: AWKWARD_PRICES[0],
+        method: "mean".to_string(),
+        normalized_open: None,
+        tick_size: Some(0.01),
+        price_scale: None,
+        ticker: None,
+        longest_identical_run: 1,
+        truncated_at: None,
+    }
+}
+
+/// The aggregator's saved data, with [`AWKWARD_PRICES`] as client averages.
+pub fn global_data() -> GlobalData {
+    GlobalData {
+        symbol: "BTCUSDT".to_string(),
+        client_averages: AWKWARD_PRICES.to_vec(),
+        global_average: AWKWARD_PRICES[4],
+        method: "trade-weighted mean of client averages".to_string(),
+        client_variance: Some(AWKWARD_PRICES[1]),
+        client_cv_pct: None,
+    }
+}
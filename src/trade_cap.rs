@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use futures::future;
+use tokio_util::sync::CancellationToken;
+
+/// The `--max-trades-total` cap on trades counted across every client over the whole
+/// run. Clones share the count, so whichever client reaches the cap ends every window.
+#[derive(Debug, Clone)]
+pub struct TradeCap {
+    max: u64,
+    counted: Arc<AtomicU64>,
+    reached: CancellationToken,
+}
+
+impl TradeCap {
+    pub fn new(max: u64) -> TradeCap {
+        TradeCap { max, counted: Arc::new(AtomicU64::new(0)), reached: CancellationToken::new() }
+    }
+
+    /// Count one trade. False if the cap was already used up, so the trade is dropped.
+    /// The trade that uses it up logs the early finish and stops every client.
+    pub fn count(&self) -> bool {
+        let counted = self.counted.fetch_add(1, Ordering::Relaxed) + 1;
+        if counted == self.max {
+            println!("Trade cap: {counted} trades counted across all clients (--max-trades-total); finishing every client early.");
+            self.reached.cancel();
+        }
+        counted <= self.max
+    }
+
+    pub fn is_reached(&self) -> bool {
+        self.reached.is_cancelled()
+    }
+}
+
+/// Resolves once the cap is reached, and never without one.
+pub async fn reached(cap: Option<&TradeCap>) {
+    match cap {
+        Some(cap) => cap.reached.cancelled().await,
+        None => future::pending().await,
+    }
+}
@@ -0,0 +1,50 @@
+use evalexpr::{ContextWithMutableVariables, HashMapContext, Node, Value};
+
+use crate::Trade;
+
+/// Trade fields a `--filter` expression can use.
+const FIELDS: [&str; 5] = ["price", "qty", "id", "time", "event_time"];
+
+/// A compiled `--filter` expression such as `qty > 0.5 && price < 65000`, deciding per
+/// trade whether it counts. Expressions use `evalexpr` syntax over the fields in
+/// [`FIELDS`]; `price` and `qty` are floats, the rest integers.
+#[derive(Debug, Clone)]
+pub struct TradeExpr {
+    source: String,
+    tree: Node,
+}
+
+impl TradeExpr {
+    /// Compile `source`, checking it against a sample trade so a syntax error, an unknown
+    /// field or an expression that isn't true or false is caught before any client starts.
+    pub fn parse(source: &str) -> Result<TradeExpr, String> {
+        let tree = evalexpr::build_operator_tree(source).map_err(|e| format!("invalid --filter {source:?}: {e}"))?;
+        if let Some(name) = tree.iter_variable_identifiers().find(|name| !FIELDS.contains(name)) {
+            return Err(format!("invalid --filter {source:?}: unknown field {name:?}; use {}", FIELDS.join(", ")));
+        }
+        let expr = TradeExpr { source: source.to_string(), tree };
+        let sample = Trade { id: 1, price: 1.0, exact_price: Default::default(), qty: 1.0, time: 0, event_time: 0 };
+        expr.eval(&sample).map_err(|e| format!("invalid --filter {source:?}: {e}"))?;
+        Ok(expr)
+    }
+
+    /// Whether `trade` passes. A trade the expression can't be evaluated on, e.g. one that
+    /// divides by a zero field, doesn't.
+    pub fn matches(&self, trade: &Trade) -> bool {
+        self.eval(trade).unwrap_or(false)
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    fn eval(&self, trade: &Trade) -> Result<bool, evalexpr::EvalexprError> {
+        let mut context = HashMapContext::new();
+        context.set_value("price".into(), Value::Float(trade.price))?;
+        context.set_value("qty".into(), Value::Float(trade.qty))?;
+        context.set_value("id".into(), Value::Int(trade.id as i64))?;
+        context.set_value("time".into(), Value::Int(trade.time))?;
+        context.set_value("event_time".into(), Value::Int(trade.event_time))?;
+        self.tree.eval_boolean_with_context(&context)
+    }
+}
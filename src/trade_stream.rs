@@ -0,0 +1,490 @@
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll};
+
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use futures::FutureExt;
+use tokio::time::{self, Instant};
+use tokio_tungstenite::tungstenite::{protocol::Message, Error as WsError};
+
+use crate::parse_pool::{self, ParsePool};
+use crate::{
+    binance_error, connect_with_retry, lap, miniticker, next_message, open_feed, wait_for_connection, ClientConfig, Connection, ConnectionStats, Frame,
+    MiniTicker, PipelineProfile, PriceError, Role, Source, StreamType, Trade,
+};
+
+/// The trades of one feed as a [`Stream`], so they can be filtered, limited and combined
+/// with the `futures` combinators instead of a hand-written read loop. Clients read their
+/// feed through it. Frames are parsed with the configured source, on the `--parse-workers`
+/// pool if there is one, and pings are answered by the WebSocket layer. A dropped or idle
+/// connection is reopened with the usual retry policy, or replaced by the warm standby
+/// with `--failover-url`, and trades seen on both connections are yielded once. Frames
+/// that aren't trades are counted in [`FeedStats`] and skipped.
+///
+/// The stream ends at `deadline`, on shutdown, or once `--max-reconnects` is used up. At
+/// the deadline and on shutdown it first yields the trades still in the parse pool, since
+/// they were received in time. An error that ends the feed early, such as a fatal Binance
+/// error frame ([`PriceError::Rejected`]), is yielded as its last item.
+///
+/// ```ignore
+/// let deadline = Instant::now() + Duration::from_secs(60);
+/// let trades: Vec<Trade> = TradeStream::new("Example", 1, config, deadline)
+///     .filter_map(|trade| async move { trade.ok() })
+///     .take(100)
+///     .collect()
+///     .await;
+/// ```
+///
+/// Doc examples don't run in a binary crate; `the_first_hundred_trades` below runs this one.
+pub(crate) struct TradeStream {
+    inner: BoxStream<'static, Result<Trade, PriceError>>,
+    stats: Arc<Mutex<FeedStats>>,
+}
+
+/// What a feed did besides yielding trades. The stream keeps it up to date as it reads, so
+/// it can be looked at while the stream runs as well as after it ends.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FeedStats {
+    pub connection: ConnectionStats,
+    /// Frames that were neither trades nor valid JSON.
+    pub parse_errors: u64,
+    /// Frames that were valid JSON but not trades.
+    pub non_trade_frames: u64,
+    /// Latest 24h snapshot, with `--stream-type miniticker`.
+    pub ticker: Option<MiniTicker>,
+    /// Frames received and the time spent reading and parsing them; only timed with
+    /// `--profile`.
+    pub profile: PipelineProfile,
+}
+
+struct State {
+    /// Prefix of log lines, e.g. `Client 1`.
+    label: String,
+    id: usize,
+    config: ClientConfig,
+    start_time: Instant,
+    deadline: Instant,
+    primary: Option<Connection>,
+    standby: Option<Connection>,
+    standby_connect: Option<BoxFuture<'static, Option<Connection>>>,
+    pool: Option<ParsePool>,
+    /// Set once the window is over, when only the frames still in the pool are left.
+    draining: bool,
+    last_trade_id: Option<u64>,
+    /// When the primary connection last produced a trade (or connected).
+    last_data: Instant,
+    got_trade: bool,
+    stats: Arc<Mutex<FeedStats>>,
+    done: bool,
+}
+
+impl TradeStream {
+    /// Trades from `config`'s feed until `deadline`. `id` seeds the mock source, as it
+    /// does for clients.
+    pub fn new(label: &str, id: usize, config: ClientConfig, deadline: Instant) -> TradeStream {
+        let stats = Arc::new(Mutex::new(FeedStats::default()));
+        let start_time = Instant::now();
+        let last_trade_id = config.resumed_trade_id();
+        let state = State {
+            label: label.to_string(),
+            id,
+            pool: config.parse_workers.map(|workers| ParsePool::new(workers, &config, last_trade_id)),
+            config,
+            start_time,
+            deadline,
+            primary: None,
+            standby: None,
+            standby_connect: None,
+            draining: false,
+            last_trade_id,
+            last_data: start_time,
+            got_trade: false,
+            stats: stats.clone(),
+            done: false,
+        };
+        TradeStream { inner: stream::unfold(state, next_trade).boxed(), stats }
+    }
+
+    /// Call `hook` with every trade as it is yielded, so code built on the stream can
+    /// react to trades (alerts, custom aggregates) without writing its own loop. The hook
+    /// runs inside the receive loop: while it runs no frames are read, so it should only
+    /// update counters or hand the trade to a channel, never wait on I/O. A client runs on
+    /// a hooked stream with `run_client`.
+    ///
+    /// ```ignore
+    /// // Count trades above a threshold while collecting the first 100.
+    /// let above = Arc::new(AtomicU64::new(0));
+    /// let counter = above.clone();
+    /// let trades: Vec<Trade> = TradeStream::new("Example", 1, config, deadline)
+    ///     .on_trade(move |trade| {
+    ///         if trade.price > 65_000.0 {
+    ///             counter.fetch_add(1, Ordering::Relaxed);
+    ///         }
+    ///     })
+    ///     .filter_map(|trade| async move { trade.ok() })
+    ///     .take(100)
+    ///     .collect()
+    ///     .await;
+    /// println!("{} of {} trades were above 65000", above.load(Ordering::Relaxed), trades.len());
+    /// ```
+    ///
+    /// `trades_above_a_threshold_are_counted` below runs this example.
+    pub fn on_trade(self, mut hook: impl FnMut(&Trade) + Send + 'static) -> TradeStream {
+        let inner = self.inner.inspect(move |trade| {
+            if let Ok(trade) = trade {
+                hook(trade);
+            }
+        });
+        TradeStream { inner: inner.boxed(), stats: self.stats }
+    }
+
+    /// The feed's statistics so far.
+    pub fn stats(&self) -> FeedStats {
+        self.stats.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+}
+
+impl Stream for TradeStream {
+    type Item = Result<Trade, PriceError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+impl State {
+    fn stats(&self) -> MutexGuard<'_, FeedStats> {
+        self.stats.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Record a new primary connection, marking reconnects in the recording.
+    fn on_connect(&mut self, conn: Connection) {
+        self.last_data = Instant::now();
+        let reconnected = self.stats().connection.on_connect(self.start_time);
+        if let (true, Some(recorder)) = (reconnected, &self.config.recorder) {
+            recorder.mark_reconnect(&self.config.name);
+        }
+        self.primary = Some(conn);
+    }
+
+    /// The window is over: read no more frames, but yield the ones still in the pool,
+    /// since they were received within it. False if there is no pool to wait for.
+    fn drain(&mut self) -> bool {
+        let Some(pool) = &mut self.pool else {
+            return false;
+        };
+        pool.close();
+        self.draining = true;
+        true
+    }
+}
+
+/// A text frame from the feed and the connection it came on. `parsed` is the pool's
+/// result for a frame that has been through it.
+struct Text {
+    role: Role,
+    text: String,
+    parsed: Option<Option<Frame>>,
+}
+
+/// Read until the next text frame, opening and replacing connections along the way. `Err`
+/// once the feed is over, with the error that ended it early, if any.
+async fn next_text(state: &mut State) -> Result<Text, Option<PriceError>> {
+    let label = state.label.clone();
+    loop {
+        if state.draining {
+            let parsed = parse_pool::next_parsed(&mut state.pool).await.ok_or(None)?;
+            return Ok(Text { role: parsed.role, text: parsed.text, parsed: Some(parsed.frame) });
+        }
+        if state.primary.is_none() {
+            let capped = {
+                let stats = state.stats();
+                stats.connection.time_to_connect.is_some() && stats.connection.reconnects >= state.config.max_reconnects
+            };
+            if let Some(standby) = state.standby.take() {
+                println!("{label}: Failing over to standby {}.", standby.url);
+                state.on_connect(standby);
+            } else if capped {
+                // Only opening a new connection counts against the cap; a connected
+                // standby is always promoted.
+                eprintln!("{label}: Reached the limit of {} reconnects; finishing with the data collected so far.", state.config.max_reconnects);
+                state.stats().connection.reconnect_cap_reached = true;
+                return Err(None);
+            } else {
+                let opened = open_feed(state.id, &state.config, state.deadline);
+                let Some(conn) = state.config.shutdown.run_until_cancelled(opened).await.flatten() else {
+                    let connected = state.stats().connection.time_to_connect.is_some();
+                    if let Some(abort) = state.config.abort.as_ref().filter(|_| !connected && !state.config.shutdown.is_cancelled()) {
+                        eprintln!("{label}: Could not connect; aborting the run (--fail-fast).");
+                        abort.cancel();
+                        return Err(Some(PriceError::ConnectFailed));
+                    }
+                    return Err(None);
+                };
+                // Every connection, including a reconnect, sends the subscription again; a
+                // new socket starts with none.
+                match state.config.source.subscription(&state.config.symbol) {
+                    Some(_) if state.stats().connection.time_to_connect.is_some() => println!("{label}: Reconnected to {} and re-subscribed.", conn.url),
+                    _ => println!("{label}: Connected to {}.", conn.url),
+                }
+                state.on_connect(conn);
+            }
+        }
+        let Some(conn) = state.primary.as_mut() else {
+            return Err(None);
+        };
+
+        let config = &state.config;
+        if let Some(failover_url) = &config.failover_url {
+            if state.standby.is_none() && state.standby_connect.is_none() {
+                // The standby always targets whichever endpoint the primary is not using.
+                let url = if conn.url == config.url { failover_url.clone() } else { config.url.clone() };
+                let subscription = config.source.subscription(&config.symbol);
+                state.standby_connect = Some(
+                    connect_with_retry(config.name.clone(), vec![url], subscription, config.retry, config.ws_config, config.handshake.clone(), state.deadline)
+                        .boxed(),
+                );
+            }
+        }
+
+        let read_started = config.profile.then(Instant::now);
+        let (role, message, parsed) = tokio::select! {
+            biased;
+            _ = time::sleep_until(state.deadline) => {
+                if state.drain() {
+                    continue;
+                }
+                return Err(None);
+            }
+            _ = config.shutdown.cancelled() => {
+                println!("{label}: Shutdown requested, finishing with the data collected so far.");
+                if state.drain() {
+                    continue;
+                }
+                return Err(None);
+            }
+            // Parsed frames are taken before new ones, so the pool's backlog stays short.
+            Some(parsed) = parse_pool::next_parsed(&mut state.pool) => (parsed.role, Some(Ok(Message::Text(parsed.text))), Some(parsed.frame)),
+            message = conn.frames.next() => (Role::Primary, message, None),
+            message = next_message(&mut state.standby) => (Role::Standby, message, None),
+            // Until the first trade, `last_data` is when the connection was made.
+            _ = time::sleep_until(state.last_data + config.first_frame_timeout.unwrap_or_default()), if !state.got_trade && config.first_frame_timeout.is_some() => {
+                eprintln!(
+                    "{label}: Connected but no trade arrived within {:.1}s (--first-frame-timeout); stopping.",
+                    state.last_data.elapsed().as_secs_f64()
+                );
+                return Err(config.first_frame_timeout.map(PriceError::NoFirstTrade));
+            }
+            // The deadline branch above still wins if both are due, so this never extends the run.
+            _ = time::sleep_until(state.last_data + config.idle_timeout.unwrap_or_default()), if config.idle_timeout.is_some() => {
+                eprintln!("{label}: No trades for {:.1}s (idle timeout). Reconnecting...", state.last_data.elapsed().as_secs_f64());
+                state.stats().connection.on_disconnect("idle timeout".to_string());
+                state.primary = None;
+                continue;
+            }
+            connected = wait_for_connection(&mut state.standby_connect) => {
+                state.standby_connect = None;
+                if let Some(conn) = connected {
+                    println!("{label}: Standby connected to {}.", conn.url);
+                    state.standby = Some(conn);
+                }
+                continue;
+            }
+        };
+
+        {
+            let mut stats = state.stats();
+            stats.profile.read += lap(read_started);
+            if message.is_some() && parsed.is_none() {
+                stats.profile.frames += 1;
+            }
+        }
+
+        let disconnect = match message {
+            Some(Ok(Message::Text(text))) => {
+                if parsed.is_some() {
+                    return Ok(Text { role, text, parsed });
+                }
+                if let Some(recorder) = &state.config.recorder {
+                    recorder.write(&state.config.name, &text);
+                }
+                if matches!(state.config.source, Source::Binance { stream: StreamType::MiniTicker }) {
+                    if let Ok(Some(snapshot)) = miniticker::process_message(&text) {
+                        state.stats().ticker = Some(snapshot);
+                    }
+                }
+                match &state.pool {
+                    Some(pool) => {
+                        pool.submit(role, text).await;
+                        continue;
+                    }
+                    None => return Ok(Text { role, text, parsed: None }),
+                }
+            }
+            Some(Ok(Message::Close(frame))) => {
+                // Binance closes every connection after 24 hours; this is routine, not a failure.
+                let reason = frame.map_or_else(|| "no reason given".to_string(), |frame| format!("{}: {}", frame.code, frame.reason));
+                println!("{label}: Server closed the connection ({role:?}, {reason}). Scheduled reconnect.");
+                format!("closed by server ({reason})")
+            }
+            Some(Ok(_)) => continue, // Ping/pong and other control frames
+            // A frame over the size limit can't be skipped in place (the rest of it is still
+            // on the wire), so drop the connection and carry on with a fresh one.
+            Some(Err(WsError::Capacity(e))) => {
+                eprintln!("{label}: Skipping an oversized frame ({role:?}): {e}. Reconnecting...");
+                format!("oversized frame ({e})")
+            }
+            Some(Err(e)) => {
+                eprintln!("{label}: Failed to receive message ({role:?}): {e}. Reconnecting...");
+                e.to_string()
+            }
+            None => {
+                eprintln!("{label}: WebSocket stream ended ({role:?}). Reconnecting...");
+                "stream ended".to_string()
+            }
+        };
+
+        // Drop the connection that failed so the next iteration replaces it.
+        match role {
+            Role::Primary => {
+                state.stats().connection.on_disconnect(disconnect);
+                state.primary = None;
+            }
+            Role::Standby => state.standby = None,
+        }
+    }
+}
+
+async fn next_trade(mut state: State) -> Option<(Result<Trade, PriceError>, State)> {
+    let label = state.label.clone();
+    while !state.done {
+        let Text { role, text, parsed } = match next_text(&mut state).await {
+            Ok(text) => text,
+            Err(error) => {
+                state.done = true;
+                return error.map(|error| (Err(error), state));
+            }
+        };
+        let parsed = parsed.unwrap_or_else(|| {
+            // Parse errors aren't Send, so don't hold one across an await.
+            let parse_started = state.config.profile.then(Instant::now);
+            let parsed = state.config.parse(&text).ok();
+            state.stats().profile.parse += lap(parse_started);
+            parsed
+        });
+        match parsed {
+            Some(Frame::Trade(trade)) => {
+                state.got_trade = true;
+                if role == Role::Primary {
+                    state.last_data = Instant::now();
+                }
+                // With a pool the workers already dropped duplicates, and trades come back in
+                // any order. Without one, trade ids are increasing, so anything at or below
+                // the last one was already yielded from the other connection.
+                if state.pool.is_none() {
+                    if state.last_trade_id.is_some_and(|last| trade.id <= last) {
+                        continue;
+                    }
+                    state.last_trade_id = Some(trade.id);
+                }
+                return Some((Ok(trade), state));
+            }
+            parsed => {
+                if let Some(error) = binance_error(&text).filter(|_| matches!(state.config.source, Source::Binance { .. })) {
+                    if error.is_fatal() {
+                        eprintln!("{label}: Binance {error}; stopping, since this stream will produce no trades.");
+                        state.done = true;
+                        return Some((Err(PriceError::Rejected(error)), state));
+                    }
+                    eprintln!("{label}: Binance {error}; ignoring it.");
+                } else if matches!(parsed, Some(Frame::Other)) {
+                    // Keepalives and acks say nothing about the feed's health, so they
+                    // neither count as data for the idle timeout nor towards --strict-json.
+                    state.stats().non_trade_frames += 1;
+                } else {
+                    let parse_errors = {
+                        let mut stats = state.stats();
+                        stats.parse_errors += 1;
+                        stats.parse_errors
+                    };
+                    if let Some(max) = state.config.strict_json.filter(|&max| parse_errors > max) {
+                        eprintln!("{label}: {parse_errors} frames were not valid trades, more than the {max} --strict-json allows; stopping.");
+                        state.done = true;
+                        return Some((Err(PriceError::TooManyParseErrors(parse_errors)), state));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use tokio::time::Duration;
+
+    use super::*;
+    use crate::test_support;
+
+    /// A feed of `count` seeded trades priced 1, 2, 3 and so on.
+    fn seeded(count: u32) -> ClientConfig {
+        let mut config = test_support::client_config(Source::Seed, "", 10);
+        config.seed_prices = (1..=count).map(f64::from).collect();
+        config
+    }
+
+    #[tokio::test]
+    async fn the_first_hundred_trades() {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let trades: Vec<Trade> = TradeStream::new("Example", 1, seeded(150), deadline)
+            .filter_map(|trade| async move { trade.ok() })
+            .take(100)
+            .collect()
+            .await;
+        let ids: Vec<u64> = trades.iter().map(|trade| trade.id).collect();
+        assert_eq!(ids, (1..=100).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn the_stream_ends_at_the_deadline() {
+        let started = Instant::now();
+        let mut feed = TradeStream::new("Example", 1, seeded(150), started + Duration::from_millis(500));
+        let mut trades = 0;
+        while let Some(trade) = feed.next().await {
+            assert_eq!(trade.unwrap().price, f64::from(trades + 1));
+            trades += 1;
+        }
+        assert_eq!(trades, 150);
+        assert!(started.elapsed() >= Duration::from_millis(500));
+        let stats = feed.stats();
+        assert_eq!(stats.profile.frames, 150);
+        assert_eq!((stats.parse_errors, stats.non_trade_frames), (0, 0));
+        assert!(stats.connection.time_to_connect.is_some());
+        assert_eq!(stats.connection.reconnects, 0);
+    }
+
+    #[tokio::test]
+    async fn trades_above_a_threshold_are_counted() {
+        let mut config = seeded(150);
+        config.seed_prices = (1..=150).map(|n| 64_950.0 + f64::from(n)).collect();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let above = Arc::new(AtomicU64::new(0));
+        let counter = above.clone();
+        let trades: Vec<Trade> = TradeStream::new("Example", 1, config, deadline)
+            .on_trade(move |trade| {
+                if trade.price > 65_000.0 {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+            .filter_map(|trade| async move { trade.ok() })
+            .take(100)
+            .collect()
+            .await;
+        // Prices 64951 to 65050: the last 50 are above.
+        assert_eq!(trades.len(), 100);
+        assert_eq!(above.load(Ordering::Relaxed), 50);
+    }
+}
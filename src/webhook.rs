@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use tokio::time::{self, Duration};
+
+use crate::redact_url;
+
+/// How long one POST may take before it counts as failed.
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Pause before the single retry.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The JSON body `--summary-webhook` POSTs at the end of a run (or `--watch` cycle).
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    /// RFC 3339 UTC timestamps.
+    pub started_at: String,
+    pub finished_at: String,
+    /// Whether the run was cut short by Ctrl-C, so the results are partial.
+    pub interrupted: bool,
+    /// Global average when a single symbol was tracked.
+    pub global_average: Option<f64>,
+    /// Global average per symbol.
+    pub global_averages: BTreeMap<String, f64>,
+    /// With `--compare-sources`, each symbol's mean spread between the sources in percent.
+    pub spread_pcts: BTreeMap<String, f64>,
+    /// Whether a spread was above `--max-spread-pct`, so the run exits with status 4.
+    pub spread_exceeded: bool,
+    /// One entry per client that produced an average, in client order.
+    pub clients: Vec<ClientSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClientSummary {
+    pub name: String,
+    pub symbol: String,
+    pub source: String,
+    pub average: f64,
+    pub trades: usize,
+    pub parse_errors: u64,
+    pub out_of_range: u64,
+    pub reconnects: u32,
+}
+
+/// POST `summary` to `url`, retrying once if the first attempt fails, and log the outcome.
+pub async fn deliver(url: &str, summary: &Summary) {
+    let shown = redact_url(url);
+    for attempt in 1..=2 {
+        match post(url, summary).await {
+            Ok(()) => {
+                println!("Webhook: Posted the summary to {shown}.");
+                return;
+            }
+            Err(e) if attempt == 1 => {
+                eprintln!("Webhook: Failed to post the summary to {shown}: {e}; retrying once.");
+                time::sleep(RETRY_DELAY).await;
+            }
+            Err(e) => eprintln!("Webhook: Failed to post the summary to {shown}: {e}; giving up."),
+        }
+    }
+}
+
+async fn post(url: &str, summary: &Summary) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()?
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(summary)?)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Write `value` to `path` as YAML, in the same layout as `--sink msgpack` files. Floats
+/// are written in their shortest form that parses back to the same f64, so they
+/// round-trip exactly.
+pub fn write<T: Serialize>(path: &str, value: &T) -> Result<(), Box<dyn std::error::Error>> {
+    serde_yaml::to_writer(BufWriter::new(File::create(path)?), value)?;
+    Ok(())
+}
+
+/// Read a value written by [`write`].
+pub fn read<T: DeserializeOwned>(path: &str) -> Result<T, Box<dyn std::error::Error>> {
+    Ok(serde_yaml::from_reader(BufReader::new(File::open(path)?))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msgpack::{ClientData, GlobalData};
+    use crate::test_support;
+
+    #[test]
+    fn saved_data_reads_back_exactly() {
+        let path = test_support::temp_path("client.yaml");
+        let client = test_support::client_data();
+        write(&path, &client).unwrap();
+        let read_back: ClientData = read(&path).unwrap();
+        let prices: Vec<u64> = read_back.trades.iter().map(|trade| trade.price.to_bits()).collect();
+        assert_eq!(prices, test_support::AWKWARD_PRICES.map(f64::to_bits));
+        assert_eq!(read_back, client);
+
+        let path = test_support::temp_path("global.yaml");
+        let global = test_support::global_data();
+        write(&path, &global).unwrap();
+        assert_eq!(read::<GlobalData>(&path).unwrap(), global);
+    }
+}